@@ -0,0 +1,37 @@
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+/// Represents how CDC changes that conflict with the target rows are handled.
+///
+/// The mode can be one of the following:
+///
+/// * Strict - Inserts of existing keys fail, like they do on the source.
+/// * Idempotent - Changes that DMS re-delivered after a restart are tolerated: inserts of
+///   existing keys are upserted, deletes of missing keys are skipped, and updates older
+///   than the last change applied to their key are skipped. The DMS timestamp of that change
+///   is kept in a schema next to the target schema, e.g. `public_dms_applied` for `public`,
+///   and the snapshot fails if that name is longer than 63 bytes or taken by another schema.
+///   The CDC files are applied one by one instead of compacted in runs, so that the tolerated
+///   changes are reported against the file that delivered them.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ApplyModeValueEnum {
+    #[default]
+    Strict,
+    Idempotent,
+}
+
+impl ApplyModeValueEnum {
+    /// Whether the changes are applied idempotently.
+    pub fn is_idempotent(&self) -> bool {
+        *self == ApplyModeValueEnum::Idempotent
+    }
+}
+
+impl Display for ApplyModeValueEnum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyModeValueEnum::Strict => write!(f, "Strict"),
+            ApplyModeValueEnum::Idempotent => write!(f, "Idempotent"),
+        }
+    }
+}
@@ -1,34 +1,326 @@
-use aws_sdk_s3::Client as S3Client;
+use anyhow::{Result, anyhow};
 use colored::Colorize;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use futures::stream::{self};
+use futures::{StreamExt, TryStreamExt};
 use log::info;
+use polars::prelude::{Column, DataFrame, PlSmallStr, Scalar};
 use rust_pgdatadiff::diff::diff_ops::Differ;
 use rust_pgdatadiff::diff::diff_payload::DiffPayload;
+use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
 use std::time::Instant;
 
+use super::schema_evolution::SchemaTimeline;
 use super::snapshot_payload::CDCOperatorSnapshotPayload;
+use super::snapshot_report::{SnapshotReport, TableSnapshotReport};
 use super::validate_payload::CDCOperatorValidatePayload;
 
+use crate::dataframe::cdc_compaction::compact_cdc_changes;
 use crate::dataframe::dataframe_ops::{
     CreateDataframePayload, DataframeOperator, DataframeOperatorImpl,
 };
+use crate::dataframe::dms_metadata_columns::CHANGE_FILE_COLUMN;
+use crate::dataframe::file_format::FileFormatValueEnum;
+use crate::postgres::column_definition::ColumnDefinition;
+use crate::postgres::postgres_copy::postgres_type_for;
 use crate::postgres::postgres_operator::{
     InsertDataframePayload, PostgresOperator, UpsertDataframePayload,
 };
-use crate::s3::s3_operator::{LoadParquetFilesPayload, S3Operator, S3OperatorImpl};
+use crate::postgres::row_identity::RowIdentity;
+use crate::postgres::source_table::{TableKind, group_partitions};
+use crate::postgres::table_constraint::{ConstraintKind, TableConstraint, load_order};
+use crate::postgres::table_query::applied_timestamps_schema;
+use crate::postgres::user_defined_type::UserDefinedType;
+use crate::s3::s3_operator::{
+    LoadParquetFilesPayload, S3Operator, S3OperatorImpl, merge_table_files,
+};
+use crate::storage::object_store::ObjectStore;
+
+/// Compacts a run of CDC files to the net change of each key and upserts it in the target database.
+///
+/// The run is applied in one transaction, the files between two LOAD files being a unit:
+/// if any change of the run fails, none of the files of the run are applied.
+/// In the idempotent apply mode, the files are not compacted but applied one by one,
+/// each in its own transaction, so that the tolerated changes are counted against
+/// the file that delivered them.
+async fn apply_cdc_files(
+    target_postgres_operator: &(impl PostgresOperator + Sync),
+    cdc_dfs: &mut Vec<DataFrame>,
+    primary_keys: &[String],
+    upsert_dataframe_payload: &UpsertDataframePayload,
+) -> Result<()> {
+    if cdc_dfs.is_empty() {
+        return Ok(());
+    }
+
+    let mut reports = Vec::new();
+    if upsert_dataframe_payload.is_compacted {
+        info!("Compacting {} CDC files", cdc_dfs.len());
+        let compacted_df = compact_cdc_changes(
+            cdc_dfs,
+            primary_keys,
+            &upsert_dataframe_payload.dms_metadata_columns,
+        )
+        .map_err(|e| anyhow!("Failed to compact CDC files: {e}"))?;
+        info!(
+            "Processing {} net changes of {} CDC files",
+            compacted_df.height(),
+            cdc_dfs.len()
+        );
+        cdc_dfs.clear();
+
+        reports = target_postgres_operator
+            .upsert_dataframe_in_target_db(&compacted_df, upsert_dataframe_payload)
+            .await?;
+    } else {
+        info!("Processing {} CDC files one by one", cdc_dfs.len());
+        for cdc_df in cdc_dfs.drain(..) {
+            reports.extend(
+                target_postgres_operator
+                    .upsert_dataframe_in_target_db(&cdc_df, upsert_dataframe_payload)
+                    .await?,
+            );
+        }
+    }
+
+    for report in reports {
+        info!(
+            "{}",
+            format!(
+                "CDC file {}: upserted {} inserts of existing keys, skipped {} deletes of missing keys and {} stale updates",
+                report.file_name.as_deref().unwrap_or("<unknown>"),
+                report.upserted_inserts,
+                report.skipped_deletes,
+                report.skipped_stale_updates
+            )
+            .yellow()
+        );
+    }
+
+    Ok(())
+}
+
+/// Creates the user-defined and extension types of the columns of the tables in the target database,
+/// once for all the tables that use them.
+///
+/// # Returns
+///
+/// The types that could not be created, with the reason and the tables that use them,
+/// or an error if the types of the tables could not be read.
+async fn create_user_defined_types(
+    source_postgres_operator: &(impl PostgresOperator + Sync),
+    target_postgres_operator: &(impl PostgresOperator + Sync),
+    schema_name: &str,
+    table_list: &[String],
+) -> Result<Vec<String>> {
+    // Each table lists a type after the types it depends on, and so does the union of the lists
+    let mut user_defined_types: indexmap::IndexMap<(String, String), (UserDefinedType, Vec<&str>)> =
+        indexmap::IndexMap::new();
+    for table_name in table_list {
+        let table_types = source_postgres_operator
+            .get_user_defined_types(table_name, schema_name)
+            .await?;
+        for user_defined_type in table_types {
+            let key = (
+                user_defined_type.schema_name.clone(),
+                user_defined_type.name.clone(),
+            );
+            user_defined_types
+                .entry(key)
+                .or_insert_with(|| (user_defined_type, Vec::new()))
+                .1
+                .push(table_name);
+        }
+    }
+
+    let mut failed_types = Vec::new();
+    for (user_defined_type, tables) in user_defined_types.values() {
+        info!("Creating type: {}", user_defined_type);
+        if let Err(e) = target_postgres_operator
+            .create_user_defined_type(user_defined_type)
+            .await
+        {
+            let failed_type = format!(
+                "type {} used by tables {:?}: {e}",
+                user_defined_type, tables
+            );
+            info!(
+                "{}",
+                format!("Failed to create {}", failed_type).red().bold()
+            );
+            failed_types.push(failed_type);
+        }
+    }
+
+    Ok(failed_types)
+}
+
+/// Creates the indexes and constraints of the source tables in the target tables, after they are loaded.
+///
+/// Foreign keys are added last, once the unique constraints they reference exist.
+/// In history mode, where each row version is kept, only the check constraints and the
+/// indexes that are not unique are created. The indexes and constraints that a target
+/// table already has, e.g. from a previous run, are skipped, and so are the unique
+/// indexes of the key the table was created with.
+///
+/// # Returns
+///
+/// The indexes and constraints that could not be created, with the reason, per table,
+/// or an error if the indexes and constraints of a table could not be read.
+async fn replicate_constraints(
+    source_postgres_operator: &(impl PostgresOperator + Sync),
+    target_postgres_operator: &(impl PostgresOperator + Sync),
+    payload: &CDCOperatorSnapshotPayload,
+    table_list: &[String],
+    row_identities: &HashMap<String, RowIdentity>,
+    source_constraints: &HashMap<String, Vec<TableConstraint>>,
+) -> Result<HashMap<String, Vec<String>>> {
+    let schema_name = payload.schema_name();
+    let keeps_history = payload.cdc_semantics().keeps_history();
+    let mut failed_constraints: HashMap<String, Vec<String>> = HashMap::new();
+
+    let mut foreign_keys = Vec::new();
+    for table_name in table_list {
+        info!(
+            "{}",
+            format!("Creating indexes and constraints of table: {}", table_name)
+                .bold()
+                .green()
+        );
+        let failed = failed_constraints.entry(table_name.clone()).or_default();
+
+        // Constraints with the name of an existing one were created by a previous run or with the table
+        let existing_constraints = target_postgres_operator
+            .get_constraints(table_name, &schema_name)
+            .await?
+            .into_iter()
+            .map(|constraint| constraint.name)
+            .collect::<Vec<_>>();
+
+        // Indexes with the name of an existing one were created by a previous run, and a unique
+        // index of the key of the table duplicates the constraint the table was created with
+        let existing_indexes = target_postgres_operator
+            .get_indexes(table_name, &schema_name)
+            .await?
+            .into_iter()
+            .map(|index| index.name)
+            .collect::<Vec<_>>();
+        let keys = row_identities
+            .get(table_name)
+            .map(|row_identity| row_identity.keys())
+            .unwrap_or_default();
+
+        let indexes = source_postgres_operator
+            .get_indexes(table_name, &schema_name)
+            .await?;
+        for index in indexes {
+            let is_unique = index.definition.starts_with("CREATE UNIQUE INDEX");
+            if (keeps_history && is_unique)
+                || existing_indexes.contains(&index.name)
+                || existing_constraints.contains(&index.name)
+                || index.is_unique_on(keys)
+            {
+                continue;
+            }
+            if let Err(e) = target_postgres_operator
+                .create_index(&index.definition)
+                .await
+            {
+                failed.push(format!("index {}: {e}", index.name));
+            }
+        }
+
+        for constraint in source_constraints.get(table_name).into_iter().flatten() {
+            if existing_constraints.contains(&constraint.name)
+                || (keeps_history && constraint.kind != ConstraintKind::Check)
+            {
+                continue;
+            }
+            if constraint.kind == ConstraintKind::ForeignKey {
+                foreign_keys.push((table_name, constraint));
+                continue;
+            }
+            if let Err(e) = target_postgres_operator
+                .add_constraint(constraint, &schema_name, table_name)
+                .await
+            {
+                failed.push(format!("constraint {}: {e}", constraint.name));
+            }
+        }
+    }
+
+    for (table_name, foreign_key) in foreign_keys {
+        let failed = failed_constraints.entry(table_name.clone()).or_default();
+        let is_replicated = foreign_key
+            .referenced_table_in(&schema_name)
+            .is_some_and(|referenced_table| table_list.iter().any(|t| t == referenced_table));
+        if !is_replicated {
+            failed.push(format!(
+                "foreign key {}: the referenced table is not replicated",
+                foreign_key.name
+            ));
+            continue;
+        }
+        if let Err(e) = target_postgres_operator
+            .add_constraint(foreign_key, &schema_name, table_name)
+            .await
+        {
+            failed.push(format!("foreign key {}: {e}", foreign_key.name));
+        }
+    }
+
+    Ok(failed_constraints)
+}
 
 /// Represents a CDC Operator that validates the data between S3 and a target database.
 pub struct CDCOperator;
 
 impl CDCOperator {
-    /// Takes a snpashot of the data stored in S3 and replicates them in a target database.
+    /// Takes a snpashot of the data stored in the object store and replicates them in a target database.
+    ///
+    /// Returns a report of the tables, which flags the tables that may not be exact replicas,
+    /// and the tables whose files could not be listed or loaded, or an error if the schema
+    /// could not be replicated, e.g. when a database can not be queried.
     pub async fn snapshot(
         cdc_operator_snapshot_payload: &CDCOperatorSnapshotPayload,
         source_postgres_operator: &(impl PostgresOperator + Sync),
         target_postgres_operator: &(impl PostgresOperator + Sync),
-        s3_client: &S3Client,
-    ) {
+        object_store: &dyn ObjectStore,
+    ) -> Result<SnapshotReport> {
+        if cdc_operator_snapshot_payload.mode_is_date_aware()
+            && cdc_operator_snapshot_payload.start_date().is_none()
+        {
+            return Err(anyhow!("start_date is required for DateAware mode"));
+        }
+
+        // The applied timestamps are kept in a schema of their own, which must not be
+        // a schema of the source database, replicated or not
+        if cdc_operator_snapshot_payload.apply_mode().is_idempotent() {
+            let schema_name = cdc_operator_snapshot_payload.schema_name();
+            let applied_schema = applied_timestamps_schema(&schema_name);
+            if source_postgres_operator
+                .schema_exists(&applied_schema)
+                .await?
+            {
+                return Err(anyhow!(
+                    "The applied timestamps of schema {schema_name} are kept in schema {applied_schema}, \
+                    which is a schema of the source database"
+                ));
+            }
+            info!(
+                "{}",
+                "Creating applied timestamps schema in the target DB"
+                    .bold()
+                    .green()
+            );
+            target_postgres_operator
+                .create_applied_timestamps_schema(&schema_name)
+                .await?;
+        }
+
         info!("{}", "Creating schema in the target DB".bold().green());
         let _ = target_postgres_operator
             .create_schema(cdc_operator_snapshot_payload.schema_name().as_str())
@@ -38,31 +330,77 @@ impl CDCOperator {
         info!("{}", "Starting snapshotting...".bold().blue());
 
         // Find the tables for snapshotting
-        let table_list = source_postgres_operator
+        let source_tables = source_postgres_operator
             .get_tables_in_schema(
                 cdc_operator_snapshot_payload.schema_name().as_str(),
-                cdc_operator_snapshot_payload.included_tables().as_slice(),
-                cdc_operator_snapshot_payload.excluded_tables().as_slice(),
-                &cdc_operator_snapshot_payload.table_mode(),
+                cdc_operator_snapshot_payload.table_filter(),
             )
-            .await
-            .unwrap();
+            .await?;
+
+        // The partitions of a partitioned table are loaded into it, and DMS does not replicate
+        // the rows of views and foreign tables
+        let (loaded_tables, skipped_tables) = group_partitions(source_tables);
+        for skipped_table in &skipped_tables {
+            info!("Skipping {}", skipped_table);
+        }
+        let table_list = loaded_tables
+            .iter()
+            .map(|loaded_table| loaded_table.table.name.clone())
+            .collect::<Vec<_>>();
+        let loaded_tables = loaded_tables
+            .into_iter()
+            .map(|loaded_table| (loaded_table.table.name.clone(), loaded_table))
+            .collect::<HashMap<_, _>>();
+
+        let failed_types = create_user_defined_types(
+            source_postgres_operator,
+            target_postgres_operator,
+            cdc_operator_snapshot_payload.schema_name().as_str(),
+            &table_list,
+        )
+        .await?;
+
+        // The constraints are read before the load, so that the tables are loaded after the tables
+        // their foreign keys reference, which the foreign keys of a previous run enforce
+        let mut source_constraints = HashMap::new();
+        let load_levels = if cdc_operator_snapshot_payload.replicate_constraints() {
+            for table_name in &table_list {
+                let constraints = source_postgres_operator
+                    .get_constraints(
+                        table_name,
+                        cdc_operator_snapshot_payload.schema_name().as_str(),
+                    )
+                    .await?;
+                source_constraints.insert(table_name.clone(), constraints);
+            }
+            let load_levels = load_order(
+                cdc_operator_snapshot_payload.schema_name().as_str(),
+                &table_list,
+                &source_constraints,
+            );
+            info!("Loading tables in foreign key order: {:?}", load_levels);
+            load_levels
+        } else {
+            vec![table_list.clone()]
+        };
 
         let cdc_operator_snapshot_payload: Arc<&CDCOperatorSnapshotPayload> =
             Arc::new(cdc_operator_snapshot_payload);
-        let client = s3_client.clone();
-        let s3_operator = Arc::new(S3OperatorImpl::new(&client));
-        let dataframe_operator = Arc::new(DataframeOperatorImpl::new(s3_client));
+        let s3_operator = Arc::new(S3OperatorImpl::new(object_store));
+        let dataframe_operator = Arc::new(DataframeOperatorImpl::new(object_store));
 
-        let tables = table_list
+        let mut tables = table_list
             .iter()
             .map(|table_name| {
                 let payload = Arc::clone(&cdc_operator_snapshot_payload);
                 let s3_operator = Arc::clone(&s3_operator);
                 let dataframe_operator = Arc::clone(&dataframe_operator);
+                let loaded_table = &loaded_tables[table_name];
 
-                async move {
+                let table: BoxFuture<'_, Result<TableSnapshotReport>> = async move {
                     let payload = Arc::clone(&payload);
+                    // The tables of every schema of the run share the permits
+                    let _permit = payload.table_permits().acquire().await?;
 
                     let start = Instant::now();
                     info!(
@@ -74,13 +412,15 @@ impl CDCOperator {
 
                     // Get the table columns
                     info!("{}", "Getting table columns".bold().green());
-                    let source_table_columns: indexmap::IndexMap<String, String> = source_postgres_operator
-                        .get_table_columns(
-                            payload.schema_name().as_str(),
-                            table_name,
-                        )
-                        .await
-                        .unwrap();
+                    let source_column_definitions = source_postgres_operator
+                        .get_column_definitions(payload.schema_name().as_str(), table_name)
+                        .await?;
+                    let source_table_columns = source_column_definitions
+                        .iter()
+                        .map(|column| (column.name.clone(), column.data_type.clone()))
+                        .collect::<indexmap::IndexMap<_, _>>();
+                    let table_column_names =
+                        source_table_columns.keys().cloned().collect::<Vec<_>>();
                     info!(
                         "Number of columns: {}, Columns: {:?}",
                         source_table_columns.len(),
@@ -90,131 +430,403 @@ impl CDCOperator {
                     // Get the primary key for the table
                     info!("{}", "Getting primary key".bold().green());
                     let primary_key_list = source_postgres_operator
-                        .get_primary_key(
-                            table_name,
-                            payload.schema_name().as_str(),
-                        )
-                        .await
-                        .unwrap();
+                        .get_primary_key(table_name, payload.schema_name().as_str())
+                        .await?;
                     info!("Primary key(s): {:?}", primary_key_list);
 
-                    // Create the table in the target database
+                    // Tables without a primary key fall back to a unique index, a key override
+                    // or, failing those, to matching the rows on all their columns
+                    let unique_key_list = if primary_key_list.is_empty() {
+                        source_postgres_operator
+                            .get_unique_key(table_name, payload.schema_name().as_str())
+                            .await?
+                    } else {
+                        Vec::new()
+                    };
+                    let row_identity = RowIdentity::resolve(
+                        primary_key_list,
+                        unique_key_list,
+                        payload.key_override(table_name),
+                    );
+                    info!("Row identity: {}", row_identity);
+                    let key_list = row_identity.keys().to_vec();
+                    let nullable_keys = row_identity.nullable_keys(&source_column_definitions);
+
+                    // A key override becomes a unique constraint of the target table,
+                    // so it must name columns of the table that identify its rows
+                    if let RowIdentity::KeyOverride(keys) = &row_identity {
+                        let missing_keys = row_identity.missing_keys(&source_column_definitions);
+                        let error = if !missing_keys.is_empty() {
+                            Some(format!(
+                                "The key override of table {} has columns that are not in the table: {}",
+                                table_name,
+                                missing_keys.join(", ")
+                            ))
+                        } else if source_postgres_operator
+                            .has_duplicate_keys(keys, table_name, payload.schema_name().as_str())
+                            .await?
+                        {
+                            Some(format!(
+                                "The {} of table {} does not identify its rows: some rows of the source table have the same key",
+                                row_identity, table_name
+                            ))
+                        } else {
+                            None
+                        };
+                        if let Some(error) = error {
+                            info!("{}", error.red().bold());
+                            return Ok(TableSnapshotReport {
+                                table_name: table_name.clone(),
+                                row_identity,
+                                nullable_keys,
+                                schema_changes: Vec::new(),
+                                failed_constraints: Vec::new(),
+                                error: Some(error),
+                            });
+                        }
+                    }
+
+                    // Every row version is kept in history mode, so the keys are not unique
+                    let target_row_identity = if payload.cdc_semantics().keeps_history() {
+                        RowIdentity::FullRow
+                    } else {
+                        row_identity.clone()
+                    };
+
+                    // History mode keeps the DMS columns of each row version
+                    let mut target_table_columns = source_column_definitions.clone();
+                    target_table_columns.extend(
+                        payload
+                            .cdc_semantics()
+                            .kept_metadata_columns(payload.dms_metadata_columns()),
+                    );
+
+                    // Create the table in the target database, with the partitioning of the source table
                     info!("{}", "Creating table in the target DB".bold().green());
+                    let partition_key = match loaded_table.table.kind {
+                        TableKind::PartitionedTable => loaded_table.table.partition_key.as_deref(),
+                        _ => None,
+                    };
                     let _ = target_postgres_operator
                         .create_table(
-                            &source_table_columns,
-                            primary_key_list.as_slice(),
+                            &target_table_columns,
+                            &target_row_identity,
+                            partition_key,
                             payload.schema_name().clone().as_str(),
                             table_name,
                         )
                         .await;
+                    // The idempotent apply mode keeps the DMS timestamp of the last change applied
+                    // to each key in a table of its own, to skip stale changes
+                    if payload.apply_mode().is_idempotent()
+                        && !payload.cdc_semantics().keeps_history()
+                        && !key_list.is_empty()
+                    {
+                        let mut applied_timestamp_columns = key_list
+                            .iter()
+                            .filter_map(|key| {
+                                source_column_definitions
+                                    .iter()
+                                    .find(|column| &column.name == key)
+                                    .map(|column| {
+                                        ColumnDefinition::new(&column.name, &column.data_type)
+                                    })
+                            })
+                            .collect::<Vec<_>>();
+                        applied_timestamp_columns.push(ColumnDefinition::new(
+                            &payload.dms_metadata_columns().timestamp_column,
+                            "timestamp",
+                        ));
+                        target_postgres_operator
+                            .create_table(
+                                &applied_timestamp_columns,
+                                &row_identity,
+                                None,
+                                &applied_timestamps_schema(&payload.schema_name()),
+                                table_name,
+                            )
+                            .await
+                            .map_err(|e| {
+                                anyhow!(
+                                    "Failed to create the applied timestamps of table {}: {e}",
+                                    table_name
+                                )
+                            })?;
+                    }
 
-                    // Get the list of Parquet files from S3
-                    info!("{}", "Getting list of Parquet files from S3".bold().green());
+                    for partition in &loaded_table.partitions {
+                        info!("Creating partition: {}", partition.name);
+                        target_postgres_operator
+                            .create_partition(partition, payload.schema_name().as_str())
+                            .await
+                            .map_err(|e| {
+                                anyhow!(
+                                    "Failed to create partition {} of table {}: {e}",
+                                    partition.name,
+                                    table_name
+                                )
+                            })?;
+                    }
 
-                    // Check if mode is DateAware and start_date is not None
-                    if payload.mode_is_date_aware() && payload.start_date().is_none() {
-                        panic!("start_date is required for DateAware mode");
+                    // PostGIS values are written in the spatial reference system of their source column
+                    let geometry_srids = source_postgres_operator
+                        .get_geometry_srids(table_name, payload.schema_name().as_str())
+                        .await
+                        .map_err(|e| {
+                            anyhow!(
+                                "Failed to get the SRIDs of the columns of table {}: {e}",
+                                table_name
+                            )
+                        })?;
+                    if !geometry_srids.is_empty() {
+                        info!("SRIDs: {:?}", geometry_srids);
                     }
 
-                    let load_parquet_files_payload
-                    = if payload.mode_is_date_aware(){
-                            LoadParquetFilesPayload::DateAware {
+                    // The columns of the target table grow with the columns that appear in the files
+                    let mut target_columns = target_postgres_operator
+                        .get_table_columns(payload.schema_name().as_str(), table_name)
+                        .await?;
+                    let mut schema_timeline = SchemaTimeline::default();
+
+                    // Get the list of Parquet files from S3
+                    info!("{}", "Getting list of Parquet files from S3".bold().green());
+
+                    // DMS writes the output of a partitioned table under the table
+                    // and under each of its partitions, which are all loaded into the table
+                    let load_parquet_files_payloads = if payload.mode_is_date_aware() {
+                        loaded_table
+                            .source_names()
+                            .into_iter()
+                            .map(|source_name| LoadParquetFilesPayload::DateAware {
                                 bucket_name: payload.bucket_name().clone(),
                                 s3_prefix: payload.key().clone(),
                                 database_name: payload.database_name().clone(),
                                 schema_name: payload.schema_name().clone(),
-                                table_name: table_name.to_string(),
+                                table_name: source_name.to_string(),
                                 start_date: payload.start_date().unwrap(),
                                 stop_date: payload.stop_date(),
-                            }
-                        }
-                    else if payload.mode_is_full_load_only() {
-                        LoadParquetFilesPayload::FullLoadOnly {
-                            bucket_name: payload.bucket_name().clone(),
-                            s3_prefix: payload.key().clone(),
-                            database_name: payload.database_name().clone(),
-                            schema_name: payload.schema_name().clone(),
-                            table_name: table_name.to_string(),
-                        }
+                                date_partition_layout: payload.date_partition_layout(),
+                            })
+                            .collect::<Vec<_>>()
+                    } else if payload.mode_is_full_load_only() {
+                        loaded_table
+                            .source_names()
+                            .into_iter()
+                            .map(|source_name| LoadParquetFilesPayload::FullLoadOnly {
+                                bucket_name: payload.bucket_name().clone(),
+                                s3_prefix: payload.key().clone(),
+                                database_name: payload.database_name().clone(),
+                                schema_name: payload.schema_name().clone(),
+                                table_name: source_name.to_string(),
+                            })
+                            .collect::<Vec<_>>()
                     } else {
-                        LoadParquetFilesPayload::AbsolutePath(payload.key().clone())
+                        vec![LoadParquetFilesPayload::AbsolutePath(payload.key().clone())]
                     };
 
                     // Read the Parquet files from S3
                     info!("{}", "Reading Parquet files from S3".bold().green());
 
-                    let parquet_files = s3_operator
-                        .get_list_of_parquet_files_from_s3(&load_parquet_files_payload)
-                        .await
-                        .unwrap_or_else(|_| {
-                            info!("No available Parquet files from S3 for table {} to process", table_name);
-                            Vec::new()
-                        });
-
-                    for file in &parquet_files {
-                        let create_dataframe_payload = CreateDataframePayload {
-                            bucket_name: payload.bucket_name().clone(),
-                            key: file.file_name.to_string(),
-                            database_name: payload.database_name().clone(),
-                            schema_name: payload.schema_name().clone(),
-                            table_name: table_name.clone(),
-                        };
-
-                        let current_df = dataframe_operator
-                            .create_dataframe_from_parquet_file(&create_dataframe_payload)
+                    // A listing error, e.g. a gap in the LOAD files, stops this table only
+                    let mut folders = Vec::new();
+                    for load_parquet_files_payload in &load_parquet_files_payloads {
+                        match s3_operator
+                            .get_list_of_parquet_files_from_s3(load_parquet_files_payload)
                             .await
-                            .map_err(|e| {
-                                panic!("Error reading Parquet file: {:?}", e);
-                            })
-                            .unwrap()
-                            .unwrap();
-
-                        if file.is_load_file() {
-                            info!("Processing LOAD file: {:?}", file);
-                            // Check if the schema of the table is the same as the schema of the Parquet file
-                            // in case of altered column names or dropped columns
-                            let has_schema_diff = current_df
-                                .get_columns()
-                                .iter()
-                                .filter(|field| field.name() != "Op" && field.name() != "_dms_ingestion_timestamp")
-                                .any(|field| !source_table_columns.contains_key(field.name().as_str()));
-
-                            if has_schema_diff {
-                                panic!("Schema of table is not the same as the schema of the Parquet file");
+                        {
+                            Ok(files) => folders.push(files),
+                            Err(e) => {
+                                let error = format!(
+                                    "Failed to list Parquet files from S3 for table {}: {}",
+                                    table_name, e
+                                );
+                                info!("{}", error.red().bold());
+                                return Ok(TableSnapshotReport {
+                                    table_name: table_name.clone(),
+                                    row_identity,
+                                    nullable_keys,
+                                    schema_changes: Vec::new(),
+                                    failed_constraints: Vec::new(),
+                                    error: Some(error),
+                                });
                             }
+                        }
+                    }
+                    let parquet_files = merge_table_files(folders);
 
-                            let insert_dataframe_payload = InsertDataframePayload {
-                                database_name: payload.database_name().clone(),
-                                schema_name: payload.schema_name().clone(),
-                                table_name: table_name.clone(),
-                            };
+                    if parquet_files.is_empty() {
+                        info!(
+                            "No available Parquet files from S3 for table {} to process",
+                            table_name
+                        );
+                    }
 
-                            target_postgres_operator
-                                .insert_dataframe_in_target_db(&current_df, &insert_dataframe_payload)
-                                .await
-                                .unwrap_or_else(|_| {
-                                    panic!("Failed to insert LOAD file {:?} into table", file)
-                                })
-                        } else {
-                            info!("Processing CDC file: {:?}", file);
-                            let primary_keys = primary_key_list.clone().as_slice().join(",");
+                    // CDC files are applied in runs, between LOAD files
+                    let mut pending_cdc_dfs = Vec::new();
+                    let upsert_dataframe_payload = UpsertDataframePayload {
+                        database_name: payload.database_name().clone(),
+                        schema_name: payload.schema_name().clone(),
+                        table_name: table_name.clone(),
+                        primary_keys: key_list.clone(),
+                        dms_metadata_columns: payload.dms_metadata_columns().clone(),
+                        cdc_semantics: payload.cdc_semantics(),
+                        apply_mode: payload.apply_mode(),
+                        is_compacted: !payload.apply_mode().is_idempotent(),
+                        geometry_srids: geometry_srids.clone(),
+                    };
 
-                            let upsert_dataframe_payload = UpsertDataframePayload {
+                    // A file that can not be read or applied stops this table only,
+                    // since the changes after it depend on it
+                    let loaded: Result<()> = async {
+                        for file in &parquet_files {
+                            let create_dataframe_payload = CreateDataframePayload {
+                                bucket_name: payload.bucket_name().clone(),
+                                key: file.file_name.to_string(),
                                 database_name: payload.database_name().clone(),
                                 schema_name: payload.schema_name().clone(),
                                 table_name: table_name.clone(),
-                                primary_key: primary_keys.clone(),
                             };
 
-                            target_postgres_operator
-                                .upsert_dataframe_in_target_db(&current_df, &upsert_dataframe_payload)
-                                .await
-                                .unwrap_or_else(|_| {
-                                    panic!("Failed to upsert CDC file {:?} into table", file)
+                            let current_df = match payload.file_format() {
+                                FileFormatValueEnum::Parquet => dataframe_operator
+                                    .create_dataframe_from_parquet_file(&create_dataframe_payload)
+                                    .await
+                                    .map_err(|e| {
+                                        anyhow!("Error reading Parquet file {}: {e}", file.file_name)
+                                    })?,
+                                FileFormatValueEnum::Csv => dataframe_operator
+                                    .create_dataframe_from_csv_file(
+                                        &create_dataframe_payload,
+                                        payload.csv_settings(),
+                                        &source_table_columns,
+                                        &payload.dms_metadata_columns().leading_columns(
+                                            file.is_load_file(),
+                                            payload.cdc_semantics(),
+                                        ),
+                                    )
+                                    .await
+                                    .map_err(|e| {
+                                        anyhow!("Error reading CSV file {}: {e}", file.file_name)
+                                    })?,
+                            }
+                            .ok_or_else(|| anyhow!("No rows read from file {}", file.file_name))?;
+
+                            // Columns are aligned by name, so files may have columns added or renamed
+                            // after the DMS task started, and lack the columns added after them
+                            let file_columns = current_df
+                                .get_columns()
+                                .iter()
+                                .filter(|column| {
+                                    !payload
+                                        .dms_metadata_columns()
+                                        .is_metadata_column(column.name(), &table_column_names)
                                 })
+                                .map(|column| (column.name().to_string(), column.dtype().clone()))
+                                .collect::<Vec<_>>();
+                            let file_column_names = file_columns
+                                .iter()
+                                .map(|(name, _)| name.clone())
+                                .collect::<Vec<_>>();
+                            if let Some(schema_change) =
+                                schema_timeline.observe(&file.file_name, &file_column_names)
+                            {
+                                info!(
+                                    "{}",
+                                    format!(
+                                        "Schema of table {} changed at {}",
+                                        table_name, schema_change
+                                    )
+                                    .yellow()
+                                );
+                            }
+
+                            let new_columns = file_columns
+                                .iter()
+                                .filter(|(name, _)| !target_columns.contains_key(name))
+                                .map(|(name, dtype)| {
+                                    let data_type = source_table_columns
+                                        .get(name)
+                                        .cloned()
+                                        .unwrap_or_else(|| postgres_type_for(dtype));
+                                    (name.clone(), data_type)
+                                })
+                                .collect::<indexmap::IndexMap<_, _>>();
+                            if !new_columns.is_empty() {
+                                info!("Adding columns to table {}: {:?}", table_name, new_columns);
+                                target_postgres_operator
+                                    .add_columns(
+                                        &new_columns,
+                                        payload.schema_name().as_str(),
+                                        table_name,
+                                    )
+                                    .await
+                                    .map_err(|e| {
+                                        anyhow!("Failed to add columns to table {}: {e}", table_name)
+                                    })?;
+                                target_columns.extend(new_columns);
+                            }
+
+                            if file.is_load_file() || payload.cdc_semantics().keeps_history() {
+                                apply_cdc_files(
+                                    target_postgres_operator,
+                                    &mut pending_cdc_dfs,
+                                    &key_list,
+                                    &upsert_dataframe_payload,
+                                )
+                                .await?;
+
+                                if file.is_load_file() {
+                                    info!("Processing LOAD file: {:?}", file);
+                                } else {
+                                    info!("Appending CDC file: {:?}", file);
+                                }
+                                let insert_dataframe_payload = InsertDataframePayload {
+                                    database_name: payload.database_name().clone(),
+                                    schema_name: payload.schema_name().clone(),
+                                    table_name: table_name.clone(),
+                                    dms_metadata_columns: payload.dms_metadata_columns().clone(),
+                                    cdc_semantics: payload.cdc_semantics(),
+                                    geometry_srids: geometry_srids.clone(),
+                                };
+
+                                target_postgres_operator
+                                    .insert_dataframe_in_target_db(
+                                        &current_df,
+                                        &insert_dataframe_payload,
+                                    )
+                                    .await
+                                    .map_err(|e| {
+                                        anyhow!("Failed to insert file {}: {e}", file.file_name)
+                                    })?;
+                            } else {
+                                info!("Reading CDC file: {:?}", file);
+                                // Keep the file of each change, to report the changes per file
+                                let mut current_df = current_df;
+                                let height = current_df.height();
+                                current_df
+                                    .with_column(Column::new_scalar(
+                                        CHANGE_FILE_COLUMN.into(),
+                                        Scalar::from(PlSmallStr::from(file.file_name.as_str())),
+                                        height,
+                                    ))?;
+                                pending_cdc_dfs.push(current_df);
+                            }
                         }
+
+                        apply_cdc_files(
+                            target_postgres_operator,
+                            &mut pending_cdc_dfs,
+                            &key_list,
+                            &upsert_dataframe_payload,
+                        )
+                        .await
                     }
+                    .await;
+                    let error = loaded.err().map(|e| {
+                        let error = format!("Failed to load table {}: {e}", table_name);
+                        info!("{}", error.red().bold());
+                        error
+                    });
 
                     let elapsed = start.elapsed();
                     info!(
@@ -227,34 +839,158 @@ impl CDCOperator {
                         .yellow()
                         .bold(),
                     );
+
+                    if !schema_timeline.changes().is_empty() {
+                        info!(
+                            "{}",
+                            format!("Schema timeline of table {}:", table_name).yellow()
+                        );
+                        for schema_change in schema_timeline.changes() {
+                            info!("{}", format!("  {}", schema_change).yellow());
+                        }
+                    }
+
+                    Ok(TableSnapshotReport {
+                        table_name: table_name.clone(),
+                        row_identity,
+                        nullable_keys,
+                        schema_changes: schema_timeline.changes().to_vec(),
+                        failed_constraints: Vec::new(),
+                        error,
+                    })
                 }
+                .boxed();
+                (table_name.clone(), table)
             })
-            .collect::<Vec<_>>();
-
-        use futures::FutureExt;
-        use futures::StreamExt;
-        use futures::stream::{self};
+            .collect::<HashMap<_, _>>();
 
         let num_of_buffers = env::var("NUM_OF_BUFFERS")
             .unwrap_or_else(|_| "80".to_string())
             .parse::<usize>()
-            .unwrap();
+            .map_err(|e| anyhow!("Invalid NUM_OF_BUFFERS: {e}"))?;
+
+        // Load the tables level by level, ensuring at most 80 futures run concurrently
+        let mut report = SnapshotReport {
+            failed_types,
+            skipped_tables: skipped_tables
+                .iter()
+                .map(|skipped_table| skipped_table.to_string())
+                .collect(),
+            ..SnapshotReport::default()
+        };
+        for level in &load_levels {
+            let stream = stream::iter(level.iter().filter_map(|table| tables.remove(table)))
+                .buffer_unordered(num_of_buffers);
+            report.tables.extend(stream.try_collect::<Vec<_>>().await?);
+        }
+
+        // The foreign keys are added after all the tables are loaded, so the load order
+        // only matters for the foreign keys that a previous run already added
+        if cdc_operator_snapshot_payload.replicate_constraints() {
+            let row_identities = report
+                .tables
+                .iter()
+                .map(|table| (table.table_name.clone(), table.row_identity.clone()))
+                .collect::<HashMap<_, _>>();
+            let mut failed_constraints = replicate_constraints(
+                source_postgres_operator,
+                target_postgres_operator,
+                &cdc_operator_snapshot_payload,
+                &table_list,
+                &row_identities,
+                &source_constraints,
+            )
+            .await?;
+            for table in &mut report.tables {
+                table.failed_constraints = failed_constraints
+                    .remove(&table.table_name)
+                    .unwrap_or_default();
+            }
+        }
+
+        for table in report.flagged_tables() {
+            let reason = if table.nullable_keys.is_empty() {
+                format!("its rows are matched on their {}", table.row_identity)
+            } else {
+                format!(
+                    "the rows with nulls in the key columns {} of its {} do not match their changes",
+                    table.nullable_keys.join(", "),
+                    table.row_identity
+                )
+            };
+            info!(
+                "{}",
+                format!(
+                    "Table {} may not be an exact replica: {}",
+                    table.table_name, reason
+                )
+                .yellow()
+                .bold()
+            );
+        }
 
-        // Convert the Vec into a stream
-        let stream = stream::iter(tables)
-            .map(|future| future.boxed())
-            .buffer_unordered(num_of_buffers);
+        for table in report.failed_tables() {
+            info!(
+                "{}",
+                format!(
+                    "Table {} was not loaded: {}",
+                    table.table_name,
+                    table.error.as_deref().unwrap_or_default()
+                )
+                .red()
+                .bold()
+            );
+        }
 
-        // Collect results, ensuring at most 80 futures run concurrently
-        stream.for_each(|_| async {}).await;
+        for table in &report.tables {
+            for failed_constraint in &table.failed_constraints {
+                info!(
+                    "{}",
+                    format!(
+                        "Table {} is missing {}",
+                        table.table_name, failed_constraint
+                    )
+                    .red()
+                    .bold()
+                );
+            }
+        }
 
         info!("{}", "Snapshotting completed...".bold().blue());
+
+        Ok(report)
     }
 
     /// Validates the data between S3 and a target database.
-    pub async fn validate(cdc_operator_validate_payload: CDCOperatorValidatePayload) {
+    ///
+    /// The tables are selected from the source database as in the snapshot, and compared by name.
+    ///
+    /// Returns an error if the tables could not be read or compared.
+    pub async fn validate(
+        cdc_operator_validate_payload: CDCOperatorValidatePayload,
+        source_postgres_operator: &(impl PostgresOperator + Sync),
+    ) -> Result<()> {
         info!("{}", "Starting pgdatadiff...".bold().blue());
 
+        let source_tables = source_postgres_operator
+            .get_tables_in_schema(
+                cdc_operator_validate_payload.schema_name().as_str(),
+                cdc_operator_validate_payload.table_filter(),
+            )
+            .await?;
+        let (loaded_tables, _) = group_partitions(source_tables);
+        let table_list = loaded_tables
+            .into_iter()
+            .map(|loaded_table| loaded_table.table.name)
+            .collect::<Vec<_>>();
+
+        // pgdatadiff compares all the tables of the schema when none are included
+        if table_list.is_empty() {
+            info!("{}", "No tables to validate".yellow().bold());
+            return Ok(());
+        }
+        info!("Tables to validate: {:?}", table_list);
+
         // Run rust-pgdatadiff
         info!(
             "{}",
@@ -274,8 +1010,8 @@ impl CDCOperator {
             .chunk_size(cdc_operator_validate_payload.chunk_size())
             .start_position(cdc_operator_validate_payload.start_position())
             .max_connections(100)
-            .include_tables(cdc_operator_validate_payload.included_tables().to_vec())
-            .exclude_tables(cdc_operator_validate_payload.excluded_tables().to_vec())
+            .include_tables(table_list)
+            .exclude_tables(Vec::<String>::new())
             .schema_name(cdc_operator_validate_payload.schema_name())
             .accept_invalid_certs_first_db(
                 cdc_operator_validate_payload.accept_invalid_certs_first_db(),
@@ -284,11 +1020,182 @@ impl CDCOperator {
                 cdc_operator_validate_payload.accept_invalid_certs_second_db(),
             )
             .build();
-        let diff_result = Differ::diff_dbs(payload).await;
-        if diff_result.is_err() {
-            panic!("Failed to run pgdatadiff: {:?}", diff_result.err().unwrap());
-        }
+        Differ::diff_dbs(payload)
+            .await
+            .map_err(|e| anyhow!("Failed to run pgdatadiff: {e}"))?;
 
         info!("{}", "Pgdatadiff completed!".bold().blue());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdc::apply_mode::ApplyModeValueEnum;
+    use crate::cdc::cdc_operator_mode::ModeValueEnum;
+    use crate::cdc::cdc_semantics::CdcSemanticsValueEnum;
+    use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+    use crate::postgres::postgres_operator::{CdcFileApplyReport, MockPostgresOperator};
+    use crate::storage::in_memory_store::InMemoryObjectStore;
+    use polars::df;
+
+    fn cdc_file(file_name: &str, ops: &[&str], ids: &[i32]) -> DataFrame {
+        df!(
+            "Op" => ops,
+            "_dms_ingestion_timestamp" => vec!["2024-02-14 10:00:00"; ops.len()],
+            "id" => ids,
+            CHANGE_FILE_COLUMN => vec![file_name; ops.len()],
+        )
+        .unwrap()
+    }
+
+    fn upsert_payload(apply_mode: ApplyModeValueEnum) -> UpsertDataframePayload {
+        UpsertDataframePayload {
+            database_name: "mydb".to_string(),
+            schema_name: "public".to_string(),
+            table_name: "items".to_string(),
+            primary_keys: vec!["id".to_string()],
+            dms_metadata_columns: DmsMetadataColumns::default(),
+            cdc_semantics: CdcSemanticsValueEnum::Full,
+            apply_mode,
+            is_compacted: !apply_mode.is_idempotent(),
+            geometry_srids: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_runs_are_compacted() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_upsert_dataframe_in_target_db()
+            .times(1)
+            .withf(|df, payload| payload.is_compacted && df.height() == 2)
+            .returning(|_, _| Ok(Vec::new()));
+
+        let mut cdc_dfs = vec![
+            cdc_file("1.csv", &["I", "U"], &[1, 1]),
+            cdc_file("2.csv", &["U", "D"], &[1, 2]),
+        ];
+        apply_cdc_files(
+            &postgres_operator,
+            &mut cdc_dfs,
+            &["id".to_string()],
+            &upsert_payload(ApplyModeValueEnum::Strict),
+        )
+        .await
+        .unwrap();
+        assert!(cdc_dfs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_files_are_applied_one_by_one() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_upsert_dataframe_in_target_db()
+            .times(2)
+            .withf(|df, payload| !payload.is_compacted && df.height() == 2)
+            .returning(|df, _| {
+                let file_name = df.column(CHANGE_FILE_COLUMN).unwrap().str().unwrap().get(0);
+                Ok(vec![CdcFileApplyReport {
+                    file_name: file_name.map(String::from),
+                    ..CdcFileApplyReport::default()
+                }])
+            });
+
+        let mut cdc_dfs = vec![
+            cdc_file("1.csv", &["I", "U"], &[1, 1]),
+            cdc_file("2.csv", &["U", "D"], &[1, 2]),
+        ];
+        apply_cdc_files(
+            &postgres_operator,
+            &mut cdc_dfs,
+            &["id".to_string()],
+            &upsert_payload(ApplyModeValueEnum::Idempotent),
+        )
+        .await
+        .unwrap();
+        assert!(cdc_dfs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_files_are_returned() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_upsert_dataframe_in_target_db()
+            .times(1)
+            .returning(|_, _| Err(anyhow!("duplicate key value violates unique constraint")));
+
+        let mut cdc_dfs = vec![
+            cdc_file("1.csv", &["I"], &[1]),
+            cdc_file("2.csv", &["I"], &[2]),
+        ];
+        let result = apply_cdc_files(
+            &postgres_operator,
+            &mut cdc_dfs,
+            &["id".to_string()],
+            &upsert_payload(ApplyModeValueEnum::Idempotent),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_errors_are_returned() {
+        let mut source_postgres_operator = MockPostgresOperator::new();
+        source_postgres_operator
+            .expect_get_tables_in_schema()
+            .times(1)
+            .returning(|_, _| Err(anyhow!("connection refused")));
+        let mut target_postgres_operator = MockPostgresOperator::new();
+        target_postgres_operator
+            .expect_create_schema()
+            .returning(|_| Ok(()));
+
+        let payload = CDCOperatorSnapshotPayload::builder()
+            .bucket_name("bucket")
+            .key("prefix")
+            .database_name("mydb")
+            .schema_name("public")
+            .included_tables(Vec::<String>::new())
+            .excluded_tables(Vec::<String>::new())
+            .mode(ModeValueEnum::FullLoadOnly)
+            .source_postgres_url("postgres://localhost/mydb".to_string())
+            .target_postgres_url("postgres://localhost/replica".to_string())
+            .build();
+        let result = CDCOperator::snapshot(
+            &payload,
+            &source_postgres_operator,
+            &target_postgres_operator,
+            &InMemoryObjectStore::new(),
+        )
+        .await;
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("connection refused")
+        );
+
+        // A date-aware snapshot needs a start date to list the files from
+        let payload = CDCOperatorSnapshotPayload::builder()
+            .bucket_name("bucket")
+            .key("prefix")
+            .database_name("mydb")
+            .schema_name("public")
+            .included_tables(Vec::<String>::new())
+            .excluded_tables(Vec::<String>::new())
+            .mode(ModeValueEnum::DateAware)
+            .source_postgres_url("postgres://localhost/mydb".to_string())
+            .target_postgres_url("postgres://localhost/replica".to_string())
+            .build();
+        let result = CDCOperator::snapshot(
+            &payload,
+            &source_postgres_operator,
+            &target_postgres_operator,
+            &InMemoryObjectStore::new(),
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("start_date"));
     }
 }
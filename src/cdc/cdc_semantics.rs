@@ -0,0 +1,137 @@
+use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+use crate::postgres::column_definition::ColumnDefinition;
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+/// Represents the DMS CDC output mode of the CDC files, and how they are applied.
+///
+/// The semantics can be one of the following:
+///
+/// * Full - The files have inserts, updates and deletes, applied as latest-wins.
+/// * InsertsOnly - DMS `CdcInsertsOnly`, every row is appended to keep the history of each key.
+/// * InsertsAndUpdates - DMS `CdcInsertsAndUpdates`, every row is upserted as latest-wins.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum CdcSemanticsValueEnum {
+    #[default]
+    Full,
+    InsertsOnly,
+    InsertsAndUpdates,
+}
+
+impl CdcSemanticsValueEnum {
+    /// Whether every row version is kept, so the target table has no primary key.
+    pub fn keeps_history(&self) -> bool {
+        *self == CdcSemanticsValueEnum::InsertsOnly
+    }
+
+    /// Whether inserts are applied as upserts, since the files do not distinguish them from updates.
+    pub fn upserts_inserts(&self) -> bool {
+        *self == CdcSemanticsValueEnum::InsertsAndUpdates
+    }
+
+    /// Gets the DMS columns that the target table keeps, next to the table columns.
+    ///
+    /// In history mode, the operation and the timestamp of each row version are kept,
+    /// since they tell the versions of a key apart. The operation is null for the rows
+    /// of LOAD files without it.
+    ///
+    /// # Arguments
+    ///
+    /// * `dms_metadata_columns` - The DMS metadata columns of the files.
+    ///
+    /// # Returns
+    ///
+    /// The definitions of the kept columns, or none when only the latest row is kept.
+    pub fn kept_metadata_columns(
+        &self,
+        dms_metadata_columns: &DmsMetadataColumns,
+    ) -> Vec<ColumnDefinition> {
+        if !self.keeps_history() {
+            return Vec::new();
+        }
+
+        let mut columns = Vec::new();
+        if dms_metadata_columns.has_op_column(true, *self) {
+            columns.push(ColumnDefinition::new(
+                &dms_metadata_columns.op_column,
+                "text",
+            ));
+        }
+        if dms_metadata_columns.has_timestamp_column() {
+            columns.push(ColumnDefinition::new(
+                &dms_metadata_columns.timestamp_column,
+                "timestamp",
+            ));
+        }
+        columns
+    }
+}
+
+impl Display for CdcSemanticsValueEnum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CdcSemanticsValueEnum::Full => write!(f, "Full"),
+            CdcSemanticsValueEnum::InsertsOnly => write!(f, "InsertsOnly"),
+            CdcSemanticsValueEnum::InsertsAndUpdates => write!(f, "InsertsAndUpdates"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_history() {
+        assert!(!CdcSemanticsValueEnum::Full.keeps_history());
+        assert!(CdcSemanticsValueEnum::InsertsOnly.keeps_history());
+        assert!(!CdcSemanticsValueEnum::InsertsAndUpdates.keeps_history());
+    }
+
+    #[test]
+    fn test_upserts_inserts() {
+        assert!(!CdcSemanticsValueEnum::Full.upserts_inserts());
+        assert!(!CdcSemanticsValueEnum::InsertsOnly.upserts_inserts());
+        assert!(CdcSemanticsValueEnum::InsertsAndUpdates.upserts_inserts());
+    }
+
+    #[test]
+    fn test_kept_metadata_columns() {
+        let dms_metadata_columns = DmsMetadataColumns::default();
+
+        assert!(
+            CdcSemanticsValueEnum::Full
+                .kept_metadata_columns(&dms_metadata_columns)
+                .is_empty()
+        );
+        assert!(
+            CdcSemanticsValueEnum::InsertsAndUpdates
+                .kept_metadata_columns(&dms_metadata_columns)
+                .is_empty()
+        );
+        assert_eq!(
+            CdcSemanticsValueEnum::InsertsOnly.kept_metadata_columns(&dms_metadata_columns),
+            vec![
+                ColumnDefinition::new("Op", "text"),
+                ColumnDefinition::new("_dms_ingestion_timestamp", "timestamp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kept_metadata_columns_without_op_column() {
+        // CdcInsertsOnly files only have the operation column with IncludeOpForFullLoad
+        let dms_metadata_columns = DmsMetadataColumns {
+            include_op_for_full_load: false,
+            ..DmsMetadataColumns::default()
+        };
+
+        assert_eq!(
+            CdcSemanticsValueEnum::InsertsOnly.kept_metadata_columns(&dms_metadata_columns),
+            vec![ColumnDefinition::new(
+                "_dms_ingestion_timestamp",
+                "timestamp"
+            )]
+        );
+    }
+}
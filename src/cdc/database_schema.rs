@@ -0,0 +1,132 @@
+use anyhow::{Result, anyhow};
+use std::fmt::{self, Display, Formatter};
+
+/// Represents a schema of a source database that a run replicates,
+/// whose DMS output is under `{prefix}/{database}/{schema}`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DatabaseSchema {
+    /// The name of the database.
+    pub database_name: String,
+    /// The name of the schema.
+    pub schema_name: String,
+}
+
+impl DatabaseSchema {
+    /// Parses a schema, as `schema` or `database.schema`, the database defaulting to the given one.
+    ///
+    /// The value is split on its first `.`, so a database name can not contain a dot.
+    /// Names are taken as they are, without quotes, and a quoted name is rejected
+    /// rather than split inside its quotes.
+    pub fn parse(value: &str, default_database: &str) -> Result<Self> {
+        if value.contains('"') {
+            return Err(anyhow!(
+                "Invalid database schema {value}: quoted names are not supported, expected schema or database.schema"
+            ));
+        }
+
+        let (database_name, schema_name) = value
+            .trim()
+            .split_once('.')
+            .unwrap_or((default_database, value.trim()));
+
+        if database_name.is_empty() || schema_name.is_empty() {
+            return Err(anyhow!(
+                "Invalid database schema {value}: expected schema or database.schema"
+            ));
+        }
+
+        Ok(DatabaseSchema {
+            database_name: database_name.to_string(),
+            schema_name: schema_name.to_string(),
+        })
+    }
+}
+
+impl Display for DatabaseSchema {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.database_name, self.schema_name)
+    }
+}
+
+/// Groups schemas by their database, so that the schemas of a database share its connections.
+///
+/// # Returns
+///
+/// The databases with their schemas, both in the order they first appear.
+pub fn group_by_database(database_schemas: &[DatabaseSchema]) -> Vec<(String, Vec<String>)> {
+    let mut databases: Vec<(String, Vec<String>)> = Vec::new();
+    for database_schema in database_schemas {
+        match databases
+            .iter_mut()
+            .find(|(database_name, _)| *database_name == database_schema.database_name)
+        {
+            Some((_, schemas)) => {
+                if !schemas.contains(&database_schema.schema_name) {
+                    schemas.push(database_schema.schema_name.clone());
+                }
+            }
+            None => databases.push((
+                database_schema.database_name.clone(),
+                vec![database_schema.schema_name.clone()],
+            )),
+        }
+    }
+    databases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            DatabaseSchema::parse("public", "mydb").unwrap(),
+            DatabaseSchema {
+                database_name: "mydb".to_string(),
+                schema_name: "public".to_string(),
+            }
+        );
+        assert_eq!(
+            DatabaseSchema::parse(" sales.billing ", "mydb")
+                .unwrap()
+                .to_string(),
+            "sales.billing"
+        );
+        assert!(DatabaseSchema::parse("sales.", "mydb").is_err());
+        assert!(DatabaseSchema::parse("", "mydb").is_err());
+
+        // Only the first dot separates the database from the schema
+        assert_eq!(
+            DatabaseSchema::parse("sales.billing.v2", "mydb").unwrap(),
+            DatabaseSchema {
+                database_name: "sales".to_string(),
+                schema_name: "billing.v2".to_string(),
+            }
+        );
+        assert!(DatabaseSchema::parse("\"billing.v2\"", "mydb").is_err());
+        assert!(DatabaseSchema::parse("sales.\"billing.v2\"", "mydb").is_err());
+    }
+
+    #[test]
+    fn test_group_by_database() {
+        let database_schemas = ["public", "sales.public", "audit", "sales.billing", "public"]
+            .iter()
+            .map(|value| DatabaseSchema::parse(value, "mydb").unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            group_by_database(&database_schemas),
+            vec![
+                (
+                    "mydb".to_string(),
+                    vec!["public".to_string(), "audit".to_string()]
+                ),
+                (
+                    "sales".to_string(),
+                    vec!["public".to_string(), "billing".to_string()]
+                ),
+            ]
+        );
+    }
+}
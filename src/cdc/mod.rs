@@ -1,5 +1,10 @@
+pub mod apply_mode;
 pub mod cdc_operator;
 pub mod cdc_operator_mode;
 pub mod cdc_operator_payload;
+pub mod cdc_semantics;
+pub mod database_schema;
+pub mod schema_evolution;
 pub mod snapshot_payload;
+pub mod snapshot_report;
 pub mod validate_payload;
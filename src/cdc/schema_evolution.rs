@@ -0,0 +1,115 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Represents a change of the columns of a table between two of its files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaChange {
+    /// The first file with the new columns.
+    pub file_name: String,
+    /// The columns that the file has and the previous file had not.
+    pub added_columns: Vec<String>,
+    /// The columns that the previous file had and the file has not.
+    pub missing_columns: Vec<String>,
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: added [{}], missing [{}]",
+            self.file_name,
+            self.added_columns.join(", "),
+            self.missing_columns.join(", ")
+        )
+    }
+}
+
+/// Tracks the columns of the files of a table in the order they are applied,
+/// keeping the changes of the columns between consecutive files.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaTimeline {
+    columns: Option<Vec<String>>,
+    changes: Vec<SchemaChange>,
+}
+
+impl SchemaTimeline {
+    /// Records the columns of the next file of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - The name of the file.
+    /// * `columns` - The table columns of the file.
+    ///
+    /// # Returns
+    ///
+    /// The change of the columns since the previous file, if they changed.
+    pub fn observe(&mut self, file_name: &str, columns: &[String]) -> Option<&SchemaChange> {
+        let previous_columns = self.columns.replace(columns.to_vec())?;
+
+        let added_columns = columns
+            .iter()
+            .filter(|column| !previous_columns.contains(column))
+            .cloned()
+            .collect::<Vec<_>>();
+        let missing_columns = previous_columns
+            .iter()
+            .filter(|column| !columns.contains(column))
+            .cloned()
+            .collect::<Vec<_>>();
+        if added_columns.is_empty() && missing_columns.is_empty() {
+            return None;
+        }
+
+        self.changes.push(SchemaChange {
+            file_name: file_name.to_string(),
+            added_columns,
+            missing_columns,
+        });
+        self.changes.last()
+    }
+
+    /// Gets the changes of the columns, in the order of the files.
+    pub fn changes(&self) -> &[SchemaChange] {
+        &self.changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut timeline = SchemaTimeline::default();
+
+        assert!(
+            timeline
+                .observe("LOAD00000001.parquet", &columns(&["id", "name"]))
+                .is_none()
+        );
+        assert!(
+            timeline
+                .observe("20240214-1.parquet", &columns(&["id", "name"]))
+                .is_none()
+        );
+
+        let change = timeline
+            .observe(
+                "20240214-2.parquet",
+                &columns(&["id", "full_name", "email"]),
+            )
+            .cloned()
+            .unwrap();
+        assert_eq!(change.added_columns, columns(&["full_name", "email"]));
+        assert_eq!(change.missing_columns, columns(&["name"]));
+        assert_eq!(
+            change.to_string(),
+            "20240214-2.parquet: added [full_name, email], missing [name]"
+        );
+
+        assert_eq!(timeline.changes(), &[change]);
+    }
+}
@@ -1,8 +1,21 @@
 use bon::bon;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
-use crate::postgres::table_mode::TableMode;
+use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+use crate::dataframe::file_format::{CsvSettings, FileFormatValueEnum};
+use crate::postgres::table_filter::TableFilter;
+use crate::s3::date_partition::DatePartitionLayout;
 
+use super::apply_mode::ApplyModeValueEnum;
 use super::cdc_operator_mode::ModeValueEnum;
+use super::cdc_semantics::CdcSemanticsValueEnum;
+
+/// Gets permits that do not bound the tables loaded at the same time.
+fn unbounded_permits() -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(Semaphore::MAX_PERMITS))
+}
 
 #[allow(clippy::too_many_arguments)]
 #[derive(Debug)]
@@ -13,11 +26,21 @@ pub struct CDCOperatorSnapshotPayload {
     schema_name: String,
     included_tables: Vec<String>,
     excluded_tables: Vec<String>,
+    table_filter: TableFilter,
     mode: ModeValueEnum,
     start_date: Option<String>,
     stop_date: Option<String>,
     source_postgres_url: String,
     target_postgres_url: String,
+    date_partition_layout: DatePartitionLayout,
+    file_format: FileFormatValueEnum,
+    csv_settings: CsvSettings,
+    dms_metadata_columns: DmsMetadataColumns,
+    cdc_semantics: CdcSemanticsValueEnum,
+    apply_mode: ApplyModeValueEnum,
+    key_overrides: HashMap<String, Vec<String>>,
+    replicate_constraints: bool,
+    table_permits: Arc<Semaphore>,
 }
 
 #[bon]
@@ -35,19 +58,43 @@ impl CDCOperatorSnapshotPayload {
         stop_date: Option<String>,
         source_postgres_url: String,
         target_postgres_url: String,
+        #[builder(default)] date_partition_layout: DatePartitionLayout,
+        #[builder(default)] file_format: FileFormatValueEnum,
+        #[builder(default)] csv_settings: CsvSettings,
+        #[builder(default)] dms_metadata_columns: DmsMetadataColumns,
+        #[builder(default)] cdc_semantics: CdcSemanticsValueEnum,
+        #[builder(default)] apply_mode: ApplyModeValueEnum,
+        #[builder(default)] key_overrides: HashMap<String, Vec<String>>,
+        #[builder(default)] replicate_constraints: bool,
+        #[builder(default = unbounded_permits())] table_permits: Arc<Semaphore>,
     ) -> Self {
+        let included_tables: Vec<String> = included_tables.into_iter().map(|x| x.into()).collect();
+        let excluded_tables: Vec<String> = excluded_tables.into_iter().map(|x| x.into()).collect();
+        let table_filter = TableFilter::new(&included_tables, &excluded_tables)
+            .unwrap_or_else(|e| panic!("Invalid table filter: {e}"));
+
         CDCOperatorSnapshotPayload {
             bucket_name: bucket_name.into(),
             key: key.into(),
             database_name: database_name.into(),
             schema_name: schema_name.into(),
-            included_tables: included_tables.into_iter().map(|x| x.into()).collect(),
-            excluded_tables: excluded_tables.into_iter().map(|x| x.into()).collect(),
+            included_tables,
+            excluded_tables,
+            table_filter,
             mode,
             start_date,
             stop_date,
             source_postgres_url,
             target_postgres_url,
+            date_partition_layout,
+            file_format,
+            csv_settings,
+            dms_metadata_columns,
+            cdc_semantics,
+            apply_mode,
+            key_overrides,
+            replicate_constraints,
+            table_permits,
         }
     }
 
@@ -75,14 +122,9 @@ impl CDCOperatorSnapshotPayload {
         self.excluded_tables.clone()
     }
 
-    pub fn table_mode(&self) -> TableMode {
-        if !self.included_tables.is_empty() {
-            TableMode::IncludeTables
-        } else if !self.excluded_tables.is_empty() {
-            TableMode::ExcludeTables
-        } else {
-            TableMode::AllTables
-        }
+    /// Gets the selection of the tables, by their included and excluded patterns.
+    pub fn table_filter(&self) -> &TableFilter {
+        &self.table_filter
     }
 
     pub fn start_date(&self) -> Option<String> {
@@ -112,4 +154,42 @@ impl CDCOperatorSnapshotPayload {
     pub fn target_postgres_url(&self) -> String {
         self.target_postgres_url.clone()
     }
+
+    pub fn date_partition_layout(&self) -> DatePartitionLayout {
+        self.date_partition_layout
+    }
+
+    pub fn file_format(&self) -> FileFormatValueEnum {
+        self.file_format
+    }
+
+    pub fn csv_settings(&self) -> &CsvSettings {
+        &self.csv_settings
+    }
+
+    pub fn dms_metadata_columns(&self) -> &DmsMetadataColumns {
+        &self.dms_metadata_columns
+    }
+
+    pub fn cdc_semantics(&self) -> CdcSemanticsValueEnum {
+        self.cdc_semantics
+    }
+
+    pub fn apply_mode(&self) -> ApplyModeValueEnum {
+        self.apply_mode
+    }
+
+    pub fn key_override(&self, table_name: &str) -> Option<Vec<String>> {
+        self.key_overrides.get(table_name).cloned()
+    }
+
+    pub fn replicate_constraints(&self) -> bool {
+        self.replicate_constraints
+    }
+
+    /// Gets the permits of the tables that are loaded at the same time, which the snapshots
+    /// of several schemas share to stay within the connections of a run.
+    pub fn table_permits(&self) -> &Semaphore {
+        &self.table_permits
+    }
 }
@@ -0,0 +1,138 @@
+use super::schema_evolution::SchemaChange;
+use crate::postgres::row_identity::RowIdentity;
+
+/// Represents the outcome of a snapshot of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableSnapshotReport {
+    /// The name of the table.
+    pub table_name: String,
+    /// How the changes of the table were matched to its rows.
+    pub row_identity: RowIdentity,
+    /// The key columns that can be null, whose rows with nulls do not match their changes.
+    pub nullable_keys: Vec<String>,
+    /// The changes of the columns between the files of the table.
+    pub schema_changes: Vec<SchemaChange>,
+    /// The indexes and constraints of the source table that could not be created, with the reason.
+    pub failed_constraints: Vec<String>,
+    /// The error that stopped the snapshot of the table, which is then not fully loaded.
+    pub error: Option<String>,
+}
+
+impl TableSnapshotReport {
+    /// Whether the table may not be an exact replica of the source table.
+    pub fn is_flagged(&self) -> bool {
+        !self.row_identity.is_exact() || !self.nullable_keys.is_empty()
+    }
+}
+
+/// Represents the outcome of a snapshot, with a report per table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotReport {
+    pub tables: Vec<TableSnapshotReport>,
+    /// The types of the columns that could not be created in the target database, with the reason.
+    pub failed_types: Vec<String>,
+    /// The views and foreign tables of the schema, which are not loaded, with their kind.
+    pub skipped_tables: Vec<String>,
+}
+
+impl SnapshotReport {
+    /// Gets the tables that may not be exact replicas of the source tables.
+    pub fn flagged_tables(&self) -> Vec<&TableSnapshotReport> {
+        self.tables
+            .iter()
+            .filter(|table| table.is_flagged())
+            .collect()
+    }
+
+    /// Gets the tables whose snapshot stopped with an error.
+    pub fn failed_tables(&self) -> Vec<&TableSnapshotReport> {
+        self.tables
+            .iter()
+            .filter(|table| table.error.is_some())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flagged_tables() {
+        let report = SnapshotReport {
+            tables: vec![
+                TableSnapshotReport {
+                    table_name: "orders".to_string(),
+                    row_identity: RowIdentity::PrimaryKey(vec!["id".to_string()]),
+                    nullable_keys: vec![],
+                    schema_changes: vec![],
+                    failed_constraints: vec![],
+                    error: None,
+                },
+                TableSnapshotReport {
+                    table_name: "events".to_string(),
+                    row_identity: RowIdentity::FullRow,
+                    nullable_keys: vec![],
+                    schema_changes: vec![],
+                    failed_constraints: vec![],
+                    error: None,
+                },
+            ],
+            ..SnapshotReport::default()
+        };
+
+        let flagged_tables = report.flagged_tables();
+        assert_eq!(flagged_tables.len(), 1);
+        assert_eq!(flagged_tables[0].table_name, "events");
+    }
+
+    #[test]
+    fn test_tables_with_nullable_keys_are_flagged() {
+        let report = SnapshotReport {
+            tables: vec![TableSnapshotReport {
+                table_name: "order_lines".to_string(),
+                row_identity: RowIdentity::KeyOverride(vec![
+                    "order_id".to_string(),
+                    "line".to_string(),
+                ]),
+                nullable_keys: vec!["line".to_string()],
+                schema_changes: vec![],
+                failed_constraints: vec![],
+                error: None,
+            }],
+            ..SnapshotReport::default()
+        };
+
+        assert_eq!(report.flagged_tables().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_tables() {
+        let report = SnapshotReport {
+            tables: vec![
+                TableSnapshotReport {
+                    table_name: "orders".to_string(),
+                    row_identity: RowIdentity::PrimaryKey(vec!["id".to_string()]),
+                    nullable_keys: vec![],
+                    schema_changes: vec![],
+                    failed_constraints: vec![],
+                    error: Some("Found gaps in the LOAD file numbering".to_string()),
+                },
+                TableSnapshotReport {
+                    table_name: "events".to_string(),
+                    row_identity: RowIdentity::PrimaryKey(vec!["id".to_string()]),
+                    nullable_keys: vec![],
+                    schema_changes: vec![],
+                    failed_constraints: vec![],
+                    error: None,
+                },
+            ],
+            ..SnapshotReport::default()
+        };
+
+        let failed_tables = report.failed_tables();
+        assert_eq!(failed_tables.len(), 1);
+        assert_eq!(failed_tables[0].table_name, "orders");
+        assert!(report.flagged_tables().is_empty());
+    }
+}
@@ -1,15 +1,20 @@
 use bon::bon;
 
+use crate::postgres::postgres_config::with_session_timezone;
+use crate::postgres::table_filter::TableFilter;
+
 pub struct CDCOperatorValidatePayload {
     pub source_postgres_url: String,
     pub target_postgres_url: String,
     pub included_tables: Vec<String>,
     pub excluded_tables: Vec<String>,
+    pub table_filter: TableFilter,
     pub schema_name: String,
     pub chunk_size: i64,
     pub start_position: i64,
     pub accept_invalid_certs_first_db: bool,
     pub accept_invalid_certs_second_db: bool,
+    pub session_timezone: Option<String>,
 }
 
 #[bon]
@@ -25,26 +30,43 @@ impl CDCOperatorValidatePayload {
         start_position: i64,
         accept_invalid_certs_first_db: bool,
         accept_invalid_certs_second_db: bool,
+        session_timezone: Option<String>,
     ) -> Self {
+        let included_tables: Vec<String> = included_tables.into_iter().map(|t| t.into()).collect();
+        let excluded_tables: Vec<String> = excluded_tables.into_iter().map(|t| t.into()).collect();
+        let table_filter = TableFilter::new(&included_tables, &excluded_tables)
+            .unwrap_or_else(|e| panic!("Invalid table filter: {e}"));
+
         CDCOperatorValidatePayload {
             source_postgres_url: source_postgres_url.into(),
             target_postgres_url: target_postgres_url.into(),
-            included_tables: included_tables.into_iter().map(|t| t.into()).collect(),
-            excluded_tables: excluded_tables.into_iter().map(|t| t.into()).collect(),
+            included_tables,
+            excluded_tables,
+            table_filter,
             schema_name: schema_name.into(),
             chunk_size,
             start_position,
             accept_invalid_certs_first_db,
             accept_invalid_certs_second_db,
+            session_timezone,
         }
     }
 
+    /// Gets the URL of the source database, with the session timezone if there is one.
     pub fn source_postgres_url(&self) -> String {
-        self.source_postgres_url.clone()
+        self.with_session_timezone(&self.source_postgres_url)
     }
 
+    /// Gets the URL of the target database, with the session timezone if there is one.
     pub fn target_postgres_url(&self) -> String {
-        self.target_postgres_url.clone()
+        self.with_session_timezone(&self.target_postgres_url)
+    }
+
+    fn with_session_timezone(&self, postgres_url: &str) -> String {
+        match &self.session_timezone {
+            Some(session_timezone) => with_session_timezone(postgres_url, session_timezone),
+            None => postgres_url.to_string(),
+        }
     }
 
     pub fn included_tables(&self) -> Vec<String> {
@@ -55,6 +77,11 @@ impl CDCOperatorValidatePayload {
         self.excluded_tables.clone()
     }
 
+    /// Gets the selection of the tables, by their included and excluded patterns.
+    pub fn table_filter(&self) -> &TableFilter {
+        &self.table_filter
+    }
+
     pub fn schema_name(&self) -> String {
         self.schema_name.clone()
     }
@@ -0,0 +1,299 @@
+use anyhow::Result;
+use polars::functions::concat_df_diagonal;
+use polars::prelude::*;
+
+use super::dataframe_ops::sort_by_ingestion_timestamp;
+use super::dms_metadata_columns::DmsMetadataColumns;
+
+/// The temporary column with the number of changes of each key in the run.
+const CHANGE_COUNT_COLUMN: &str = "_dms_cdc_change_count";
+
+/// The temporary column with the first change of each key in the run.
+const FIRST_CHANGE_COLUMN: &str = "_dms_cdc_first_change";
+
+/// Compacts a run of CDC files of a table to the net change of each primary key.
+///
+/// The files are combined and ordered by ingestion timestamp, keeping the order of the
+/// files for changes with the same timestamp. Updates of the primary key are split into
+/// a delete of the old key and an update of the new key, when the files have before-images.
+/// Only the last change of each key is kept. A key that the run first inserts stays an
+/// insert, since it did not exist before the run. Otherwise an insert that follows other
+/// changes of its key, e.g. a delete and a re-insert, becomes an update, since the key
+/// may already exist in the target.
+///
+/// # Arguments
+///
+/// * `dfs` - The DataFrames of the CDC files, in the order DMS wrote them.
+/// * `primary_keys` - The primary key columns of the table.
+/// * `dms_metadata_columns` - The DMS metadata columns of the files.
+///
+/// # Returns
+///
+/// A DataFrame with the columns of the files and at most one row per key,
+/// or all the changes in order if the table has no primary key.
+pub fn compact_cdc_changes(
+    dfs: &[DataFrame],
+    primary_keys: &[String],
+    dms_metadata_columns: &DmsMetadataColumns,
+) -> Result<DataFrame> {
+    if dfs.is_empty() {
+        return Ok(DataFrame::empty());
+    }
+
+    let df = concat_df_diagonal(dfs)?;
+    let df = sort_by_ingestion_timestamp(&df, &dms_metadata_columns.timestamp_column)?;
+
+    if primary_keys.is_empty() {
+        return Ok(df);
+    }
+
+    let mut df = dms_metadata_columns.split_key_changes(&df, primary_keys)?;
+    let op_column = dms_metadata_columns.op_column.as_str();
+
+    // Files without an Op column only contain inserts
+    if df.column(op_column).is_err() {
+        df.with_column(Column::new_scalar(
+            op_column.into(),
+            Scalar::from(PlSmallStr::from("I")),
+            df.height(),
+        ))?;
+    }
+
+    let keys = primary_keys
+        .iter()
+        .map(|key| col(key.as_str()))
+        .collect::<Vec<_>>();
+
+    let df = df
+        .lazy()
+        .with_columns([
+            len().over(&keys).alias(CHANGE_COUNT_COLUMN),
+            col(op_column)
+                .first()
+                .over(&keys)
+                .alias(FIRST_CHANGE_COLUMN),
+        ])
+        .unique_stable(
+            Some(primary_keys.iter().map(PlSmallStr::from).collect()),
+            UniqueKeepStrategy::Last,
+        )
+        .with_column(
+            when(col(op_column).eq(lit("D")))
+                .then(col(op_column))
+                .when(col(FIRST_CHANGE_COLUMN).eq(lit("I")))
+                .then(lit("I"))
+                .when(
+                    col(op_column)
+                        .eq(lit("I"))
+                        .and(col(CHANGE_COUNT_COLUMN).gt(lit(1))),
+                )
+                .then(lit("U"))
+                .otherwise(col(op_column))
+                .alias(op_column),
+        )
+        .drop([CHANGE_COUNT_COLUMN, FIRST_CHANGE_COLUMN])
+        .collect()?;
+
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops_by_key(df: &DataFrame) -> Vec<(i32, String, Option<String>)> {
+        let ids = df.column("id").unwrap().i32().unwrap();
+        let ops = df.column("Op").unwrap().str().unwrap();
+        let names = df.column("name").unwrap().str().unwrap();
+
+        let mut rows = (0..df.height())
+            .map(|idx| {
+                (
+                    ids.get(idx).unwrap(),
+                    ops.get(idx).unwrap().to_string(),
+                    names.get(idx).map(String::from),
+                )
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_compact_keeps_last_change_per_key() {
+        let first_file = df!(
+            "Op" => ["U", "U", "I"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00", "2024-02-14 10:00:01", "2024-02-14 10:00:02"],
+            "id" => [1, 1, 2],
+            "name" => ["a", "b", "c"],
+        )
+        .unwrap();
+        let second_file = df!(
+            "Op" => ["U", "D"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 11:00:00", "2024-02-14 11:00:01"],
+            "id" => [1, 3],
+            "name" => [Some("d"), None],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(
+            &[first_file, second_file],
+            &["id".to_string()],
+            &DmsMetadataColumns::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![
+                (1, "U".to_string(), Some("d".to_string())),
+                (2, "I".to_string(), Some("c".to_string())),
+                (3, "D".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_delete_then_reinsert_becomes_update() {
+        let first_file = df!(
+            "Op" => ["D"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00"],
+            "id" => [1],
+            "name" => ["a"],
+        )
+        .unwrap();
+        let second_file = df!(
+            "Op" => ["I", "I", "D"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:01", "2024-02-14 10:00:02", "2024-02-14 10:00:03"],
+            "id" => [1, 2, 2],
+            "name" => ["b", "c", "c"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(
+            &[first_file, second_file],
+            &["id".to_string()],
+            &DmsMetadataColumns::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![
+                (1, "U".to_string(), Some("b".to_string())),
+                (2, "D".to_string(), Some("c".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_same_timestamp_keeps_file_order() {
+        let first_file = df!(
+            "Op" => ["U"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00"],
+            "id" => [1],
+            "name" => ["a"],
+        )
+        .unwrap();
+        let second_file = df!(
+            "Op" => ["U"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00"],
+            "id" => [1],
+            "name" => ["b"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(
+            &[first_file, second_file],
+            &["id".to_string()],
+            &DmsMetadataColumns::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![(1, "U".to_string(), Some("b".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_compact_without_op_column() {
+        let df = df!(
+            "id" => [1, 1],
+            "name" => ["a", "b"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(&[df], &["id".to_string()], &DmsMetadataColumns::default())
+            .unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![(1, "I".to_string(), Some("b".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_compact_insert_then_update_stays_insert() {
+        let df = df!(
+            "Op" => ["I", "U", "U"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00", "2024-02-14 10:00:01", "2024-02-14 10:00:02"],
+            "id" => [1, 1, 2],
+            "name" => ["a", "b", "c"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(&[df], &["id".to_string()], &DmsMetadataColumns::default())
+            .unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![
+                (1, "I".to_string(), Some("b".to_string())),
+                (2, "U".to_string(), Some("c".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_primary_key_changes() {
+        let dms_metadata_columns = DmsMetadataColumns {
+            before_image_prefix: Some("BI_".to_string()),
+            ..DmsMetadataColumns::default()
+        };
+        // 1 is renamed to 2 and then to 3, and 4 is renamed to 1
+        let df = df!(
+            "Op" => ["U", "U", "U"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00", "2024-02-14 10:00:01", "2024-02-14 10:00:02"],
+            "BI_id" => [1, 2, 4],
+            "id" => [2, 3, 1],
+            "name" => ["a", "a", "d"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(&[df], &["id".to_string()], &dms_metadata_columns).unwrap();
+
+        assert_eq!(
+            ops_by_key(&df),
+            vec![
+                (1, "U".to_string(), Some("d".to_string())),
+                (2, "D".to_string(), Some("a".to_string())),
+                (3, "U".to_string(), Some("a".to_string())),
+                (4, "D".to_string(), Some("d".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_without_primary_key_keeps_all_changes() {
+        let df = df!(
+            "Op" => ["I", "U"],
+            "id" => [1, 1],
+            "name" => ["a", "b"],
+        )
+        .unwrap();
+
+        let df = compact_cdc_changes(&[df], &[], &DmsMetadataColumns::default()).unwrap();
+        assert_eq!(df.height(), 2);
+    }
+}
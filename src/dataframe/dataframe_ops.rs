@@ -1,12 +1,43 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use aws_sdk_s3::Client as S3Client;
+use flate2::read::GzDecoder;
+use indexmap::IndexMap;
 use log::debug;
 use polars::prelude::*;
+use std::io::Read;
+
+use super::file_format::CsvSettings;
+use crate::storage::object_store::ObjectStore;
 
 #[cfg(test)]
 use mockall::automock;
 
+/// Sorts the rows of a DataFrame by the DMS ingestion timestamp.
+///
+/// Rows with the same timestamp keep their original order, so that the
+/// last change of a key in the file is still applied last.
+///
+/// # Arguments
+///
+/// * `df` - The DataFrame to sort.
+/// * `timestamp_column` - The name of the DMS ingestion timestamp column.
+///
+/// # Returns
+///
+/// The sorted DataFrame, or the same DataFrame if it has no timestamp column.
+pub fn sort_by_ingestion_timestamp(df: &DataFrame, timestamp_column: &str) -> Result<DataFrame> {
+    if df.column(timestamp_column).is_err() {
+        return Ok(df.clone());
+    }
+
+    let df = df.sort(
+        [timestamp_column],
+        SortMultipleOptions::default().with_maintain_order(true),
+    )?;
+
+    Ok(df)
+}
+
 pub struct CreateDataframePayload {
     pub bucket_name: String,
     pub key: String,
@@ -18,7 +49,7 @@ pub struct CreateDataframePayload {
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait DataframeOperator {
-    /// Reads a Parquet file from S3.
+    /// Reads a Parquet file from the object store.
     ///
     /// # Arguments
     ///
@@ -31,15 +62,35 @@ pub trait DataframeOperator {
         &self,
         payload: &CreateDataframePayload,
     ) -> Result<Option<polars::prelude::DataFrame>>;
+
+    /// Reads a DMS CSV file from the object store.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload to create a DataFrame from a CSV file.
+    /// * `csv_settings` - The DMS settings of the CSV file.
+    /// * `table_columns` - The columns of the source table and their data types.
+    /// * `metadata_columns` - The DMS metadata columns that precede the table columns.
+    ///
+    /// # Returns
+    ///
+    /// A DataFrame, with the column names and types of the source table.
+    async fn create_dataframe_from_csv_file(
+        &self,
+        payload: &CreateDataframePayload,
+        csv_settings: &CsvSettings,
+        table_columns: &IndexMap<String, String>,
+        metadata_columns: &[String],
+    ) -> Result<Option<polars::prelude::DataFrame>>;
 }
 
 pub struct DataframeOperatorImpl<'a> {
-    s3_client: &'a S3Client,
+    object_store: &'a dyn ObjectStore,
 }
 
 impl<'a> DataframeOperatorImpl<'a> {
-    pub fn new(s3_client: &'a S3Client) -> Self {
-        Self { s3_client }
+    pub fn new(object_store: &'a dyn ObjectStore) -> Self {
+        Self { object_store }
     }
 }
 
@@ -63,16 +114,10 @@ impl DataframeOperator for DataframeOperatorImpl<'_> {
         // debug!("{:?}", df.schema());
         // Ok(df)
 
-        let object = self
-            .s3_client
-            .get_object()
-            .bucket(&payload.bucket_name)
-            .key(&payload.key)
-            .send()
-            .await
-            .unwrap();
-
-        let bytes = object.body.collect().await.unwrap().into_bytes();
+        let bytes = self
+            .object_store
+            .get_object(&payload.bucket_name, &payload.key)
+            .await?;
         let cursor = std::io::Cursor::new(bytes);
 
         let reader = ParquetReader::new(cursor);
@@ -85,15 +130,187 @@ impl DataframeOperator for DataframeOperatorImpl<'_> {
 
         Ok(Some(df))
     }
+
+    async fn create_dataframe_from_csv_file(
+        &self,
+        payload: &CreateDataframePayload,
+        csv_settings: &CsvSettings,
+        table_columns: &IndexMap<String, String>,
+        metadata_columns: &[String],
+    ) -> Result<Option<polars::prelude::DataFrame>> {
+        let bytes = self
+            .object_store
+            .get_object(&payload.bucket_name, &payload.key)
+            .await?;
+
+        let bytes = if csv_settings.gzip {
+            let mut decompressed_bytes = Vec::new();
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed_bytes)?;
+            decompressed_bytes
+        } else {
+            bytes
+        };
+
+        let df = read_dms_csv(bytes, csv_settings, table_columns, metadata_columns)?;
+        debug!("{:?}", df.schema());
+
+        Ok(Some(df))
+    }
+}
+
+/// Reads the bytes of a DMS CSV file into a DataFrame.
+///
+/// Every value is read as a string, and then cast to the type of its source table column.
+/// Files without a header get the column names of the source table, preceded by
+/// the DMS metadata columns that the settings of the task write.
+///
+/// # Arguments
+///
+/// * `bytes` - The uncompressed bytes of the CSV file.
+/// * `csv_settings` - The DMS settings of the CSV file.
+/// * `table_columns` - The columns of the source table and their data types.
+/// * `metadata_columns` - The DMS metadata columns that precede the table columns.
+///
+/// # Returns
+///
+/// A DataFrame.
+pub fn read_dms_csv(
+    bytes: Vec<u8>,
+    csv_settings: &CsvSettings,
+    table_columns: &IndexMap<String, String>,
+    metadata_columns: &[String],
+) -> Result<DataFrame> {
+    let null_values = if csv_settings.null_value.is_empty() {
+        None
+    } else {
+        Some(NullValues::AllColumnsSingle(
+            csv_settings.null_value.as_str().into(),
+        ))
+    };
+
+    let parse_options = CsvParseOptions::default()
+        .with_separator(csv_settings.delimiter)
+        .with_eol_char(csv_settings.row_delimiter)
+        .with_null_values(null_values);
+
+    let mut df = CsvReadOptions::default()
+        .with_has_header(csv_settings.has_header)
+        .with_infer_schema_length(Some(0))
+        .with_raise_if_empty(false)
+        .with_parse_options(parse_options)
+        .into_reader_with_file_handle(std::io::Cursor::new(bytes))
+        .finish()?;
+
+    // The metadata columns of the file are known from the DMS settings, and the table columns
+    // follow them, without the columns that were added to the table after the file was written
+    if !csv_settings.has_header && df.width() > 0 {
+        let table_columns_count = df
+            .width()
+            .checked_sub(metadata_columns.len())
+            .filter(|count| (1..=table_columns.len()).contains(count))
+            .ok_or_else(|| {
+                anyhow!(
+                    "CSV file has {} columns, but the table has {} columns after the DMS columns {:?}",
+                    df.width(),
+                    table_columns.len(),
+                    metadata_columns
+                )
+            })?;
+
+        let column_names = metadata_columns
+            .iter()
+            .chain(table_columns.keys().take(table_columns_count))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        df.set_column_names(column_names)?;
+    }
+
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|column| {
+            match table_columns
+                .get(column.name().as_str())
+                .and_then(|data_type| csv_column_data_type(data_type))
+            {
+                Some(data_type) => cast_csv_column(column, &data_type),
+                None => Ok(column.clone()),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Gets the DataFrame type of a CSV column from its Postgres type.
+///
+/// Types that Postgres parses from text without loss (numerics, dates, json, ...)
+/// are kept as strings.
+fn csv_column_data_type(postgres_data_type: &str) -> Option<DataType> {
+    match postgres_data_type {
+        "smallint" => Some(DataType::Int16),
+        "integer" => Some(DataType::Int32),
+        "bigint" => Some(DataType::Int64),
+        "real" => Some(DataType::Float32),
+        "double precision" => Some(DataType::Float64),
+        "boolean" => Some(DataType::Boolean),
+        _ => None,
+    }
+}
+
+fn cast_csv_column(column: &Column, data_type: &DataType) -> Result<Column> {
+    if *data_type != DataType::Boolean {
+        return Ok(column.strict_cast(data_type)?);
+    }
+
+    let values = column
+        .str()?
+        .into_iter()
+        .map(|value| {
+            value
+                .map(|value| match value.to_lowercase().as_str() {
+                    "t" | "true" | "1" | "y" | "yes" => Ok(true),
+                    "f" | "false" | "0" | "n" | "no" => Ok(false),
+                    _ => Err(anyhow!(
+                        "Invalid boolean value {value} in column {}",
+                        column.name()
+                    )),
+                })
+                .transpose()
+        })
+        .collect::<Result<BooleanChunked>>()?;
+
+    Ok(values.with_name(column.name().clone()).into_column())
 }
 
 #[cfg(test)]
 mod tests {
-    use polars::prelude::DataFrame;
+    use chrono::Utc;
+    use flate2::{Compression, write::GzEncoder};
+    use indexmap::IndexMap;
+    use polars::prelude::*;
+    use std::io::Write;
 
+    use crate::cdc::cdc_semantics::CdcSemanticsValueEnum;
     use crate::dataframe::dataframe_ops::{
-        CreateDataframePayload, DataframeOperator, MockDataframeOperator,
+        CreateDataframePayload, DataframeOperator, DataframeOperatorImpl, MockDataframeOperator,
+        read_dms_csv, sort_by_ingestion_timestamp,
     };
+    use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+    use crate::dataframe::file_format::CsvSettings;
+    use crate::storage::in_memory_store::InMemoryObjectStore;
+
+    fn table_columns() -> IndexMap<String, String> {
+        let mut table_columns = IndexMap::new();
+        table_columns.insert("id".to_string(), "integer".to_string());
+        table_columns.insert("name".to_string(), "text".to_string());
+        table_columns.insert("active".to_string(), "boolean".to_string());
+        table_columns
+    }
+
+    fn metadata_columns() -> Vec<String> {
+        DmsMetadataColumns::default().leading_columns(false, CdcSemanticsValueEnum::Full)
+    }
 
     #[tokio::test]
     async fn test_create_dataframe_from_parquet_file() {
@@ -118,4 +335,175 @@ mod tests {
 
         assert_eq!(df.unwrap().height(), 0);
     }
+
+    #[test]
+    fn test_sort_by_ingestion_timestamp() {
+        let df = df!(
+            "Op" => ["U", "I", "U", "D"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:02", "2024-02-14 10:00:00", "2024-02-14 10:00:01", "2024-02-14 10:00:01"],
+            "id" => [1, 1, 1, 2],
+        )
+        .unwrap();
+
+        let sorted_df = sort_by_ingestion_timestamp(&df, "_dms_ingestion_timestamp").unwrap();
+        let ops = sorted_df
+            .column("Op")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+
+        // Rows with the same timestamp keep their order in the file
+        assert_eq!(ops, vec!["I", "U", "D", "U"]);
+    }
+
+    #[test]
+    fn test_sort_without_ingestion_timestamp() {
+        let df = df!("id" => [2, 1]).unwrap();
+
+        let sorted_df = sort_by_ingestion_timestamp(&df, "_dms_ingestion_timestamp").unwrap();
+        assert!(sorted_df.equals(&df));
+    }
+
+    #[test]
+    fn test_read_headerless_dms_csv_with_metadata_columns() {
+        let bytes = b"I,2024-02-14 10:00:00,1,a,t\nD,2024-02-14 10:00:01,2,,f\n".to_vec();
+
+        let df = read_dms_csv(
+            bytes,
+            &CsvSettings::default(),
+            &table_columns(),
+            &metadata_columns(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            df.get_column_names_str(),
+            vec!["Op", "_dms_ingestion_timestamp", "id", "name", "active"]
+        );
+        assert_eq!(df.column("id").unwrap().dtype(), &DataType::Int32);
+        assert_eq!(df.column("active").unwrap().dtype(), &DataType::Boolean);
+        assert_eq!(df.column("name").unwrap().null_count(), 1);
+    }
+
+    #[test]
+    fn test_read_headerless_dms_csv_of_inserts_only() {
+        // CdcInsertsOnly files have no operation column without IncludeOpForFullLoad
+        let metadata_columns = DmsMetadataColumns {
+            include_op_for_full_load: false,
+            ..DmsMetadataColumns::default()
+        }
+        .leading_columns(false, CdcSemanticsValueEnum::InsertsOnly);
+        let bytes = b"2024-02-14 10:00:00,1,a,t\n".to_vec();
+
+        let df = read_dms_csv(
+            bytes,
+            &CsvSettings::default(),
+            &table_columns(),
+            &metadata_columns,
+        )
+        .unwrap();
+
+        assert_eq!(
+            df.get_column_names_str(),
+            vec!["_dms_ingestion_timestamp", "id", "name", "active"]
+        );
+        assert_eq!(df.column("id").unwrap().dtype(), &DataType::Int32);
+    }
+
+    #[test]
+    fn test_read_headerless_dms_csv_written_before_columns_were_added() {
+        let bytes = b"I,2024-02-14 10:00:00,1,a\n".to_vec();
+
+        let df = read_dms_csv(
+            bytes,
+            &CsvSettings::default(),
+            &table_columns(),
+            &metadata_columns(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            df.get_column_names_str(),
+            vec!["Op", "_dms_ingestion_timestamp", "id", "name"]
+        );
+    }
+
+    #[test]
+    fn test_read_dms_csv_with_header_and_null_value() {
+        let bytes = b"id|name|active\r\n1|NULL|true\r\n".to_vec();
+        let csv_settings = CsvSettings {
+            delimiter: b'|',
+            null_value: "NULL".to_string(),
+            has_header: true,
+            ..CsvSettings::default()
+        };
+
+        let df = read_dms_csv(bytes, &csv_settings, &table_columns(), &metadata_columns()).unwrap();
+
+        assert_eq!(df.get_column_names_str(), vec!["id", "name", "active"]);
+        assert_eq!(df.column("name").unwrap().null_count(), 1);
+        assert_eq!(
+            df.column("active").unwrap().get(0).unwrap(),
+            AnyValue::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn test_read_dms_csv_with_unexpected_columns() {
+        let bytes = b"1,a\n".to_vec();
+
+        let result = read_dms_csv(
+            bytes,
+            &CsvSettings::default(),
+            &table_columns(),
+            &metadata_columns(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_dataframe_from_gzip_csv_file() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"I,2024-02-14 10:00:00,1,a,f\n").unwrap();
+
+        let object_store = InMemoryObjectStore::new();
+        object_store.put_object(
+            "bucket_name",
+            "key.csv.gz",
+            encoder.finish().unwrap(),
+            Utc::now(),
+        );
+
+        let dataframe_operator = DataframeOperatorImpl::new(&object_store);
+        let create_dataframe_payload = CreateDataframePayload {
+            bucket_name: "bucket_name".to_string(),
+            key: "key.csv.gz".to_string(),
+            database_name: "database_name".to_string(),
+            schema_name: "schema_name".to_string(),
+            table_name: "table_name".to_string(),
+        };
+        let csv_settings = CsvSettings {
+            gzip: true,
+            ..CsvSettings::default()
+        };
+
+        let df = dataframe_operator
+            .create_dataframe_from_csv_file(
+                &create_dataframe_payload,
+                &csv_settings,
+                &table_columns(),
+                &metadata_columns(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(df.height(), 1);
+        assert_eq!(
+            df.get_column_names_str(),
+            vec!["Op", "_dms_ingestion_timestamp", "id", "name", "active"]
+        );
+    }
 }
@@ -0,0 +1,643 @@
+use anyhow::Result;
+use polars::prelude::*;
+
+use crate::cdc::cdc_semantics::CdcSemanticsValueEnum;
+
+/// The column of a change set with the normalized operation (I, U or D) of each row.
+pub const CHANGE_OP_COLUMN: &str = "_dms_cdc_op";
+
+/// The column of a change set with the position of each row in the order of the changes.
+pub const CHANGE_SEQUENCE_COLUMN: &str = "_dms_cdc_seq";
+
+/// The column of a change set with the name of the file each row was read from.
+pub const CHANGE_FILE_COLUMN: &str = "_dms_cdc_file";
+
+/// The temporary column that marks the updates that are split into a delete and an update.
+const SPLIT_COLUMN: &str = "_dms_cdc_split";
+
+/// The temporary column with the position of each row before the updates are split.
+const SPLIT_ORDER_COLUMN: &str = "_dms_cdc_split_order";
+
+/// Whether a column is one of the columns a change set adds to the table columns.
+pub fn is_change_column(column_name: &str) -> bool {
+    [CHANGE_OP_COLUMN, CHANGE_SEQUENCE_COLUMN, CHANGE_FILE_COLUMN].contains(&column_name)
+}
+
+/// Represents the columns that DMS adds to the table columns in its output files.
+///
+/// The names match the DMS defaults of this operator, and can be changed to match
+/// custom DMS settings (e.g. `TimestampColumnName`). Both columns are optional in the
+/// files, so they are only dropped or used when they are present. An empty timestamp
+/// column name means that the files have no timestamp column, which headerless CSV
+/// files need to be read.
+///
+/// The files may also have before-image columns, i.e. the values of the table columns
+/// before an update, named by prefixing the table column (e.g. `BI_id` for `id`).
+/// They are only used when a prefix is set, to apply updates of the primary key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmsMetadataColumns {
+    /// The name of the column with the operation (I, U or D) of each row.
+    pub op_column: String,
+    /// The name of the column with the commit timestamp of each row.
+    pub timestamp_column: String,
+    /// Whether the LOAD files have the operation column, i.e. the DMS `IncludeOpForFullLoad` setting.
+    pub include_op_for_full_load: bool,
+    /// The prefix of the before-image columns, i.e. the DMS `BeforeImageSettings` of the task.
+    pub before_image_prefix: Option<String>,
+}
+
+impl Default for DmsMetadataColumns {
+    fn default() -> Self {
+        Self {
+            op_column: "Op".to_string(),
+            timestamp_column: "_dms_ingestion_timestamp".to_string(),
+            include_op_for_full_load: true,
+            before_image_prefix: None,
+        }
+    }
+}
+
+impl DmsMetadataColumns {
+    /// Whether a column of a file is added by DMS, rather than one of the table columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `column_name` - The name of the column.
+    /// * `table_columns` - The names of the columns of the table.
+    pub fn is_metadata_column(&self, column_name: &str, table_columns: &[String]) -> bool {
+        column_name == self.op_column
+            || column_name == self.timestamp_column
+            || self.is_before_image_column(column_name, table_columns)
+    }
+
+    /// Whether a column has the before-image of a table column, i.e. it is named by
+    /// prefixing a table column and is not a table column itself (e.g. a `BI_total` column).
+    ///
+    /// # Arguments
+    ///
+    /// * `column_name` - The name of the column.
+    /// * `table_columns` - The names of the columns of the table.
+    pub fn is_before_image_column(&self, column_name: &str, table_columns: &[String]) -> bool {
+        let is_table_column = |name: &str| table_columns.iter().any(|column| column == name);
+        self.before_image_prefix
+            .as_ref()
+            .and_then(|prefix| column_name.strip_prefix(prefix.as_str()))
+            .is_some_and(|column| is_table_column(column) && !is_table_column(column_name))
+    }
+
+    /// Gets the before-image columns of the primary key, if a DataFrame has all of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - The CDC DataFrame.
+    /// * `primary_keys` - The primary key columns of the table.
+    ///
+    /// # Returns
+    ///
+    /// The names of the before-image columns, in the order of the primary key columns.
+    pub fn before_image_keys(
+        &self,
+        df: &DataFrame,
+        primary_keys: &[String],
+    ) -> Option<Vec<String>> {
+        let prefix = self.before_image_prefix.as_ref()?;
+        if primary_keys.is_empty() {
+            return None;
+        }
+
+        primary_keys
+            .iter()
+            .map(|key| format!("{prefix}{key}"))
+            .map(|column_name| df.column(&column_name).is_ok().then_some(column_name))
+            .collect()
+    }
+
+    /// Splits the updates that changed the primary key of a row into a delete of the
+    /// old key, read from the before-image columns, followed by the update of the new key.
+    ///
+    /// The before-images of the split updates are cleared, so splitting again has no effect.
+    /// The DataFrame is returned as it is if it has no operation or before-image columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - The CDC DataFrame, ordered by ingestion timestamp.
+    /// * `primary_keys` - The primary key columns of the table.
+    pub fn split_key_changes(&self, df: &DataFrame, primary_keys: &[String]) -> Result<DataFrame> {
+        self.split_updates_of(df, primary_keys, true)
+    }
+
+    /// Splits every update of a table without a key into a delete of the row before the
+    /// update, read from the before-image columns, followed by the update of the row.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - The CDC DataFrame, ordered by ingestion timestamp.
+    /// * `columns` - The columns the rows are matched on.
+    pub fn split_updates(&self, df: &DataFrame, columns: &[String]) -> Result<DataFrame> {
+        self.split_updates_of(df, columns, false)
+    }
+
+    /// Splits the updates with before-images of the given columns, either all of them
+    /// or only those that changed the value of a column.
+    fn split_updates_of(
+        &self,
+        df: &DataFrame,
+        keys: &[String],
+        only_changed_keys: bool,
+    ) -> Result<DataFrame> {
+        let Some(before_image_keys) = self.before_image_keys(df, keys) else {
+            return Ok(df.clone());
+        };
+        if df.column(&self.op_column).is_err() {
+            return Ok(df.clone());
+        }
+
+        let key_types = keys
+            .iter()
+            .map(|key| Ok(df.column(key)?.dtype().clone()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let has_split = keys
+            .iter()
+            .zip(&before_image_keys)
+            .zip(&key_types)
+            .map(|((key, before_image_key), key_type)| {
+                let before_image = col(before_image_key.as_str()).cast(key_type.clone());
+                if only_changed_keys {
+                    before_image
+                        .clone()
+                        .is_not_null()
+                        .and(before_image.neq(col(key.as_str())))
+                } else {
+                    before_image.is_not_null()
+                }
+            })
+            .reduce(|split, has_split| split.or(has_split))
+            .unwrap();
+
+        let changes = df
+            .clone()
+            .lazy()
+            .with_row_index(SPLIT_ORDER_COLUMN, None)
+            .with_column(
+                col(self.op_column.as_str())
+                    .eq(lit("U"))
+                    .and(has_split)
+                    .alias(SPLIT_COLUMN),
+            );
+
+        let mut old_keys = keys
+            .iter()
+            .zip(&before_image_keys)
+            .zip(&key_types)
+            .map(|((key, before_image_key), key_type)| {
+                col(before_image_key.as_str())
+                    .cast(key_type.clone())
+                    .alias(key.as_str())
+            })
+            .collect::<Vec<_>>();
+        old_keys.push(lit("D").alias(self.op_column.as_str()));
+        let deletes = changes
+            .clone()
+            .filter(col(SPLIT_COLUMN))
+            .with_columns(old_keys);
+
+        let cleared_before_images = before_image_keys
+            .iter()
+            .map(|before_image_key| {
+                Ok(when(col(SPLIT_COLUMN))
+                    .then(lit(NULL).cast(df.column(before_image_key)?.dtype().clone()))
+                    .otherwise(col(before_image_key.as_str()))
+                    .alias(before_image_key.as_str()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let updates = changes.with_columns(cleared_before_images);
+
+        // Each delete precedes the update it was split from
+        let df = concat([deletes, updates], UnionArgs::default())?
+            .sort(
+                [SPLIT_ORDER_COLUMN],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .drop([SPLIT_ORDER_COLUMN, SPLIT_COLUMN])
+            .collect()?;
+
+        Ok(df)
+    }
+
+    /// Whether the files have the operation column.
+    ///
+    /// DMS only writes it in LOAD files with `IncludeOpForFullLoad`, and the same
+    /// setting decides whether the CDC files of `CdcInsertsOnly` have it.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_load_file` - Whether the columns are of a LOAD file
+    /// * `cdc_semantics` - The DMS CDC output mode of the CDC files
+    pub fn has_op_column(&self, is_load_file: bool, cdc_semantics: CdcSemanticsValueEnum) -> bool {
+        self.include_op_for_full_load || (!is_load_file && !cdc_semantics.keeps_history())
+    }
+
+    /// Whether the files have the timestamp column, i.e. the DMS `TimestampColumnName` is set.
+    pub fn has_timestamp_column(&self) -> bool {
+        !self.timestamp_column.is_empty()
+    }
+
+    /// Gets the metadata columns that precede the table columns, in the order DMS writes them.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_load_file` - Whether the columns are of a LOAD file
+    /// * `cdc_semantics` - The DMS CDC output mode of the CDC files
+    ///
+    /// # Returns
+    ///
+    /// The names of the metadata columns.
+    pub fn leading_columns(
+        &self,
+        is_load_file: bool,
+        cdc_semantics: CdcSemanticsValueEnum,
+    ) -> Vec<String> {
+        let mut leading_columns = Vec::new();
+        if self.has_op_column(is_load_file, cdc_semantics) {
+            leading_columns.push(self.op_column.clone());
+        }
+        if self.has_timestamp_column() {
+            leading_columns.push(self.timestamp_column.clone());
+        }
+        leading_columns
+    }
+
+    /// Drops the metadata columns that are present in a DataFrame.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - The DataFrame of a file.
+    /// * `table_columns` - The names of the columns of the table, which are kept.
+    pub fn drop_from(&self, df: &DataFrame, table_columns: &[String]) -> DataFrame {
+        let mut df = self.drop_op_from(df, table_columns);
+        let _ = df.drop_in_place(&self.timestamp_column);
+        df
+    }
+
+    /// Drops the operation and before-image columns if they are present in a DataFrame,
+    /// keeping the timestamp column.
+    pub fn drop_op_from(&self, df: &DataFrame, table_columns: &[String]) -> DataFrame {
+        let mut df = self.drop_before_images_from(df, table_columns);
+        let _ = df.drop_in_place(&self.op_column);
+        df
+    }
+
+    /// Drops the before-image columns if they are present in a DataFrame,
+    /// keeping the operation and timestamp columns.
+    pub fn drop_before_images_from(&self, df: &DataFrame, table_columns: &[String]) -> DataFrame {
+        let mut df = df.clone();
+        let before_image_columns = df
+            .get_column_names_str()
+            .into_iter()
+            .filter(|column_name| self.is_before_image_column(column_name, table_columns))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        for column_name in before_image_columns {
+            let _ = df.drop_in_place(&column_name);
+        }
+        df
+    }
+
+    /// Builds the change set of a CDC DataFrame, already ordered by ingestion timestamp.
+    ///
+    /// The change set has the table columns, followed by the operation of each row
+    /// reduced to a single I, U or D, its position in the file, and the file it was
+    /// read from if the DataFrame has a file column.
+    /// Rows without an operation column are inserts.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - The CDC DataFrame.
+    /// * `table_columns` - The names of the columns of the table.
+    /// * `keep_timestamp` - Whether the timestamp column is kept as a table column.
+    pub fn changes_from(
+        &self,
+        df: &DataFrame,
+        table_columns: &[String],
+        keep_timestamp: bool,
+    ) -> Result<DataFrame> {
+        let ops = match df.column(&self.op_column) {
+            Ok(op_column) => op_column
+                .cast(&DataType::String)?
+                .str()?
+                .into_iter()
+                .map(normalized_op)
+                .collect::<Vec<_>>(),
+            Err(_) => vec!["I"; df.height()],
+        };
+        let sequence = (0..df.height() as i64).collect::<Vec<_>>();
+
+        let mut changes = if keep_timestamp {
+            self.drop_op_from(df, table_columns)
+        } else {
+            self.drop_from(df, table_columns)
+        };
+        let files = match changes.drop_in_place(CHANGE_FILE_COLUMN) {
+            Ok(files) => files.cast(&DataType::String)?,
+            Err(_) => Column::full_null(CHANGE_FILE_COLUMN.into(), df.height(), &DataType::String),
+        };
+        changes.with_column(Column::new(CHANGE_OP_COLUMN.into(), ops))?;
+        changes.with_column(Column::new(CHANGE_SEQUENCE_COLUMN.into(), sequence))?;
+        changes.with_column(files)?;
+
+        Ok(changes)
+    }
+}
+
+fn normalized_op(op: Option<&str>) -> &'static str {
+    match op {
+        Some(op) if op.contains('D') => "D",
+        Some(op) if op.contains('U') => "U",
+        _ => "I",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_from_present_columns_only() {
+        let metadata_columns = DmsMetadataColumns {
+            timestamp_column: "commit_ts".to_string(),
+            ..DmsMetadataColumns::default()
+        };
+        let df = df!(
+            "commit_ts" => ["2024-02-14 10:00:00"],
+            "id" => [1],
+        )
+        .unwrap();
+
+        let df = metadata_columns.drop_from(&df, &["id".to_string()]);
+        assert_eq!(df.get_column_names_str(), vec!["id"]);
+    }
+
+    #[test]
+    fn test_leading_columns() {
+        let metadata_columns = DmsMetadataColumns {
+            include_op_for_full_load: false,
+            ..DmsMetadataColumns::default()
+        };
+
+        assert_eq!(
+            metadata_columns.leading_columns(true, CdcSemanticsValueEnum::Full),
+            vec!["_dms_ingestion_timestamp"]
+        );
+        assert_eq!(
+            metadata_columns.leading_columns(false, CdcSemanticsValueEnum::Full),
+            vec!["Op", "_dms_ingestion_timestamp"]
+        );
+        assert_eq!(
+            metadata_columns.leading_columns(false, CdcSemanticsValueEnum::InsertsAndUpdates),
+            vec!["Op", "_dms_ingestion_timestamp"]
+        );
+
+        // DMS writes the operation of CdcInsertsOnly files only with IncludeOpForFullLoad
+        assert_eq!(
+            metadata_columns.leading_columns(false, CdcSemanticsValueEnum::InsertsOnly),
+            vec!["_dms_ingestion_timestamp"]
+        );
+        assert_eq!(
+            DmsMetadataColumns::default()
+                .leading_columns(false, CdcSemanticsValueEnum::InsertsOnly),
+            vec!["Op", "_dms_ingestion_timestamp"]
+        );
+
+        let metadata_columns = DmsMetadataColumns {
+            timestamp_column: String::new(),
+            ..DmsMetadataColumns::default()
+        };
+        assert_eq!(
+            metadata_columns.leading_columns(true, CdcSemanticsValueEnum::Full),
+            vec!["Op"]
+        );
+    }
+
+    #[test]
+    fn test_changes_from() {
+        let df = df!(
+            "Op" => [Some("I"), Some("U"), Some("D"), None],
+            "_dms_ingestion_timestamp" => ["t1", "t2", "t3", "t4"],
+            "id" => [1, 1, 2, 3],
+        )
+        .unwrap();
+
+        let changes = DmsMetadataColumns::default()
+            .changes_from(&df, &["id".to_string()], false)
+            .unwrap();
+        assert_eq!(
+            changes.get_column_names_str(),
+            vec![
+                "id",
+                CHANGE_OP_COLUMN,
+                CHANGE_SEQUENCE_COLUMN,
+                CHANGE_FILE_COLUMN
+            ]
+        );
+
+        let ops = changes
+            .column(CHANGE_OP_COLUMN)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["I", "U", "D", "I"]);
+
+        let sequence = changes
+            .column(CHANGE_SEQUENCE_COLUMN)
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(sequence, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_changes_from_without_op_column() {
+        let df = df!("id" => [1, 2]).unwrap();
+
+        let changes = DmsMetadataColumns::default()
+            .changes_from(&df, &["id".to_string()], false)
+            .unwrap();
+        let ops = changes
+            .column(CHANGE_OP_COLUMN)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["I", "I"]);
+    }
+
+    #[test]
+    fn test_changes_from_keeping_timestamp_and_file() {
+        let df = df!(
+            CHANGE_FILE_COLUMN => ["20240214-100000000.parquet"],
+            "Op" => ["U"],
+            "_dms_ingestion_timestamp" => ["2024-02-14 10:00:00"],
+            "id" => [1],
+        )
+        .unwrap();
+
+        let changes = DmsMetadataColumns::default()
+            .changes_from(&df, &["id".to_string()], true)
+            .unwrap();
+        assert_eq!(
+            changes.get_column_names_str(),
+            vec![
+                "_dms_ingestion_timestamp",
+                "id",
+                CHANGE_OP_COLUMN,
+                CHANGE_SEQUENCE_COLUMN,
+                CHANGE_FILE_COLUMN
+            ]
+        );
+        assert!(is_change_column(CHANGE_FILE_COLUMN));
+        assert!(!is_change_column("_dms_ingestion_timestamp"));
+    }
+
+    #[test]
+    fn test_split_key_changes() {
+        let metadata_columns = DmsMetadataColumns {
+            before_image_prefix: Some("BI_".to_string()),
+            ..DmsMetadataColumns::default()
+        };
+        let df = df!(
+            "Op" => ["U", "U", "I"],
+            "BI_id" => [Some(1i64), Some(2), None],
+            "id" => [1i32, 3, 4],
+            "name" => ["a", "b", "c"],
+        )
+        .unwrap();
+
+        let df = metadata_columns
+            .split_key_changes(&df, &["id".to_string()])
+            .unwrap();
+        let ops = df
+            .column("Op")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        let ids = df
+            .column("id")
+            .unwrap()
+            .i32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["U", "D", "U", "I"]);
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        // The split updates have no before-images left
+        let split_again = metadata_columns
+            .split_key_changes(&df, &["id".to_string()])
+            .unwrap();
+        assert!(split_again.equals_missing(&df));
+
+        assert_eq!(
+            metadata_columns
+                .drop_from(&df, &["id".to_string(), "name".to_string()])
+                .get_column_names_str(),
+            vec!["id", "name"]
+        );
+    }
+
+    #[test]
+    fn test_table_columns_with_the_before_image_prefix_are_kept() {
+        let metadata_columns = DmsMetadataColumns {
+            before_image_prefix: Some("BI_".to_string()),
+            ..DmsMetadataColumns::default()
+        };
+        let table_columns = vec!["id".to_string(), "BI_total".to_string()];
+        let df = df!(
+            "Op" => ["U"],
+            "BI_id" => [1],
+            "BI_BI_total" => [10],
+            "id" => [1],
+            "BI_total" => [20],
+        )
+        .unwrap();
+
+        assert!(metadata_columns.is_before_image_column("BI_id", &table_columns));
+        assert!(metadata_columns.is_before_image_column("BI_BI_total", &table_columns));
+        assert!(!metadata_columns.is_before_image_column("BI_total", &table_columns));
+        assert!(!metadata_columns.is_metadata_column("BI_total", &table_columns));
+        // The before-images are only of the table columns
+        assert!(!metadata_columns.is_before_image_column("BI_note", &table_columns));
+
+        assert_eq!(
+            metadata_columns
+                .drop_from(&df, &table_columns)
+                .get_column_names_str(),
+            vec!["id", "BI_total"]
+        );
+    }
+
+    #[test]
+    fn test_before_image_keys() {
+        let df = df!(
+            "BI_id" => [1],
+            "id" => [1],
+            "tenant" => [1],
+        )
+        .unwrap();
+        let metadata_columns = DmsMetadataColumns {
+            before_image_prefix: Some("BI_".to_string()),
+            ..DmsMetadataColumns::default()
+        };
+
+        assert_eq!(
+            metadata_columns.before_image_keys(&df, &["id".to_string()]),
+            Some(vec!["BI_id".to_string()])
+        );
+        assert_eq!(
+            metadata_columns.before_image_keys(&df, &["id".to_string(), "tenant".to_string()]),
+            None
+        );
+        assert_eq!(
+            DmsMetadataColumns::default().before_image_keys(&df, &["id".to_string()]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_split_updates() {
+        let metadata_columns = DmsMetadataColumns {
+            before_image_prefix: Some("BI_".to_string()),
+            ..DmsMetadataColumns::default()
+        };
+        let df = df!(
+            "Op" => ["U", "U", "D"],
+            "BI_id" => [Some(1), Some(2), None],
+            "BI_name" => [Some("a"), None, None],
+            "id" => [1, 2, 3],
+            "name" => [Some("b"), Some("c"), Some("d")],
+        )
+        .unwrap();
+
+        let columns = ["id".to_string(), "name".to_string()];
+        let df = metadata_columns
+            .split_updates(&df, &columns)
+            .unwrap()
+            .select(["id", "name", "Op"])
+            .unwrap();
+
+        let expected = df!(
+            "id" => [1, 1, 2, 2, 3],
+            "name" => [Some("a"), Some("b"), None, Some("c"), Some("d")],
+            "Op" => ["D", "U", "D", "U", "D"],
+        )
+        .unwrap();
+        assert!(df.equals_missing(&expected));
+    }
+}
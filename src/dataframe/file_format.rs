@@ -0,0 +1,94 @@
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+/// Represents the format of the files that DMS writes to S3.
+///
+/// The format can be one of the following:
+///
+/// * Parquet - Parquet files.
+/// * Csv - CSV files, optionally compressed with gzip.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum FileFormatValueEnum {
+    #[default]
+    Parquet,
+    Csv,
+}
+
+impl Display for FileFormatValueEnum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FileFormatValueEnum::Parquet => write!(f, "Parquet"),
+            FileFormatValueEnum::Csv => write!(f, "Csv"),
+        }
+    }
+}
+
+/// Represents the DMS settings of CSV output files.
+///
+/// The defaults match the DMS defaults: comma separated columns,
+/// newline separated rows, empty null values, no header and no compression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvSettings {
+    /// The DMS `CsvDelimiter` setting.
+    pub delimiter: u8,
+    /// The DMS `CsvRowDelimiter` setting.
+    pub row_delimiter: u8,
+    /// The DMS `CsvNullValue` setting.
+    pub null_value: String,
+    /// Whether the files have a header row, i.e. the DMS `AddColumnName` setting.
+    pub has_header: bool,
+    /// Whether the files are gzip compressed, i.e. the DMS `CompressionType` is `GZIP`.
+    pub gzip: bool,
+}
+
+impl Default for CsvSettings {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            row_delimiter: b'\n',
+            null_value: String::new(),
+            has_header: false,
+            gzip: false,
+        }
+    }
+}
+
+impl CsvSettings {
+    /// Parses a DMS delimiter setting into a single byte.
+    ///
+    /// Escaped characters like `\t` and `\n` are accepted, and a `\r\n` row delimiter
+    /// is read as `\n`, since the trailing `\r` is dropped by the CSV reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `delimiter` - The delimiter setting
+    ///
+    /// # Returns
+    ///
+    /// The delimiter byte, or None if the delimiter is not a single byte.
+    pub fn parse_delimiter(delimiter: &str) -> Option<u8> {
+        match delimiter {
+            "\\t" => Some(b'\t'),
+            "\\n" | "\\r\\n" | "\r\n" => Some(b'\n'),
+            "\\r" => Some(b'\r'),
+            _ if delimiter.len() == 1 => delimiter.bytes().next(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(CsvSettings::parse_delimiter(","), Some(b','));
+        assert_eq!(CsvSettings::parse_delimiter("|"), Some(b'|'));
+        assert_eq!(CsvSettings::parse_delimiter("\\t"), Some(b'\t'));
+        assert_eq!(CsvSettings::parse_delimiter("\\n"), Some(b'\n'));
+        assert_eq!(CsvSettings::parse_delimiter("\\r\\n"), Some(b'\n'));
+        assert_eq!(CsvSettings::parse_delimiter(""), None);
+        assert_eq!(CsvSettings::parse_delimiter("||"), None);
+    }
+}
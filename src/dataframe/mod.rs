@@ -1 +1,4 @@
+pub mod cdc_compaction;
 pub mod dataframe_ops;
+pub mod dms_metadata_columns;
+pub mod file_format;
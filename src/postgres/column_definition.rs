@@ -0,0 +1,152 @@
+use super::table_query::quote_identifier;
+
+/// Represents how the values of an identity column are generated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdentityGeneration {
+    Always,
+    ByDefault,
+}
+
+impl IdentityGeneration {
+    /// Parses the `attidentity` of a column, empty when it is not an identity column.
+    pub fn from_attidentity(attidentity: &str) -> Option<Self> {
+        match attidentity {
+            "a" => Some(IdentityGeneration::Always),
+            "d" => Some(IdentityGeneration::ByDefault),
+            _ => None,
+        }
+    }
+}
+
+/// Represents a column of a table as it is defined in the catalog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnDefinition {
+    /// The name of the column.
+    pub name: String,
+    /// The exact type of the column, as formatted by `format_type`.
+    pub data_type: String,
+    /// Whether the column is NOT NULL.
+    pub not_null: bool,
+    /// The default expression of the column.
+    pub default: Option<String>,
+    /// How the values of the column are generated, if it is an identity column.
+    pub identity: Option<IdentityGeneration>,
+    /// The expression of the column, if it is a stored generated column.
+    pub generated: Option<String>,
+}
+
+impl ColumnDefinition {
+    /// Creates a nullable column without a default.
+    pub fn new(name: &str, data_type: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            not_null: false,
+            default: None,
+            identity: None,
+            generated: None,
+        }
+    }
+
+    /// Whether values can be written to the column.
+    ///
+    /// Generated columns are computed by the target from the other columns of the row.
+    pub fn is_writable(&self) -> bool {
+        self.generated.is_none()
+    }
+
+    /// Gets the column as it is written in a `CREATE TABLE` statement.
+    ///
+    /// Defaults that take values from a sequence are left out, since the sequence
+    /// only exists in the source and the values always come from the files.
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!("{} {}", quote_identifier(&self.name), self.data_type);
+
+        if let Some(generated) = &self.generated {
+            sql.push_str(&format!(" GENERATED ALWAYS AS ({generated}) STORED"));
+        } else if let Some(identity) = &self.identity {
+            sql.push_str(match identity {
+                IdentityGeneration::Always => " GENERATED ALWAYS AS IDENTITY",
+                IdentityGeneration::ByDefault => " GENERATED BY DEFAULT AS IDENTITY",
+            });
+        } else if let Some(default) = self
+            .default
+            .as_ref()
+            .filter(|default| !default.contains("nextval("))
+        {
+            sql.push_str(&format!(" DEFAULT {default}"));
+        }
+
+        if self.not_null {
+            sql.push_str(" NOT NULL");
+        }
+
+        sql
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_sql() {
+        assert_eq!(
+            ColumnDefinition::new("Name", "character varying(20)").to_sql(),
+            r#""Name" character varying(20)"#
+        );
+
+        let id = ColumnDefinition {
+            not_null: true,
+            identity: Some(IdentityGeneration::Always),
+            ..ColumnDefinition::new("id", "bigint")
+        };
+        assert_eq!(
+            id.to_sql(),
+            r#""id" bigint GENERATED ALWAYS AS IDENTITY NOT NULL"#
+        );
+
+        let total = ColumnDefinition {
+            generated: Some("(price * (quantity)::numeric)".to_string()),
+            ..ColumnDefinition::new("total", "numeric(12,2)")
+        };
+        assert_eq!(
+            total.to_sql(),
+            r#""total" numeric(12,2) GENERATED ALWAYS AS ((price * (quantity)::numeric)) STORED"#
+        );
+        assert!(!total.is_writable());
+
+        let status = ColumnDefinition {
+            not_null: true,
+            default: Some("'new'::text".to_string()),
+            ..ColumnDefinition::new("status", "text")
+        };
+        assert_eq!(
+            status.to_sql(),
+            r#""status" text DEFAULT 'new'::text NOT NULL"#
+        );
+    }
+
+    #[test]
+    fn test_to_sql_skips_sequence_defaults() {
+        let serial = ColumnDefinition {
+            not_null: true,
+            default: Some("nextval('orders_id_seq'::regclass)".to_string()),
+            ..ColumnDefinition::new("id", "integer")
+        };
+        assert_eq!(serial.to_sql(), r#""id" integer NOT NULL"#);
+    }
+
+    #[test]
+    fn test_from_attidentity() {
+        assert_eq!(
+            IdentityGeneration::from_attidentity("a"),
+            Some(IdentityGeneration::Always)
+        );
+        assert_eq!(
+            IdentityGeneration::from_attidentity("d"),
+            Some(IdentityGeneration::ByDefault)
+        );
+        assert_eq!(IdentityGeneration::from_attidentity(""), None);
+    }
+}
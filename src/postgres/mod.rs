@@ -1,11 +1,19 @@
+pub mod column_definition;
 pub mod postgres_config;
+pub mod postgres_copy;
 pub mod postgres_geometry_type;
 pub mod postgres_operator;
 pub mod postgres_operator_impl;
-pub mod postgres_row_struct;
-pub mod table_mode;
+pub mod postgres_value_converter;
+pub mod row_identity;
+pub mod source_table;
+pub mod table_constraint;
+pub mod table_filter;
+pub mod table_index;
 pub mod table_query;
+pub mod user_defined_type;
 
 #[cfg(test)]
 mod postgres_geometry_type_tests;
+mod postgres_operator_impl_tests;
 mod postgres_operator_tests;
@@ -0,0 +1,516 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use polars::prelude::*;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Write};
+use tokio_postgres::types::{IsNull, ToSql, Type, to_sql_checked};
+
+/// The format used to stream a DataFrame into Postgres with `COPY ... FROM STDIN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// Postgres binary format, used when every column has a native encoding.
+    Binary,
+    /// CSV text format, parsed by Postgres with the input function of each column.
+    Csv,
+}
+
+impl CopyFormat {
+    /// Pick the binary format if every DataFrame column can be encoded
+    /// natively for the matching target column type, otherwise fall back to CSV.
+    pub fn for_columns(dtypes: &[DataType], pg_types: &[Type]) -> Self {
+        let all_binary = dtypes.len() == pg_types.len()
+            && dtypes
+                .iter()
+                .zip(pg_types)
+                .all(|(dtype, pg_type)| supports_binary_copy(dtype, pg_type));
+
+        if all_binary {
+            CopyFormat::Binary
+        } else {
+            CopyFormat::Csv
+        }
+    }
+}
+
+impl Display for CopyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CopyFormat::Binary => write!(f, "binary"),
+            CopyFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// Whether values of a DataFrame column can be written in the binary COPY format
+/// for the given target column type without changing their meaning.
+pub fn supports_binary_copy(dtype: &DataType, pg_type: &Type) -> bool {
+    match dtype {
+        DataType::Boolean => *pg_type == Type::BOOL,
+        DataType::Int8 | DataType::UInt8 | DataType::Int16 => {
+            [Type::INT2, Type::INT4, Type::INT8].contains(pg_type)
+        }
+        DataType::UInt16 | DataType::Int32 => [Type::INT4, Type::INT8].contains(pg_type),
+        DataType::UInt32 | DataType::Int64 => *pg_type == Type::INT8,
+        DataType::Float32 => [Type::FLOAT4, Type::FLOAT8].contains(pg_type),
+        DataType::Float64 => *pg_type == Type::FLOAT8,
+        DataType::String => [Type::TEXT, Type::VARCHAR, Type::BPCHAR, Type::NAME].contains(pg_type),
+        DataType::Date => *pg_type == Type::DATE,
+        // Wall times and instants are converted into each other in the timezone of the session
+        DataType::Datetime(_, None) => *pg_type == Type::TIMESTAMP,
+        DataType::Datetime(_, Some(_)) => *pg_type == Type::TIMESTAMPTZ,
+        _ => false,
+    }
+}
+
+/// The Postgres type of a column created for the values of a DataFrame column,
+/// e.g. a column that appeared in the files of a table after the table was created.
+pub fn postgres_type_for(dtype: &DataType) -> String {
+    match dtype {
+        DataType::Boolean => "boolean".to_string(),
+        DataType::Int8 | DataType::UInt8 | DataType::Int16 => "smallint".to_string(),
+        DataType::UInt16 | DataType::Int32 => "integer".to_string(),
+        DataType::UInt32 | DataType::Int64 => "bigint".to_string(),
+        DataType::UInt64 => "numeric(20)".to_string(),
+        DataType::Float32 => "real".to_string(),
+        DataType::Float64 => "double precision".to_string(),
+        DataType::Decimal(Some(precision), Some(scale)) => format!("numeric({precision},{scale})"),
+        DataType::Decimal(_, _) => "numeric".to_string(),
+        DataType::Binary => "bytea".to_string(),
+        DataType::Date => "date".to_string(),
+        DataType::Time => "time".to_string(),
+        DataType::Datetime(_, None) => "timestamp".to_string(),
+        DataType::Datetime(_, Some(_)) => "timestamptz".to_string(),
+        DataType::List(inner) => format!("{}[]", postgres_type_for(inner)),
+        _ => "text".to_string(),
+    }
+}
+
+/// A single value written with the binary COPY format.
+#[derive(Debug)]
+pub enum CopyValue<'a> {
+    Null,
+    Bool(bool),
+    Int2(i16),
+    Int4(i32),
+    Int8(i64),
+    Float4(f32),
+    Float8(f64),
+    Text(Cow<'a, str>),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+}
+
+impl<'a> CopyValue<'a> {
+    /// Convert a DataFrame value into the encoding of the target column type.
+    ///
+    /// Only called for columns accepted by [`supports_binary_copy`].
+    pub fn new(value: AnyValue<'a>, pg_type: &Type) -> Result<Self> {
+        let copy_value = match value {
+            AnyValue::Null => CopyValue::Null,
+            AnyValue::Boolean(v) => CopyValue::Bool(v),
+            AnyValue::Float32(v) if *pg_type == Type::FLOAT4 => CopyValue::Float4(v),
+            AnyValue::Float32(v) => CopyValue::Float8(v as f64),
+            AnyValue::Float64(v) => CopyValue::Float8(v),
+            AnyValue::String(v) => CopyValue::Text(strip_nul(v)),
+            AnyValue::Date(days) => CopyValue::Date(date_from_days(days)?),
+            AnyValue::Datetime(v, unit, _) => {
+                let datetime = datetime_from_timestamp(v, unit)?;
+                if *pg_type == Type::TIMESTAMPTZ {
+                    CopyValue::TimestampTz(datetime)
+                } else {
+                    CopyValue::Timestamp(datetime.naive_utc())
+                }
+            }
+            ref v if v.is_integer() => {
+                let integer = v
+                    .extract::<i64>()
+                    .ok_or_else(|| anyhow!("Could not read integer value {v}"))?;
+                match *pg_type {
+                    Type::INT2 => CopyValue::Int2(i16::try_from(integer)?),
+                    Type::INT4 => CopyValue::Int4(i32::try_from(integer)?),
+                    _ => CopyValue::Int8(integer),
+                }
+            }
+            v => {
+                return Err(anyhow!(
+                    "Value {v} of type {} can not be copied as {pg_type}",
+                    v.dtype()
+                ));
+            }
+        };
+
+        Ok(copy_value)
+    }
+}
+
+impl ToSql for CopyValue<'_> {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut bytes::BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match self {
+            CopyValue::Null => Ok(IsNull::Yes),
+            CopyValue::Bool(v) => v.to_sql(ty, out),
+            CopyValue::Int2(v) => v.to_sql(ty, out),
+            CopyValue::Int4(v) => v.to_sql(ty, out),
+            CopyValue::Int8(v) => v.to_sql(ty, out),
+            CopyValue::Float4(v) => v.to_sql(ty, out),
+            CopyValue::Float8(v) => v.to_sql(ty, out),
+            CopyValue::Text(v) => v.as_ref().to_sql(ty, out),
+            CopyValue::Date(v) => v.to_sql(ty, out),
+            CopyValue::Timestamp(v) => v.to_sql(ty, out),
+            CopyValue::TimestampTz(v) => v.to_sql(ty, out),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        // The column types are checked by `supports_binary_copy`
+        // before any value is converted
+        true
+    }
+
+    to_sql_checked!();
+}
+
+/// Append one row in the CSV COPY format to the buffer.
+///
+/// Nulls are written as unquoted empty fields and every other text value is quoted,
+/// so that empty strings and nulls stay distinct.
+pub fn write_csv_row(buf: &mut Vec<u8>, row: &[AnyValue]) {
+    let fields = row.iter().map(copy_text_value).collect::<Vec<_>>();
+    write_csv_fields(buf, &fields);
+}
+
+/// Append one row of values already rendered as text, or nulls, in the CSV COPY format.
+pub fn write_csv_fields(buf: &mut Vec<u8>, fields: &[Option<String>]) {
+    for (idx, field) in fields.iter().enumerate() {
+        if idx > 0 {
+            buf.push(b',');
+        }
+        if let Some(text) = field {
+            buf.push(b'"');
+            buf.extend_from_slice(text.replace('"', "\"\"").as_bytes());
+            buf.push(b'"');
+        }
+    }
+    buf.push(b'\n');
+}
+
+/// Render a value in the text representation Postgres accepts as column input.
+///
+/// Returns `None` for nulls.
+pub fn copy_text_value(value: &AnyValue) -> Option<String> {
+    let text = match value {
+        AnyValue::Null => return None,
+        AnyValue::String(_) | AnyValue::StringOwned(_) => {
+            strip_nul(&value.str_value()).into_owned()
+        }
+        AnyValue::Boolean(v) => if *v { "t" } else { "f" }.to_string(),
+        // A real keeps its own shortest digits, which its widening to f64 would lose
+        AnyValue::Float32(v) if v.is_finite() => v.to_string(),
+        AnyValue::Float32(v) => format_float(*v as f64),
+        AnyValue::Float64(v) => format_float(*v),
+        AnyValue::Decimal(integer, scale) => format_decimal(*integer, *scale),
+        AnyValue::Date(days) => match date_from_days(*days) {
+            Ok(date) => date.to_string(),
+            Err(_) => value.to_string(),
+        },
+        AnyValue::DatetimeOwned(v, unit, tz) => {
+            return copy_text_value(&AnyValue::Datetime(*v, *unit, tz.as_deref()));
+        }
+        AnyValue::Datetime(v, unit, tz) => match datetime_from_timestamp(*v, *unit) {
+            Ok(datetime) if tz.is_some() => datetime.to_rfc3339(),
+            Ok(datetime) => datetime
+                .naive_utc()
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string(),
+            Err(_) => value.to_string(),
+        },
+        AnyValue::List(series) => {
+            let elements = series
+                .iter()
+                .map(|element| match copy_text_value(&element) {
+                    Some(text) => {
+                        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+                    }
+                    None => "NULL".to_string(),
+                })
+                .collect::<Vec<_>>();
+            format!("{{{}}}", elements.join(","))
+        }
+        AnyValue::Time(nanos) => match time_from_nanos(*nanos) {
+            Ok(time) => time.format("%H:%M:%S%.6f").to_string(),
+            Err(_) => value.to_string(),
+        },
+        AnyValue::Duration(v, unit) => format_duration(*v, *unit),
+        AnyValue::Binary(bytes) => format!("\\x{}", hex_encode(bytes)),
+        AnyValue::BinaryOwned(bytes) => format!("\\x{}", hex_encode(bytes)),
+        v => v.to_string(),
+    };
+
+    Some(text)
+}
+
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() && value.is_sign_positive() {
+        "Infinity".to_string()
+    } else if value.is_infinite() {
+        "-Infinity".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format a duration as the seconds of an interval, to the microsecond Postgres keeps.
+fn format_duration(value: i64, unit: TimeUnit) -> String {
+    let micros = match unit {
+        TimeUnit::Nanoseconds => value as i128 / 1_000,
+        TimeUnit::Microseconds => value as i128,
+        TimeUnit::Milliseconds => value as i128 * 1_000,
+    };
+    let sign = if micros < 0 { "-" } else { "" };
+    let micros = micros.unsigned_abs();
+    format!(
+        "{sign}{}.{:06} seconds",
+        micros / 1_000_000,
+        micros % 1_000_000
+    )
+}
+
+/// Remove the NUL characters of a text value, which Postgres does not store.
+pub fn strip_nul(value: &str) -> Cow<'_, str> {
+    if value.contains('\0') {
+        Cow::Owned(value.replace('\0', ""))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Format a decimal stored as an integer with a scale without losing precision.
+pub fn format_decimal(integer: i128, scale: usize) -> String {
+    let digits = integer.unsigned_abs().to_string();
+    let sign = if integer < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{sign}{digits}");
+    }
+
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+    format!("{sign}{int_part}.{frac_part}")
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn date_from_days(days: i32) -> Result<NaiveDate> {
+    NaiveDate::from_ymd_opt(1970, 1, 1)
+        .and_then(|epoch| epoch.checked_add_signed(chrono::Duration::days(days as i64)))
+        .ok_or_else(|| anyhow!("Date out of range: {days} days since epoch"))
+}
+
+fn time_from_nanos(nanos: i64) -> Result<NaiveTime> {
+    let seconds = u32::try_from(nanos.div_euclid(1_000_000_000))?;
+    let nanos = nanos.rem_euclid(1_000_000_000) as u32;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos)
+        .ok_or_else(|| anyhow!("Time out of range: {seconds} seconds since midnight"))
+}
+
+pub fn datetime_from_timestamp(value: i64, unit: TimeUnit) -> Result<DateTime<Utc>> {
+    let datetime = match unit {
+        TimeUnit::Nanoseconds => Some(DateTime::from_timestamp_nanos(value)),
+        TimeUnit::Microseconds => DateTime::from_timestamp_micros(value),
+        TimeUnit::Milliseconds => DateTime::from_timestamp_millis(value),
+    };
+    datetime.ok_or_else(|| anyhow!("Timestamp out of range: {value} {unit}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_format_is_binary_for_native_types() {
+        let dtypes = vec![
+            DataType::Int32,
+            DataType::String,
+            DataType::Datetime(TimeUnit::Microseconds, None),
+        ];
+        let pg_types = vec![Type::INT8, Type::VARCHAR, Type::TIMESTAMP];
+
+        assert_eq!(
+            CopyFormat::for_columns(&dtypes, &pg_types),
+            CopyFormat::Binary
+        );
+    }
+
+    #[test]
+    fn test_copy_format_falls_back_to_csv() {
+        // Narrowing an integer or writing strings into a non text type needs the text input
+        assert_eq!(
+            CopyFormat::for_columns(&[DataType::Int64], &[Type::INT4]),
+            CopyFormat::Csv
+        );
+        assert_eq!(
+            CopyFormat::for_columns(
+                &[DataType::String, DataType::Int64],
+                &[Type::JSONB, Type::INT8]
+            ),
+            CopyFormat::Csv
+        );
+    }
+
+    #[test]
+    fn test_postgres_type_for() {
+        assert_eq!(postgres_type_for(&DataType::Int64), "bigint");
+        assert_eq!(
+            postgres_type_for(&DataType::Decimal(Some(10), Some(2))),
+            "numeric(10,2)"
+        );
+        assert_eq!(
+            postgres_type_for(&DataType::Datetime(
+                TimeUnit::Microseconds,
+                Some(TimeZone::UTC)
+            )),
+            "timestamptz"
+        );
+        assert_eq!(
+            postgres_type_for(&DataType::List(Box::new(DataType::String))),
+            "text[]"
+        );
+        assert_eq!(postgres_type_for(&DataType::Null), "text");
+    }
+
+    #[test]
+    fn test_copy_value_widens_integers() {
+        let value = CopyValue::new(AnyValue::Int16(7), &Type::INT8).unwrap();
+        assert!(matches!(value, CopyValue::Int8(7)));
+
+        let value = CopyValue::new(AnyValue::Null, &Type::INT8).unwrap();
+        assert!(matches!(value, CopyValue::Null));
+    }
+
+    #[test]
+    fn test_wide_numerics_are_copied_as_text() {
+        // numeric(38,x) values beyond 64 bits and beyond the 28 digits of a 96 bit decimal
+        let above_i64 = i64::MAX as i128 + 1;
+        let max_numeric_38 = 10_i128.pow(38) - 1;
+
+        // No binary encoding exists for them, so tables with such columns are copied as CSV
+        for dtype in [
+            DataType::Decimal(Some(38), Some(0)),
+            DataType::Decimal(Some(38), Some(10)),
+            DataType::UInt64,
+            DataType::Int64,
+        ] {
+            assert_eq!(
+                CopyFormat::for_columns(&[DataType::Int32, dtype], &[Type::INT4, Type::NUMERIC]),
+                CopyFormat::Csv
+            );
+        }
+
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(above_i64, 0)),
+            Some("9223372036854775808".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(-above_i64, 2)),
+            Some("-92233720368547758.08".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(max_numeric_38, 0)),
+            Some("99999999999999999999999999999999999999".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(max_numeric_38, 10)),
+            Some("9999999999999999999999999999.9999999999".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(-max_numeric_38, 38)),
+            Some("-0.99999999999999999999999999999999999999".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::UInt64(u64::MAX)),
+            Some("18446744073709551615".to_string())
+        );
+
+        // The binary encoding refuses them rather than truncating
+        assert!(CopyValue::new(AnyValue::Decimal(above_i64, 0), &Type::NUMERIC).is_err());
+        assert!(CopyValue::new(AnyValue::Decimal(max_numeric_38, 10), &Type::NUMERIC).is_err());
+        assert!(CopyValue::new(AnyValue::UInt64(u64::MAX), &Type::INT8).is_err());
+        assert!(CopyValue::new(AnyValue::UInt64(1 << 63), &Type::INT8).is_err());
+
+        let value = CopyValue::new(AnyValue::Int64(i64::MAX), &Type::INT8).unwrap();
+        assert!(matches!(value, CopyValue::Int8(i64::MAX)));
+    }
+
+    #[test]
+    fn test_copy_value_timestamp_units() {
+        let value = CopyValue::new(
+            AnyValue::Datetime(1_700_000_000_123, TimeUnit::Milliseconds, None),
+            &Type::TIMESTAMP,
+        )
+        .unwrap();
+        let expected = DateTime::from_timestamp_millis(1_700_000_000_123)
+            .unwrap()
+            .naive_utc();
+        assert!(matches!(value, CopyValue::Timestamp(v) if v == expected));
+    }
+
+    #[test]
+    fn test_write_csv_row() {
+        let mut buf = Vec::new();
+        write_csv_row(
+            &mut buf,
+            &[
+                AnyValue::Int64(1),
+                AnyValue::String("say \"hi\", bye"),
+                AnyValue::Null,
+                AnyValue::String(""),
+                AnyValue::Boolean(true),
+            ],
+        );
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "\"1\",\"say \"\"hi\"\", bye\",,\"\",\"t\"\n"
+        );
+    }
+
+    #[test]
+    fn test_copy_text_value() {
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(-12345, 2)),
+            Some("-123.45".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(5, 3)),
+            Some("0.005".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Decimal(i128::MIN, 38)),
+            Some("-1.70141183460469231731687303715884105728".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Float64(f64::NAN)),
+            Some("NaN".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::Date(19_000)),
+            Some("2022-01-08".to_string())
+        );
+        assert_eq!(
+            copy_text_value(&AnyValue::String(" MULTIPOLYGON(((0 0,1 1,1 0,0 0))) ")),
+            Some(" MULTIPOLYGON(((0 0,1 1,1 0,0 0))) ".to_string())
+        );
+        assert_eq!(copy_text_value(&AnyValue::Null), None);
+    }
+}
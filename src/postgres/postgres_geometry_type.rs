@@ -1,55 +1,171 @@
+use anyhow::{Result, anyhow};
+use polars::prelude::AnyValue;
+
+use super::postgres_copy::hex_encode;
+
+/// Represents a PostGIS `geometry` or `geography` column, whose values DMS writes
+/// as WKT, EWKT or hex WKB.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PostgresGeometryType {
-    value_type: String,
     srid_value: i32,
 }
 
 impl PostgresGeometryType {
-    const MULTIPOLYGON: &str = "MULTIPOLYGON";
-    const GEOMETRY_TYPE_FROM_TEXT_PREFIX: &str = "ST_GeomFromText";
-    const ACCEPTED_GEOMETRY_KEYWORDS: &[&str] = &[Self::MULTIPOLYGON];
-    const NUM_OF_CHARS_FOR_GEOMETRY_TYPE_CHECK: usize = 30;
-
-    pub fn new(input: &str, srid_value: i32) -> Self {
-        let value_type = input
-            .chars()
-            .take(Self::NUM_OF_CHARS_FOR_GEOMETRY_TYPE_CHECK)
-            .collect::<String>();
-
-        let sanitized_value_type = if value_type.starts_with('"') && value_type.ends_with('"') {
-            value_type
-                .strip_prefix('"')
-                .and_then(|s| s.strip_suffix('"'))
-                .unwrap_or(&value_type)
-                .to_string()
-        } else {
-            value_type.to_string()
-        };
+    const SPATIAL_TYPES: &[&str] = &["geometry", "geography"];
+    const ACCEPTED_GEOMETRY_KEYWORDS: &[&str] = &[
+        "POINT",
+        "LINESTRING",
+        "POLYGON",
+        "MULTIPOINT",
+        "MULTILINESTRING",
+        "MULTIPOLYGON",
+        "GEOMETRYCOLLECTION",
+        "CIRCULARSTRING",
+        "COMPOUNDCURVE",
+        "CURVEPOLYGON",
+        "MULTICURVE",
+        "MULTISURFACE",
+        "POLYHEDRALSURFACE",
+        "TRIANGLE",
+        "TIN",
+    ];
+    const EWKT_SRID_PREFIX: &str = "SRID=";
+    /// The flag of the EWKB geometry type telling that a SRID follows the type.
+    const EWKB_SRID_FLAG: u32 = 0x2000_0000;
+    const NUM_OF_CHARS_IN_ERRORS: usize = 30;
 
-        PostgresGeometryType {
-            value_type: sanitized_value_type,
-            srid_value,
+    /// Creates the type of a column whose values are in the given spatial reference system,
+    /// 0 when the column does not constrain it.
+    pub fn new(srid_value: i32) -> Self {
+        PostgresGeometryType { srid_value }
+    }
+
+    /// Whether a column type, as formatted by `format_type` or named by the catalog,
+    /// is `geometry` or `geography`, e.g. `geometry(PointZ,4326)` or `extensions.geography`.
+    pub fn is_geometry_type(data_type: &str) -> bool {
+        let type_name = data_type.split('(').next().unwrap_or(data_type).trim();
+        let type_name = type_name.rsplit('.').next().unwrap_or(type_name);
+        Self::SPATIAL_TYPES.contains(&type_name.trim_matches('"'))
+    }
+
+    /// Renders a value of the column in the text representation PostGIS accepts as input.
+    ///
+    /// Returns `None` for nulls.
+    pub fn copy_text_value(&self, value: &AnyValue) -> Result<Option<String>> {
+        match value {
+            AnyValue::Null => Ok(None),
+            AnyValue::Binary(wkb) => self.format_wkb(wkb).map(Some),
+            AnyValue::BinaryOwned(wkb) => self.format_wkb(wkb).map(Some),
+            v => self.format_value(&v.str_value()).map(Some),
         }
     }
 
-    pub fn is_geometry_type(&self) -> bool {
-        self.value_type
-            .find('(')
-            .map(|pos| &self.value_type[..pos])
-            .is_some_and(|prefix| Self::ACCEPTED_GEOMETRY_KEYWORDS.contains(&prefix))
+    /// Formats a WKT, EWKT or hex WKB value as EWKT or hex EWKB in the SRID of the column.
+    ///
+    /// EWKT and EWKB values that carry a SRID are kept as they are.
+    pub fn format_value(&self, value: &str) -> Result<String> {
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .trim();
+
+        if value
+            .get(..Self::EWKT_SRID_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(Self::EWKT_SRID_PREFIX))
+        {
+            return Ok(value.to_string());
+        }
+
+        if is_wkt(value) {
+            return Ok(match self.srid_value {
+                0 => value.to_string(),
+                srid => format!("{}{srid};{value}", Self::EWKT_SRID_PREFIX),
+            });
+        }
+
+        if let Some(wkb) = hex_decode(value.strip_prefix("\\x").unwrap_or(value)) {
+            return self.format_wkb(&wkb);
+        }
+
+        Err(anyhow!(
+            "Value {} is not a WKT, EWKT or hex WKB geometry",
+            value
+                .chars()
+                .take(Self::NUM_OF_CHARS_IN_ERRORS)
+                .collect::<String>()
+        ))
     }
 
-    pub fn format_value(&self, value: &str) -> String {
-        let value_type =
-            &self.value_type[..self.value_type.find('(').unwrap_or(self.value_type.len())];
-
-        match value_type {
-            Self::MULTIPOLYGON => format!(
-                "{}('{}', {})",
-                Self::GEOMETRY_TYPE_FROM_TEXT_PREFIX,
-                value,
-                self.srid_value
-            ),
-            _ => self.value_type.clone(),
+    /// Formats a WKB value as hex EWKB in the SRID of the column.
+    pub fn format_wkb(&self, wkb: &[u8]) -> Result<String> {
+        if wkb.len() < 5 {
+            return Err(anyhow!("WKB geometry of {} bytes is too short", wkb.len()));
         }
+
+        let little_endian = match wkb[0] {
+            0 => false,
+            1 => true,
+            byte_order => return Err(anyhow!("WKB geometry has byte order {byte_order}")),
+        };
+        let type_bytes = [wkb[1], wkb[2], wkb[3], wkb[4]];
+        let geometry_type = if little_endian {
+            u32::from_le_bytes(type_bytes)
+        } else {
+            u32::from_be_bytes(type_bytes)
+        };
+
+        if self.srid_value == 0 || geometry_type & Self::EWKB_SRID_FLAG != 0 {
+            return Ok(hex_encode(wkb));
+        }
+
+        // The SRID follows the geometry type, in the byte order of the geometry
+        let geometry_type = geometry_type | Self::EWKB_SRID_FLAG;
+        let (type_bytes, srid_bytes) = if little_endian {
+            (geometry_type.to_le_bytes(), self.srid_value.to_le_bytes())
+        } else {
+            (geometry_type.to_be_bytes(), self.srid_value.to_be_bytes())
+        };
+        let mut ewkb = Vec::with_capacity(wkb.len() + srid_bytes.len());
+        ewkb.push(wkb[0]);
+        ewkb.extend_from_slice(&type_bytes);
+        ewkb.extend_from_slice(&srid_bytes);
+        ewkb.extend_from_slice(&wkb[5..]);
+
+        Ok(hex_encode(&ewkb))
     }
 }
+
+/// Whether a value is WKT, of any geometry type and with any of the Z, M and ZM dimensions,
+/// e.g. `POINT ZM (1 2 3 4)`, `MULTIPOLYGONZ(...)` or `LINESTRING EMPTY`.
+pub fn is_wkt(value: &str) -> bool {
+    let tag = value
+        .find('(')
+        .map(|pos| &value[..pos])
+        .or_else(|| value.strip_suffix("EMPTY"))
+        .or_else(|| value.strip_suffix("empty"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    let geometry_type = tag
+        .strip_suffix("ZM")
+        .or_else(|| tag.strip_suffix('Z'))
+        .or_else(|| tag.strip_suffix('M'))
+        .unwrap_or(&tag);
+
+    PostgresGeometryType::ACCEPTED_GEOMETRY_KEYWORDS.contains(&geometry_type)
+}
+
+fn hex_decode(value: &str) -> Option<Vec<u8>> {
+    if value.is_empty() || value.len() % 2 != 0 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|pos| u8::from_str_radix(&value[pos..pos + 2], 16).ok())
+        .collect()
+}
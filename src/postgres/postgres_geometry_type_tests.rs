@@ -1,16 +1,130 @@
 #[cfg(test)]
 mod tests {
-    use crate::postgres::postgres_geometry_type::PostgresGeometryType;
+    use polars::prelude::AnyValue;
+
+    use crate::postgres::postgres_geometry_type::{PostgresGeometryType, is_wkt};
 
     #[test]
     fn test_is_geometry_type() {
-        let geometry_type = PostgresGeometryType::new("MULTIPOLYGON(", 0);
-        assert!(geometry_type.is_geometry_type());
+        assert!(PostgresGeometryType::is_geometry_type("geometry"));
+        assert!(PostgresGeometryType::is_geometry_type(
+            "geometry(MultiPolygon,4326)"
+        ));
+        assert!(PostgresGeometryType::is_geometry_type(
+            "extensions.geography(PointZ,4326)"
+        ));
     }
 
     #[test]
     fn test_is_not_geometry_type() {
-        let geometry_type = PostgresGeometryType::new("POINT(", 0);
-        assert!(!geometry_type.is_geometry_type());
+        assert!(!PostgresGeometryType::is_geometry_type("text"));
+        assert!(!PostgresGeometryType::is_geometry_type("geometry[]"));
+        assert!(!PostgresGeometryType::is_geometry_type("box"));
+    }
+
+    #[test]
+    fn test_is_wkt() {
+        for value in [
+            "POINT(1 2)",
+            "POINT Z (1 2 3)",
+            "POINTM(1 2 4)",
+            "POINT ZM (1 2 3 4)",
+            "LINESTRING(0 0,1 1)",
+            "POLYGON((0 0,1 1,1 0,0 0))",
+            "MULTIPOINT((0 0),(1 1))",
+            "MULTILINESTRING((0 0,1 1))",
+            "MULTIPOLYGON Z (((0 0 0,1 1 0,1 0 0,0 0 0)))",
+            "GEOMETRYCOLLECTION(POINT(1 2))",
+            "multipolygon(((0 0,1 1,1 0,0 0)))",
+            "LINESTRING EMPTY",
+        ] {
+            assert!(is_wkt(value), "{value}");
+        }
+
+        assert!(!is_wkt("hello (world)"));
+        assert!(!is_wkt("0101000000000000000000F03F0000000000000040"));
+    }
+
+    #[test]
+    fn test_format_wkt() {
+        let geometry_type = PostgresGeometryType::new(4326);
+        assert_eq!(
+            geometry_type
+                .format_value(" \"MULTIPOLYGON(((0 0,1 1,1 0,0 0)))\" ")
+                .unwrap(),
+            "SRID=4326;MULTIPOLYGON(((0 0,1 1,1 0,0 0)))"
+        );
+        assert_eq!(
+            PostgresGeometryType::new(0)
+                .format_value("POINT Z (1 2 3)")
+                .unwrap(),
+            "POINT Z (1 2 3)"
+        );
+    }
+
+    #[test]
+    fn test_format_ewkt_keeps_its_srid() {
+        let geometry_type = PostgresGeometryType::new(4326);
+        assert_eq!(
+            geometry_type.format_value("SRID=3857;POINT(1 2)").unwrap(),
+            "SRID=3857;POINT(1 2)"
+        );
+    }
+
+    #[test]
+    fn test_format_hex_wkb() {
+        // POINT(1 2) in little and big endian WKB
+        let little_endian = "0101000000000000000000f03f0000000000000040";
+        let big_endian = "00000000013ff00000000000004000000000000000";
+
+        let geometry_type = PostgresGeometryType::new(4326);
+        assert_eq!(
+            geometry_type.format_value(little_endian).unwrap(),
+            "0101000020e6100000000000000000f03f0000000000000040"
+        );
+        assert_eq!(
+            geometry_type.format_value(big_endian).unwrap(),
+            "0020000001000010e63ff00000000000004000000000000000"
+        );
+        assert_eq!(
+            PostgresGeometryType::new(0)
+                .format_value(little_endian)
+                .unwrap(),
+            little_endian
+        );
+    }
+
+    #[test]
+    fn test_format_ewkb_keeps_its_srid() {
+        let ewkb = "0101000020110f0000000000000000f03f0000000000000040";
+        assert_eq!(
+            PostgresGeometryType::new(4326).format_value(ewkb).unwrap(),
+            ewkb
+        );
+    }
+
+    #[test]
+    fn test_copy_text_value_of_binary_wkb() {
+        let wkb = [
+            1u8, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 0, 64,
+        ];
+        let geometry_type = PostgresGeometryType::new(4326);
+        assert_eq!(
+            geometry_type
+                .copy_text_value(&AnyValue::Binary(&wkb))
+                .unwrap(),
+            Some("0101000020e6100000000000000000f03f0000000000000040".to_string())
+        );
+        assert_eq!(
+            geometry_type.copy_text_value(&AnyValue::Null).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_format_invalid_value() {
+        let geometry_type = PostgresGeometryType::new(4326);
+        assert!(geometry_type.format_value("not a geometry").is_err());
+        assert!(geometry_type.format_value("02000000").is_err());
     }
 }
@@ -1,6 +1,16 @@
-use crate::postgres::table_mode::TableMode;
+use crate::cdc::apply_mode::ApplyModeValueEnum;
+use crate::cdc::cdc_semantics::CdcSemanticsValueEnum;
+use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+use crate::postgres::column_definition::ColumnDefinition;
+use crate::postgres::row_identity::RowIdentity;
+use crate::postgres::source_table::SourceTable;
+use crate::postgres::table_constraint::TableConstraint;
+use crate::postgres::table_filter::TableFilter;
+use crate::postgres::table_index::TableIndex;
+use crate::postgres::user_defined_type::UserDefinedType;
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
 
 #[cfg(test)]
 use mockall::automock;
@@ -10,6 +20,10 @@ pub struct InsertDataframePayload {
     pub database_name: String,
     pub schema_name: String,
     pub table_name: String,
+    pub dms_metadata_columns: DmsMetadataColumns,
+    pub cdc_semantics: CdcSemanticsValueEnum,
+    /// The SRID of each PostGIS column of the table.
+    pub geometry_srids: HashMap<String, i32>,
 }
 
 impl InsertDataframePayload {
@@ -26,7 +40,29 @@ pub struct UpsertDataframePayload {
     pub database_name: String,
     pub schema_name: String,
     pub table_name: String,
-    pub primary_key: String,
+    pub primary_keys: Vec<String>,
+    pub dms_metadata_columns: DmsMetadataColumns,
+    pub cdc_semantics: CdcSemanticsValueEnum,
+    pub apply_mode: ApplyModeValueEnum,
+    /// Whether the changes are already compacted to the net change of each key,
+    /// e.g. by `compact_cdc_changes`, so that they are applied without reducing them again.
+    pub is_compacted: bool,
+    /// The SRID of each PostGIS column of the table.
+    pub geometry_srids: HashMap<String, i32>,
+}
+
+/// Counts the changes of a CDC file that the idempotent apply mode tolerated,
+/// instead of applying them as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CdcFileApplyReport {
+    /// The file of the changes, if it is known.
+    pub file_name: Option<String>,
+    /// Inserts of keys that already existed, applied as updates.
+    pub upserted_inserts: i64,
+    /// Deletes of keys that did not exist.
+    pub skipped_deletes: i64,
+    /// Updates older than the stored row.
+    pub skipped_stale_updates: i64,
 }
 
 #[cfg_attr(test, automock)]
@@ -41,13 +77,29 @@ pub trait PostgresOperator {
     ///
     /// # Returns
     ///
-    /// A IndexMap containing the column names and their data types.
+    /// A IndexMap containing the column names and their exact data types.
     async fn get_table_columns(
         &self,
         schema_name: &str,
         table_name: &str,
     ) -> Result<indexmap::IndexMap<String, String>>;
 
+    /// Get the definitions of the columns of a table, to create it in the target database.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema_name` - The name of the schema.
+    /// * `table_name` - The name of the table.
+    ///
+    /// # Returns
+    ///
+    /// The definitions of the columns, in the order of the table.
+    async fn get_column_definitions(
+        &self,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<Vec<ColumnDefinition>>;
+
     //// Get the primary key of a table.
     ///
     /// # Arguments
@@ -60,6 +112,37 @@ pub trait PostgresOperator {
     /// The primary key of the table.
     async fn get_primary_key(&self, table_name: &str, schema_name: &str) -> Result<Vec<String>>;
 
+    /// Get the columns of the first unique index of a table, other than its primary key,
+    /// whose columns are all NOT NULL.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// The columns of the index, or none if the table has no unique index.
+    async fn get_unique_key(&self, table_name: &str, schema_name: &str) -> Result<Vec<String>>;
+
+    /// Check whether some rows of a table have the same values in the key columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The key columns.
+    /// * `table_name` - The name of the table.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// Whether the key columns do not identify the rows of the table.
+    async fn has_duplicate_keys(
+        &self,
+        keys: &[String],
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<bool>;
+
     /// Create a schema in the target database.
     ///
     /// # Arguments
@@ -71,47 +154,202 @@ pub trait PostgresOperator {
     /// A Result indicating success or failure.
     async fn create_schema(&self, schema_name: &str) -> Result<()>;
 
+    /// Check whether a schema exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// Whether the schema exists.
+    async fn schema_exists(&self, schema_name: &str) -> Result<bool>;
+
+    /// Create the schema with the applied timestamps of a target schema, for the idempotent apply mode.
+    ///
+    /// Fails if the name of the schema is too long for Postgres, which would truncate it,
+    /// if a schema of that name already exists that does not keep applied timestamps,
+    /// or if the target schema itself keeps the applied timestamps of another schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema_name` - The name of the target schema.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure.
+    async fn create_applied_timestamps_schema(&self, schema_name: &str) -> Result<()>;
+
     /// Create a table in the target database.
     ///
     /// # Arguments
     ///
-    /// * `column_data_types` - The data types of the columns in the table.
-    /// * `primary_key` - The primary key of the table.
+    /// * `columns` - The definitions of the columns in the table.
+    /// * `row_identity` - The key of the table, created as its primary key or a unique constraint.
+    /// * `partition_key` - The partitioning of the table, if it is partitioned, e.g. `RANGE (created_at)`.
     /// * `schema_name` - The name of the schema.
     /// * `table_name` - The name of the table.
     ///
     /// # Returns
     ///
     /// A Result indicating success or failure.
-    async fn create_table(
+    async fn create_table<'a>(
+        &self,
+        columns: &[ColumnDefinition],
+        row_identity: &RowIdentity,
+        partition_key: Option<&'a str>,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<()>;
+
+    /// Create a partition of a partitioned table in the target database, with the bound of the source partition.
+    ///
+    /// # Arguments
+    ///
+    /// * `partition` - The source partition.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure, e.g. when the table it is attached to is not partitioned.
+    async fn create_partition(&self, partition: &SourceTable, schema_name: &str) -> Result<()>;
+
+    /// Add columns to a table in the target database, skipping the columns it already has.
+    ///
+    /// # Arguments
+    ///
+    /// * `column_data_types` - The data types of the columns to add.
+    /// * `schema_name` - The name of the schema.
+    /// * `table_name` - The name of the table.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure.
+    async fn add_columns(
         &self,
         column_data_types: &indexmap::IndexMap<String, String>,
-        primary_key: &[String],
         schema_name: &str,
         table_name: &str,
     ) -> Result<()>;
 
-    /// Get the tables in a schema.
+    /// Get the indexes of a table, other than the indexes of its primary key and constraints.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// The indexes, with their `CREATE INDEX` statements.
+    async fn get_indexes(&self, table_name: &str, schema_name: &str) -> Result<Vec<TableIndex>>;
+
+    /// Get the unique, check, exclusion and foreign key constraints of a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// The constraints of the table.
+    async fn get_constraints(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<Vec<TableConstraint>>;
+
+    /// Create an index in the target database, unless an index with its name exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `index_definition` - The `CREATE INDEX` statement of the index.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure.
+    async fn create_index(&self, index_definition: &str) -> Result<()>;
+
+    /// Add a constraint to a table in the target database.
+    ///
+    /// # Arguments
+    ///
+    /// * `constraint` - The constraint to add.
+    /// * `schema_name` - The name of the schema.
+    /// * `table_name` - The name of the table.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure, e.g. when the rows violate the constraint.
+    async fn add_constraint(
+        &self,
+        constraint: &TableConstraint,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<()>;
+
+    /// Get the user-defined and extension types that the columns of a table depend on.
     ///
     /// # Arguments
     ///
+    /// * `table_name` - The name of the table.
     /// * `schema_name` - The name of the schema.
-    /// * `included_tables` - The tables to include.
-    /// * `excluded_tables` - The tables to exclude.
-    /// * `table_mode` - The mode to use for the tables.
     ///
     /// # Returns
     ///
-    /// A Vec containing the tables in the schema.
+    /// The types, each after the types it depends on.
+    async fn get_user_defined_types(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<Vec<UserDefinedType>>;
+
+    /// Get the spatial reference system of each PostGIS column of a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table.
+    /// * `schema_name` - The name of the schema.
+    ///
+    /// # Returns
+    ///
+    /// The SRID of each `geometry` and `geography` column, 0 when the column does not constrain it.
+    async fn get_geometry_srids(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<HashMap<String, i32>>;
+
+    /// Create a user-defined type, or the extension it belongs to, in the target database,
+    /// unless the type exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_defined_type` - The type to create.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure, e.g. when the type has no statement that creates it.
+    async fn create_user_defined_type(&self, user_defined_type: &UserDefinedType) -> Result<()>;
+
+    /// Get the tables, partitioned tables, partitions, views and foreign tables in a schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema_name` - The name of the schema.
+    /// * `table_filter` - The tables to select, with the partitions of the selected partitioned tables.
+    ///
+    /// # Returns
+    ///
+    /// A Vec containing the selected tables in the schema, with their kind and partitioning.
     async fn get_tables_in_schema(
         &self,
         schema_name: &str,
-        included_tables: &[String],
-        excluded_tables: &[String],
-        table_mode: &TableMode,
-    ) -> Result<Vec<String>>;
+        table_filter: &TableFilter,
+    ) -> Result<Vec<SourceTable>>;
 
-    /// Insert a DataFrame into the target database.
+    /// Insert a DataFrame into the target database with `COPY ... FROM STDIN`.
     ///
     /// # Arguments
     ///
@@ -131,22 +369,28 @@ pub trait PostgresOperator {
 
     /// Upsert a DataFrame into the target database.
     ///
+    /// The rows are staged in a temporary table and applied in one transaction,
+    /// keeping only the last change of each key, unless the payload tells they are
+    /// already compacted. The changes of a table without a key are matched to its
+    /// rows on all their columns.
+    ///
     /// # Arguments
     ///
     /// * `df` - The DataFrame to upsert.
     /// * `database_name` - The name of the database.
     /// * `schema_name` - The name of the schema.
     /// * `table_name` - The name of the table.
-    /// * `primary_key` - The primary key of the table.
+    /// * `primary_keys` - The columns that identify a row, or none to match rows on all their columns.
     ///
     /// # Returns
     ///
-    /// A Result indicating success or failure.
+    /// The counts of the tolerated changes per file in the idempotent apply mode,
+    /// or no counts in the strict apply mode. On error, none of the changes are applied.
     async fn upsert_dataframe_in_target_db(
         &self,
         df: &polars::frame::DataFrame,
         payload: &UpsertDataframePayload,
-    ) -> Result<()>;
+    ) -> Result<Vec<CdcFileApplyReport>>;
 
     /// Drop schema in the target database.
     ///
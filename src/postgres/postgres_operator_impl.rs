@@ -1,27 +1,53 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use bon::bon;
-use deadpool_postgres::{GenericClient, Pool};
+use bytes::Bytes;
+use chrono_tz::Tz;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use futures::SinkExt;
 use indexmap::IndexMap;
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use polars::prelude::*;
-use rust_decimal::prelude::ToPrimitive;
+use std::collections::HashMap;
+use std::pin::pin;
 use std::sync::LazyLock;
+use tokio_postgres::{Client, binary_copy::BinaryCopyInWriter, types::Type};
 
-use std::{fmt::Display, time::Instant};
+use std::time::Instant;
 
 use TableQuery::*;
 use tracing::info;
 
-use super::postgres_geometry_type::PostgresGeometryType;
+use super::postgres_copy::{CopyFormat, CopyValue, write_csv_fields};
 pub(crate) use super::postgres_operator::PostgresOperator;
 use super::{
-    postgres_operator::{InsertDataframePayload, UpsertDataframePayload},
-    table_query::TableQuery,
+    column_definition::{ColumnDefinition, IdentityGeneration},
+    postgres_geometry_type::PostgresGeometryType,
+    postgres_operator::{CdcFileApplyReport, InsertDataframePayload, UpsertDataframePayload},
+    postgres_value_converter::PostgresValueConverter,
+    row_identity::RowIdentity,
+    source_table::{SourceTable, TableKind},
+    table_constraint::{ConstraintKind, TableConstraint},
+    table_query::{
+        APPLIED_TIMESTAMPS_SCHEMA_COMMENT, MAX_IDENTIFIER_LENGTH, STAGING_TABLE, TableQuery,
+        applied_timestamps_schema, qualified_name,
+    },
+    user_defined_type::{TypeKind, UserDefinedType},
 };
 
-use crate::postgres::postgres_row_struct::RowStruct;
-use crate::postgres::table_mode::TableMode;
+use crate::dataframe::dataframe_ops::sort_by_ingestion_timestamp;
+use crate::dataframe::dms_metadata_columns::{CHANGE_OP_COLUMN, is_change_column};
+use crate::postgres::table_filter::TableFilter;
+use crate::postgres::table_index::TableIndex;
+
+/// Rows streamed per COPY statement when the table has no delayable config
+const ROWS_PER_COPY: usize = 1_000_000;
+
+/// The first Postgres version, as in `server_version_num`, with MERGE
+const MERGE_MIN_SERVER_VERSION: i32 = 150_000;
+
+/// Size of the CSV buffer sent to Postgres in a single COPY message
+const CSV_COPY_BUFFER_SIZE: usize = 1 << 20;
 
 static INSERT_DELAYABLES: LazyLock<Vec<String>> = LazyLock::new(|| {
     let insert_delayables: Vec<String> = std::env::var("DELAYABLE_CONFIG")
@@ -33,21 +59,6 @@ static INSERT_DELAYABLES: LazyLock<Vec<String>> = LazyLock::new(|| {
     insert_delayables
 });
 
-/// Represents the data type of a column in a table.
-enum ColumnDataType {
-    Array,
-    Rest(String),
-}
-
-impl Display for ColumnDataType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ColumnDataType::Array => write!(f, "text[]"),
-            ColumnDataType::Rest(data_type) => write!(f, "{}", data_type),
-        }
-    }
-}
-
 pub struct PostgresOperatorImpl {
     pool: Pool,
 }
@@ -67,35 +78,33 @@ impl PostgresOperator for PostgresOperatorImpl {
         schema_name: &str,
         table_name: &str,
     ) -> Result<IndexMap<String, String>> {
-        // Prepare the query to get all columns for a table
-        let query = FindAllColumns(schema_name.to_string(), table_name.to_string());
+        let columns = self
+            .get_column_definitions(schema_name, table_name)
+            .await?
+            .into_iter()
+            .map(|column| (column.name, column.data_type))
+            .collect();
+
+        Ok(columns)
+    }
 
-        // Fetch columns for the table
+    async fn get_column_definitions(
+        &self,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<Vec<ColumnDefinition>> {
         let client = self.pool.get().await?;
-
-        let rows = client.query(&query.to_string(), &[]).await?;
-        let mut res = IndexMap::new();
-        for row in rows {
-            let column_name: String = row.get("column_name");
-            let data_type: String = row.get("data_type");
-            if data_type.eq("ARRAY") {
-                res.insert(column_name, ColumnDataType::Array.to_string());
-            } else {
-                res.insert(column_name, ColumnDataType::Rest(data_type).to_string());
-            }
-        }
-
-        Ok(res)
+        column_definitions(&client, schema_name, table_name).await
     }
 
     async fn get_primary_key(&self, table_name: &str, schema_name: &str) -> Result<Vec<String>> {
         // Prepare the query to get the primary key for a table
-        let query = FindPrimaryKey(table_name.to_string(), schema_name.to_string());
+        let query = FindPrimaryKey;
         // Fetch the primary key for the table
         let client = self.pool.get().await?;
 
         let row = client
-            .query(&query.to_string(), &[])
+            .query(&query.to_string(), &[&schema_name, &table_name])
             .await
             .unwrap_or(vec![]);
 
@@ -108,6 +117,35 @@ impl PostgresOperator for PostgresOperatorImpl {
         Ok(primary_key_list)
     }
 
+    async fn get_unique_key(&self, table_name: &str, schema_name: &str) -> Result<Vec<String>> {
+        let query = FindUniqueKey;
+        let client = self.pool.get().await?;
+
+        let rows = client
+            .query(&query.to_string(), &[&schema_name, &table_name])
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("attname")).collect())
+    }
+
+    async fn has_duplicate_keys(
+        &self,
+        keys: &[String],
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<bool> {
+        let query = FindDuplicateKeys(
+            schema_name.to_string(),
+            table_name.to_string(),
+            keys.to_vec(),
+        );
+        let client = self.pool.get().await?;
+
+        let row = client.query_one(&query.to_string(), &[]).await?;
+
+        Ok(row.get(0))
+    }
+
     async fn create_schema(&self, schema_name: &str) -> Result<()> {
         // Prepare the query to create a schema
         let query = CreateSchema(schema_name.to_string());
@@ -121,56 +159,89 @@ impl PostgresOperator for PostgresOperatorImpl {
         Ok(())
     }
 
+    async fn schema_exists(&self, schema_name: &str) -> Result<bool> {
+        let client = self.pool.get().await?;
+        Ok(schema_comment(&client, schema_name).await?.is_some())
+    }
+
+    async fn create_applied_timestamps_schema(&self, schema_name: &str) -> Result<()> {
+        let applied_schema = applied_timestamps_schema(schema_name);
+
+        // Postgres truncates longer names, which could then be the same for two target schemas
+        if applied_schema.len() > MAX_IDENTIFIER_LENGTH {
+            return Err(anyhow!(
+                "The applied timestamps of schema {schema_name} are kept in schema {applied_schema}, \
+                whose name is longer than {MAX_IDENTIFIER_LENGTH} bytes"
+            ));
+        }
+
+        let client = self.pool.get().await?;
+        let is_applied_timestamps_schema = |comment: &Option<String>| {
+            comment.as_deref() == Some(APPLIED_TIMESTAMPS_SCHEMA_COMMENT)
+        };
+        if schema_comment(&client, schema_name)
+            .await?
+            .is_some_and(|comment| is_applied_timestamps_schema(&comment))
+        {
+            return Err(anyhow!(
+                "Schema {schema_name} keeps the applied timestamps of another schema"
+            ));
+        }
+        if schema_comment(&client, &applied_schema)
+            .await?
+            .is_some_and(|comment| !is_applied_timestamps_schema(&comment))
+        {
+            return Err(anyhow!(
+                "The applied timestamps of schema {schema_name} are kept in schema {applied_schema}, \
+                which already exists for other tables"
+            ));
+        }
+
+        let queries = [
+            CreateSchema(applied_schema.clone()),
+            CommentOnAppliedTimestampsSchema(applied_schema),
+        ];
+        for query in queries {
+            client.batch_execute(&query.to_string()).await?;
+        }
+
+        Ok(())
+    }
+
     async fn get_tables_in_schema(
         &self,
         schema_name: &str,
-        included_tables: &[String],
-        excluded_tables: &[String],
-        table_mode: &TableMode,
-    ) -> Result<Vec<String>> {
-        let subquery = match table_mode {
-            TableMode::IncludeTables => {
-                format!(
-                    "AND table_name IN ({})",
-                    included_tables
-                        .iter()
-                        .map(|table| format!("'{}'", table))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )
-            }
-            TableMode::ExcludeTables => {
-                format!(
-                    "AND table_name NOT IN ({})",
-                    excluded_tables
-                        .iter()
-                        .map(|table| format!("'{}'", table))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )
-            }
-            TableMode::AllTables => "".to_string(),
-        };
-
-        let query = FindTablesForSchema(schema_name.to_string(), subquery);
+        table_filter: &TableFilter,
+    ) -> Result<Vec<SourceTable>> {
+        let query = FindTablesForSchema;
 
         let client = self.pool.get().await?;
         let rows = client
-            .query(&query.to_string(), &[])
+            .query(&query.to_string(), &[&schema_name])
             .await
             .expect("Failed to fetch tables");
 
+        // The patterns of the filter are matched here, so that they work the same on every table list
         let tables = rows
             .iter()
-            .map(|row| row.get("table_name"))
-            .collect::<Vec<String>>();
+            .map(|row| SourceTable {
+                name: row.get("table_name"),
+                kind: TableKind::from_relkind(row.get("kind"), row.get("is_partition")),
+                parent: row.get("parent_name"),
+                root: row.get("root_name"),
+                partition_key: row.get("partition_key"),
+                partition_bound: row.get("partition_bound"),
+            })
+            .filter(|table| table_filter.selects(table))
+            .collect::<Vec<SourceTable>>();
         Ok(tables)
     }
 
-    async fn create_table(
+    async fn create_table<'a>(
         &self,
-        column_data_types: &IndexMap<String, String>,
-        primary_keys: &[String],
+        columns: &[ColumnDefinition],
+        row_identity: &RowIdentity,
+        partition_key: Option<&'a str>,
         schema_name: &str,
         table_name: &str,
     ) -> Result<()> {
@@ -178,8 +249,9 @@ impl PostgresOperator for PostgresOperatorImpl {
         let query = CreateTable(
             schema_name.to_string(),
             table_name.to_string(),
-            column_data_types.clone(),
-            primary_keys.join(","),
+            columns.to_vec(),
+            row_identity.clone(),
+            partition_key.map(String::from),
         );
 
         let client = self.pool.get().await?;
@@ -191,6 +263,209 @@ impl PostgresOperator for PostgresOperatorImpl {
         Ok(())
     }
 
+    async fn create_partition(&self, partition: &SourceTable, schema_name: &str) -> Result<()> {
+        let (Some(parent), Some(bound)) = (&partition.parent, &partition.partition_bound) else {
+            return Err(anyhow!("Table {} is not a partition", partition.name));
+        };
+        let query = CreatePartition(
+            schema_name.to_string(),
+            partition.name.clone(),
+            parent.clone(),
+            bound.clone(),
+            partition.partition_key.clone(),
+        );
+        trace!("Query: {}", query);
+
+        let client = self.pool.get().await?;
+        client.execute(&query.to_string(), &[]).await?;
+
+        Ok(())
+    }
+
+    async fn add_columns(
+        &self,
+        column_data_types: &IndexMap<String, String>,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<()> {
+        let query = AddColumns(
+            schema_name.to_string(),
+            table_name.to_string(),
+            column_data_types.clone(),
+        );
+
+        let client = self.pool.get().await?;
+        client.execute(&query.to_string(), &[]).await?;
+
+        Ok(())
+    }
+
+    async fn get_indexes(&self, table_name: &str, schema_name: &str) -> Result<Vec<TableIndex>> {
+        let client = self.pool.get().await?;
+
+        let rows = client
+            .query(&FindIndexes.to_string(), &[&schema_name, &table_name])
+            .await?;
+
+        let indexes = rows
+            .iter()
+            .map(|row| TableIndex {
+                name: row.get("name"),
+                definition: row.get("definition"),
+                unique_columns: row.get("unique_columns"),
+            })
+            .collect();
+
+        Ok(indexes)
+    }
+
+    async fn get_constraints(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<Vec<TableConstraint>> {
+        let client = self.pool.get().await?;
+
+        let rows = client
+            .query(&FindConstraints.to_string(), &[&schema_name, &table_name])
+            .await?;
+
+        let constraints = rows
+            .iter()
+            .filter_map(|row| {
+                let kind = ConstraintKind::from_contype(row.get("kind"))?;
+                let referenced_schema: Option<String> = row.get("referenced_schema");
+                let referenced_table: Option<String> = row.get("referenced_table");
+                Some(TableConstraint {
+                    name: row.get("name"),
+                    kind,
+                    definition: row.get("definition"),
+                    referenced_table: referenced_schema.zip(referenced_table),
+                })
+            })
+            .collect();
+
+        Ok(constraints)
+    }
+
+    async fn create_index(&self, index_definition: &str) -> Result<()> {
+        // pg_get_indexdef has no IF NOT EXISTS, so that re-runs skip the existing indexes,
+        // and the index of a partitioned table is created on its partitions too, without ONLY
+        let query = index_definition
+            .replacen(" INDEX ", " INDEX IF NOT EXISTS ", 1)
+            .replacen(" ON ONLY ", " ON ", 1);
+        trace!("Query: {}", query);
+
+        let client = self.pool.get().await?;
+        client.execute(&query, &[]).await?;
+
+        Ok(())
+    }
+
+    async fn add_constraint(
+        &self,
+        constraint: &TableConstraint,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<()> {
+        let query = AddConstraint(
+            schema_name.to_string(),
+            table_name.to_string(),
+            constraint.name.clone(),
+            constraint.definition.clone(),
+        );
+        trace!("Query: {}", query);
+
+        let client = self.pool.get().await?;
+        client.execute(&query.to_string(), &[]).await?;
+
+        Ok(())
+    }
+
+    async fn get_user_defined_types(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<Vec<UserDefinedType>> {
+        let client = self.pool.get().await?;
+
+        let rows = client
+            .query(
+                &FindUserDefinedTypes.to_string(),
+                &[&schema_name, &table_name],
+            )
+            .await?;
+
+        let user_defined_types = rows
+            .iter()
+            .map(|row| {
+                let extension: Option<String> = row.get("extension");
+                let extension_schema: Option<String> = row.get("extension_schema");
+                UserDefinedType {
+                    schema_name: row.get("schema_name"),
+                    name: row.get("name"),
+                    kind: TypeKind::from_typtype(row.get("kind")),
+                    extension: extension.zip(extension_schema),
+                    definition: row.get("definition"),
+                }
+            })
+            .collect();
+
+        Ok(user_defined_types)
+    }
+
+    async fn get_geometry_srids(
+        &self,
+        table_name: &str,
+        schema_name: &str,
+    ) -> Result<HashMap<String, i32>> {
+        let client = self.pool.get().await?;
+
+        // The PostGIS views are only read for tables with columns of its types
+        let column_definitions = column_definitions(&client, schema_name, table_name).await?;
+        if !column_definitions
+            .iter()
+            .any(|column| PostgresGeometryType::is_geometry_type(&column.data_type))
+        {
+            return Ok(HashMap::new());
+        }
+
+        let rows = client
+            .query(
+                &FindGeometryColumns.to_string(),
+                &[&schema_name, &table_name],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("column_name"), row.get("srid")))
+            .collect())
+    }
+
+    async fn create_user_defined_type(&self, user_defined_type: &UserDefinedType) -> Result<()> {
+        let client = self.pool.get().await?;
+
+        let type_name = qualified_name(&user_defined_type.schema_name, &user_defined_type.name);
+        let type_exists: bool = client
+            .query_one(&TypeExists.to_string(), &[&type_name])
+            .await?
+            .get("type_exists");
+        if type_exists {
+            return Ok(());
+        }
+
+        let query = user_defined_type.to_sql().ok_or_else(|| {
+            anyhow!(
+                "No statement creates {user_defined_type}: it is a base type without an extension, or the row type of a table"
+            )
+        })?;
+        trace!("Query: {}", query);
+        client.batch_execute(&query).await?;
+
+        Ok(())
+    }
+
     async fn drop_schema(&self, schema_name: &str) -> Result<()> {
         // Prepare the query to drop a schema
         let query = DropSchema(schema_name.to_string());
@@ -209,25 +484,45 @@ impl PostgresOperator for PostgresOperatorImpl {
         df: &DataFrame,
         payload: &InsertDataframePayload,
     ) -> Result<()> {
-        let mut df = df.clone();
-
-        // Drop the columns added by DMS
-        _ = df.drop_in_place("Op").expect("Failed to drop 'Op' column");
-        _ = df
-            .drop_in_place("_dms_ingestion_timestamp")
-            .expect("Failed to drop '_dms_ingestion_timestamp' column");
+        let client = self.pool.get().await?;
+        let target_columns =
+            column_definitions(&client, &payload.schema_name, &payload.table_name).await?;
+        let table_columns = column_names(&target_columns);
+
+        // Drop the columns added by DMS, history mode keeps the operation and timestamp of each row version
+        let df = if payload.cdc_semantics.keeps_history() {
+            payload
+                .dms_metadata_columns
+                .drop_before_images_from(df, &table_columns)
+        } else {
+            payload.dms_metadata_columns.drop_from(df, &table_columns)
+        };
+        let df = drop_generated_columns(&df, &target_columns);
 
         let column_names = df.get_column_names_str();
         let fields = column_names.join(", ");
 
         debug!("Columns names: {fields}");
 
-        let df_height = df.height().to_f64().unwrap();
+        let df_height = df.height();
 
         info!("Total DF height: {df_height}");
 
         let insert_by_chunk_start = Instant::now();
-        let client = self.pool.get().await?;
+
+        let copy_target = prepare_copy(
+            &client,
+            &payload.schema_name,
+            &payload.table_name,
+            &df,
+            &payload.geometry_srids,
+        )
+        .await?;
+        info!(
+            "Copying into {table} with {copy_format} format",
+            table = payload.table_name,
+            copy_format = copy_target.format
+        );
 
         let rows_per_df = rows_per_df(payload);
         let should_delay_insert = should_delay_insert(payload);
@@ -241,60 +536,31 @@ impl PostgresOperator for PostgresOperatorImpl {
 
         let insert_delay = insert_delay();
 
-        let mut offset = 0f64;
+        let mut offset = 0;
 
         while offset < df_height {
             debug!(
-                "Inserting rows at offset: {offset}, table: {table}",
+                "Copying rows at offset: {offset}, table: {table}",
                 table = payload.table_name
             );
             let df_chunk = df.slice(offset as i64, rows_per_df);
             let df_chunk_height = df_chunk.height();
-            let df_columns = df_chunk.get_columns();
-
-            let values = (0..df_chunk_height)
-                .map(|row_idx| {
-                    let values = df_columns
-                        .iter()
-                        .map(|column| {
-                            let v = column.get(row_idx).unwrap();
-                            preprocess_value(&v)
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-
-                    format!("({})", values)
-                })
-                .collect::<Vec<String>>()
-                .join(", ");
-
-            let query = format!(
-                "INSERT INTO {schema_name}.{table_name} ({fields}) VALUES {values}",
-                schema_name = payload.schema_name,
-                table_name = payload.table_name,
-            );
 
-            let insert_result = client.execute(query.as_str(), &[]).await;
-
-            match insert_result {
-                Ok(_) => (),
+            match copy_chunk(&client, &copy_target, &df_chunk).await {
+                Ok(copied_rows) => debug!("Copied {copied_rows} rows"),
                 Err(e) => {
                     error!("DF height at point: {df_height}");
                     error!("DF chunk height at point: {df_chunk_height}");
                     error!("Offset at point: {offset}");
-                    error!("Failed to insert: {e}");
                     error!(
-                        "Failed to insert data into table -> {}: {e}",
+                        "Failed to copy data into table -> {}: {e}",
                         payload.table_name
                     );
-                    panic!(
-                        "Failed query: {}",
-                        query.chars().take(1000).collect::<String>()
-                    );
+                    panic!("Failed query: {}", copy_target.query);
                 }
             }
 
-            offset += rows_per_df.to_f64().unwrap();
+            offset += rows_per_df;
 
             if should_delay_insert {
                 tokio::time::sleep(insert_delay).await;
@@ -311,128 +577,79 @@ impl PostgresOperator for PostgresOperatorImpl {
         &self,
         df: &DataFrame,
         payload: &UpsertDataframePayload,
-    ) -> Result<()> {
-        let mut row_values = Vec::new();
-        let mut deleted_row: bool;
-
-        let mut column_names = df.get_column_names_str();
-        column_names.retain(|column| *column != "Op" && *column != "_dms_ingestion_timestamp");
-
-        let fields = column_names.join(", ");
-        let client = self.pool.get().await?;
-
-        for row in 0..df.height() {
-            row_values.clear();
-            deleted_row = false;
-
-            let pk_vector = payload
-                .primary_key
-                .split(',')
-                .map(|key| df.column(key).unwrap().get(row).unwrap().to_string())
-                .collect::<Vec<String>>();
-
-            for column in df.get_columns() {
-                // Operation: Delete
-                // Delete the rows where Op="D"
-                let column_name = column.name();
-                let is_op = column_name == "Op";
-
-                let value = column.get(row).unwrap();
-                let is_delete = value.to_string().contains('D');
-                let is_op_and_delete = is_op && is_delete;
-
-                if !is_op_and_delete {
-                    row_values.push(value);
-                    continue;
-                }
-
-                let query = DeleteRows(
-                    payload.schema_name.clone(),
-                    payload.table_name.clone(),
-                    payload.primary_key.clone(),
-                    pk_vector.as_slice().join(","),
-                );
-                trace!("Query: {}", query);
-
-                let query = query.to_string().replace('"', "'");
-
-                client.query(&query, &[]).await.unwrap_or_else(|_| {
-                    panic!(
-                        "Failed to delete rows from table: {schema_name}.{table_name}",
-                        schema_name = payload.schema_name.clone(),
-                        table_name = payload.table_name.clone()
-                    )
-                });
-
-                deleted_row = true;
-                break;
-            }
-
-            if deleted_row {
-                debug!("Deleted row");
-                continue;
-            }
-
-            // Operation: Update
-            let is_update_op = row_values.first().unwrap().to_string().contains('U');
-
-            debug!("Row values: {:?}", row_values);
-
-            // Remove the Op and _dms_ingestion_timestamp column from the row values
-            let row_values = row_values.iter().skip(2).collect::<Vec<_>>();
-            let values_of_row = row_values
-                .iter()
-                .map(|v| RowStruct::new(v).displayed())
-                .collect::<Vec<_>>()
-                .join(", ");
-
-            let on_conflict_strategy = if !is_update_op {
-                String::from("")
-            } else {
-                let column_names = column_names
-                    .clone()
-                    .into_iter()
-                    .enumerate()
-                    .map(|(index, column)| {
-                        format!(
-                            "{} = {}",
-                            column,
-                            RowStruct::new(row_values.get(index).unwrap()).displayed()
-                        )
-                    })
-                    .collect::<Vec<_>>();
-
-                // Construct the query, on Conflict, update the row
-                let strategy = format!(" ON CONFLICT ({}) DO UPDATE SET ", payload.primary_key);
-                let concatenated_values = column_names.join(", ");
-
-                format!("{strategy} {concatenated_values}")
-            };
-
-            let query = format!(
-                "INSERT INTO {schema_name}.{table_name} ({fields}) VALUES ({values_of_row});",
-                schema_name = payload.schema_name,
-                table_name = payload.table_name,
-            );
-            let query = format!("{query}{on_conflict_strategy}");
-
-            trace!("Query: {}", query);
-
-            let client = self.pool.get().await?;
-
-            client
-                .execute(query.as_str(), &[])
+    ) -> Result<Vec<CdcFileApplyReport>> {
+        let dms_metadata_columns = &payload.dms_metadata_columns;
+
+        // Number the changes in the order DMS captured them,
+        // so that only the last change of each key is applied
+        let df = sort_by_ingestion_timestamp(df, &dms_metadata_columns.timestamp_column)?;
+
+        let mut client = self.pool.get().await?;
+        let target_columns =
+            column_definitions(&client, &payload.schema_name, &payload.table_name).await?;
+        let table_columns = column_names(&target_columns);
+        let df = drop_generated_columns(&df, &target_columns);
+        let identity_columns = target_columns
+            .iter()
+            .filter(|column| column.identity == Some(IdentityGeneration::Always))
+            .map(|column| column.name.clone())
+            .collect::<Vec<_>>();
+
+        // Rows of a table without a key are matched on the values of all their columns
+        let is_full_row = payload.primary_keys.is_empty();
+        let keys = if is_full_row {
+            df.get_column_names_str()
+                .into_iter()
+                .filter(|column| {
+                    !dms_metadata_columns.is_metadata_column(column, &table_columns)
+                        && !is_change_column(column)
+                })
+                .map(String::from)
+                .collect()
+        } else {
+            payload.primary_keys.clone()
+        };
+        let has_before_images = dms_metadata_columns.before_image_keys(&df, &keys).is_some();
+        let df = if is_full_row {
+            dms_metadata_columns.split_updates(&df, &keys)?
+        } else {
+            dms_metadata_columns.split_key_changes(&df, &keys)?
+        };
+        // The timestamps of the changes are only compared for the rows with a key
+        let changes = dms_metadata_columns.changes_from(
+            &df,
+            &table_columns,
+            payload.apply_mode.is_idempotent() && !is_full_row,
+        )?;
+
+        let server_version = server_version_num(&client).await?;
+
+        let transaction = client.transaction().await?;
+        let reports = if is_full_row {
+            apply_full_row_changes(&transaction, &changes, payload, &keys, has_before_images)
                 .await
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "Failed to upsert data in table: {schema_name}.{table_name}",
-                        schema_name = payload.schema_name.clone(),
-                        table_name = payload.table_name.clone()
-                    )
-                });
+                .map(|_| Vec::new())
+        } else {
+            apply_changes(
+                &transaction,
+                &changes,
+                payload,
+                &identity_columns,
+                server_version,
+                has_before_images,
+            )
+            .await
         }
+        .map_err(|e| {
+            anyhow!(
+                "Failed to upsert data in table: {schema_name}.{table_name}: {e}",
+                schema_name = payload.schema_name,
+                table_name = payload.table_name
+            )
+        })?;
+        transaction.commit().await?;
 
-        Ok(())
+        Ok(reports)
     }
 
     async fn run_sql_command(&self, sql_command: &str) -> Result<()> {
@@ -454,7 +671,7 @@ impl PostgresOperator for PostgresOperatorImpl {
 // Use Env Vars to tune Insert chunk size/speed
 fn rows_per_df(payload: &InsertDataframePayload) -> usize {
     if !INSERT_DELAYABLES.contains(&payload.as_key()) {
-        return 10_000;
+        return ROWS_PER_COPY;
     }
     std::env::var("ROWS_PER_DF")
         .unwrap_or(10_000.to_string())
@@ -481,26 +698,454 @@ fn insert_delay() -> std::time::Duration {
     )
 }
 
-fn preprocess_value(value: &AnyValue) -> String {
-    match value {
-        AnyValue::String(_) | AnyValue::StringOwned(_) => {
-            let string_value = &value.str_value();
-            let potential_geometry_value = string_value.to_string();
-            let potential_geometry_value = potential_geometry_value.trim();
-            let postgres_geometry_type = PostgresGeometryType::new(potential_geometry_value, 0);
-            if postgres_geometry_type.is_geometry_type() {
-                let formatted_geometry_value =
-                    postgres_geometry_type.format_value(potential_geometry_value);
-                debug!("Formatted Geometry value: {formatted_geometry_value}");
-                formatted_geometry_value
-            } else {
-                RowStruct::FromString(string_value.to_string()).displayed()
+/// A COPY into a table, with the encoding matching the exact types of its columns.
+struct CopyTarget {
+    query: String,
+    format: CopyFormat,
+    pg_types: Vec<Type>,
+    /// The conversion of the values of each column to the text input of its type.
+    converters: Vec<PostgresValueConverter>,
+}
+
+async fn prepare_copy(
+    client: &Client,
+    schema_name: &str,
+    table_name: &str,
+    df: &DataFrame,
+    geometry_srids: &HashMap<String, i32>,
+) -> Result<CopyTarget> {
+    let columns = df
+        .get_column_names_str()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let query = SelectNoRows(
+        schema_name.to_string(),
+        table_name.to_string(),
+        columns.clone(),
+    );
+    let statement = client.prepare(&query.to_string()).await?;
+    let pg_types = statement
+        .columns()
+        .iter()
+        .map(|column| column.type_().clone())
+        .collect::<Vec<_>>();
+
+    // Values are converted for the declared type of their column, not by their content
+    let session_timezone = session_timezone(client).await?;
+    let converters = columns
+        .iter()
+        .zip(&pg_types)
+        .map(|(column, pg_type)| {
+            PostgresValueConverter::for_column(
+                pg_type,
+                geometry_srids.get(column).copied(),
+                session_timezone,
+            )
+        })
+        .collect();
+
+    let format = CopyFormat::for_columns(&df.dtypes(), &pg_types);
+    let query = CopyFromStdin(
+        schema_name.to_string(),
+        table_name.to_string(),
+        columns,
+        format.to_string(),
+    )
+    .to_string();
+
+    Ok(CopyTarget {
+        query,
+        format,
+        pg_types,
+        converters,
+    })
+}
+
+/// Stream a DataFrame into the COPY target, returning the number of copied rows.
+async fn copy_chunk(client: &Client, copy_target: &CopyTarget, df: &DataFrame) -> Result<u64> {
+    match copy_target.format {
+        CopyFormat::Binary => {
+            copy_binary_chunk(client, &copy_target.query, df, &copy_target.pg_types).await
+        }
+        CopyFormat::Csv => {
+            copy_csv_chunk(client, &copy_target.query, df, &copy_target.converters).await
+        }
+    }
+}
+
+/// Stream a DataFrame with the binary COPY format, returning the number of copied rows.
+async fn copy_binary_chunk(
+    client: &Client,
+    copy_query: &str,
+    df: &DataFrame,
+    pg_types: &[Type],
+) -> Result<u64> {
+    let sink = client.copy_in(copy_query).await?;
+    let mut writer = pin!(BinaryCopyInWriter::new(sink, pg_types));
+
+    let columns = materialized_columns(df);
+    let mut column_iters = columns
+        .iter()
+        .map(|series| series.iter())
+        .collect::<Vec<_>>();
+
+    for _ in 0..df.height() {
+        let row = column_iters
+            .iter_mut()
+            .zip(pg_types)
+            .map(|(values, pg_type)| CopyValue::new(values.next().unwrap(), pg_type))
+            .collect::<Result<Vec<_>>>()?;
+        writer.as_mut().write_raw(row).await?;
+    }
+
+    Ok(writer.finish().await?)
+}
+
+/// Stream a DataFrame with the CSV COPY format, returning the number of copied rows.
+async fn copy_csv_chunk(
+    client: &Client,
+    copy_query: &str,
+    df: &DataFrame,
+    converters: &[PostgresValueConverter],
+) -> Result<u64> {
+    let sink = client.copy_in::<_, Bytes>(copy_query).await?;
+    let mut sink = pin!(sink);
+
+    let columns = materialized_columns(df);
+    let mut column_iters = columns
+        .iter()
+        .map(|series| series.iter())
+        .collect::<Vec<_>>();
+    let mut buf = Vec::with_capacity(CSV_COPY_BUFFER_SIZE);
+
+    for _ in 0..df.height() {
+        let fields = column_iters
+            .iter_mut()
+            .zip(converters)
+            .map(|(values, converter)| converter.copy_text_value(&values.next().unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+        write_csv_fields(&mut buf, &fields);
+
+        if buf.len() >= CSV_COPY_BUFFER_SIZE {
+            sink.send(Bytes::from(std::mem::take(&mut buf))).await?;
+        }
+    }
+
+    if !buf.is_empty() {
+        sink.send(Bytes::from(buf)).await?;
+    }
+
+    Ok(sink.as_mut().finish().await?)
+}
+
+fn materialized_columns(df: &DataFrame) -> Vec<Series> {
+    df.get_columns()
+        .iter()
+        .map(|column| column.as_materialized_series().rechunk())
+        .collect()
+}
+
+/// Get the definitions of the columns of a table from the catalog.
+async fn column_definitions(
+    client: &Client,
+    schema_name: &str,
+    table_name: &str,
+) -> Result<Vec<ColumnDefinition>> {
+    let rows = client
+        .query(&FindAllColumns.to_string(), &[&schema_name, &table_name])
+        .await?;
+
+    let columns = rows
+        .iter()
+        .map(|row| {
+            // The expression of a generated column is stored as its default
+            let default: Option<String> = row.get("column_default");
+            let is_generated = !row.get::<_, &str>("generated").is_empty();
+            ColumnDefinition {
+                name: row.get("column_name"),
+                data_type: row.get("data_type"),
+                not_null: row.get("not_null"),
+                default: default.clone().filter(|_| !is_generated),
+                identity: IdentityGeneration::from_attidentity(row.get("identity")),
+                generated: default.filter(|_| is_generated),
             }
+        })
+        .collect();
+
+    Ok(columns)
+}
+
+/// Get the comment of a schema, which is none if the schema does not exist.
+async fn schema_comment(client: &Client, schema_name: &str) -> Result<Option<Option<String>>> {
+    let row = client
+        .query_opt(&FindSchemaComment.to_string(), &[&schema_name])
+        .await?;
+    Ok(row.map(|row| row.get("comment")))
+}
+
+/// Get the names of the columns of a table, which tell them from the columns added by DMS.
+fn column_names(columns: &[ColumnDefinition]) -> Vec<String> {
+    columns.iter().map(|column| column.name.clone()).collect()
+}
+
+/// Drop the columns that the target table generates from the other columns of the row.
+fn drop_generated_columns(df: &DataFrame, target_columns: &[ColumnDefinition]) -> DataFrame {
+    let generated_columns = target_columns
+        .iter()
+        .filter(|column| !column.is_writable())
+        .map(|column| column.name.as_str());
+    df.drop_many(generated_columns)
+}
+
+/// Get the `TimeZone` of the session, in which Postgres reads and shows wall times.
+async fn session_timezone(client: &Client) -> Result<Tz> {
+    let row = client.query_one(&SessionTimezone.to_string(), &[]).await?;
+    let session_timezone = row.get::<_, String>(0);
+    session_timezone
+        .parse::<Tz>()
+        .map_err(|e| anyhow!("Unknown session timezone {session_timezone}: {e}"))
+}
+
+async fn server_version_num(client: &Client) -> Result<i32> {
+    let row = client.query_one(&ServerVersion.to_string(), &[]).await?;
+    let server_version = row.get::<_, String>(0).parse::<i32>()?;
+    Ok(server_version)
+}
+
+/// Apply a change set in a transaction, through a staging table reduced to
+/// the last change of each primary key.
+async fn apply_changes(
+    transaction: &Transaction<'_>,
+    changes: &DataFrame,
+    payload: &UpsertDataframePayload,
+    identity_columns: &[String],
+    server_version: i32,
+    has_before_images: bool,
+) -> Result<Vec<CdcFileApplyReport>> {
+    let schema_name = payload.schema_name.clone();
+    let table_name = payload.table_name.clone();
+    let is_idempotent = payload.apply_mode.is_idempotent();
+    let upsert_inserts = payload.cdc_semantics.upserts_inserts() || is_idempotent;
+
+    let staged_columns = changes
+        .get_column_names_str()
+        .into_iter()
+        .filter(|column| !is_change_column(column))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let primary_keys = payload.primary_keys.clone();
+
+    // Changes older than the last change applied to their key are skipped when the files have
+    // the timestamp column, which is kept next to the target table rather than in it
+    let timestamp_column = Some(payload.dms_metadata_columns.timestamp_column.clone())
+        .filter(|timestamp_column| is_idempotent && staged_columns.contains(timestamp_column));
+    let columns = staged_columns
+        .iter()
+        .filter(|column| Some(*column) != timestamp_column.as_ref())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let query = CreateStagingTable(
+        schema_name.clone(),
+        table_name.clone(),
+        columns.clone(),
+        timestamp_column.clone(),
+    );
+    transaction.batch_execute(&query.to_string()).await?;
+
+    let copy_target = prepare_copy(
+        transaction.client(),
+        "pg_temp",
+        STAGING_TABLE,
+        changes,
+        &payload.geometry_srids,
+    )
+    .await?;
+    let staged_rows = copy_chunk(transaction.client(), &copy_target, changes).await?;
+    debug!("Staged {staged_rows} changes for table: {table_name}");
+
+    // Compacted changes already have a single change per key
+    let query = if payload.is_compacted {
+        UseStagingTableAsChanges
+    } else {
+        ReduceStagingTable(staged_columns, primary_keys.clone())
+    };
+    transaction.batch_execute(&query.to_string()).await?;
+
+    // Count the tolerated changes against the rows before they are applied
+    let reports = if is_idempotent {
+        let query = CountConflictingChanges(
+            schema_name.clone(),
+            table_name.clone(),
+            primary_keys.clone(),
+            timestamp_column.clone(),
+        );
+        transaction
+            .query(&query.to_string(), &[])
+            .await?
+            .iter()
+            .map(|row| CdcFileApplyReport {
+                file_name: row.get("file_name"),
+                upserted_inserts: row.get("upserted_inserts"),
+                skipped_deletes: row.get("skipped_deletes"),
+                skipped_stale_updates: row.get("skipped_stale_updates"),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if let Some(timestamp_column) = &timestamp_column {
+        let query = SkipStaleChanges(
+            schema_name.clone(),
+            table_name.clone(),
+            primary_keys.clone(),
+            timestamp_column.clone(),
+        );
+        trace!("Query: {}", query);
+        transaction.execute(&query.to_string(), &[]).await?;
+    }
+
+    // Without before-images, an update of the primary key looks like an update of a missing key
+    if !has_before_images {
+        let query = CountUpdatesOfMissingKeys(
+            schema_name.clone(),
+            table_name.clone(),
+            primary_keys.clone(),
+        );
+        let missing_keys = transaction
+            .query_one(&query.to_string(), &[])
+            .await?
+            .get::<_, i64>(0);
+        if missing_keys > 0 {
+            warn!(
+                "{missing_keys} updates of table {schema_name}.{table_name} are of keys that are not in the table. \
+                If their primary key changed on the source, the rows with the old keys remain in the table: \
+                enable DMS before-images of the primary key and set the before-image column prefix to apply key changes"
+            );
         }
-        _ => {
-            debug!("On other =>: {}", RowStruct::new(value).displayed());
-            debug!("Type of value: {}", value.dtype());
-            RowStruct::new(value).displayed()
+    }
+
+    // MERGE is only available from Postgres 15
+    let queries = if server_version >= MERGE_MIN_SERVER_VERSION {
+        vec![MergeChanges(
+            schema_name.clone(),
+            table_name.clone(),
+            columns.clone(),
+            primary_keys.clone(),
+            identity_columns.to_vec(),
+            upsert_inserts,
+        )]
+    } else {
+        vec![
+            DeleteChanges(
+                schema_name.clone(),
+                table_name.clone(),
+                primary_keys.clone(),
+            ),
+            UpsertChanges(
+                schema_name.clone(),
+                table_name.clone(),
+                columns.clone(),
+                primary_keys.clone(),
+                identity_columns.to_vec(),
+                upsert_inserts,
+            ),
+        ]
+    };
+
+    for query in queries {
+        trace!("Query: {}", query);
+        transaction.execute(&query.to_string(), &[]).await?;
+    }
+
+    // Inserts of keys that already exist fail, as they do on the source, unless they are upserted
+    if !upsert_inserts {
+        let query = InsertChanges(schema_name.clone(), table_name.clone(), columns);
+        trace!("Query: {}", query);
+        transaction.execute(&query.to_string(), &[]).await?;
+    }
+
+    if let Some(timestamp_column) = timestamp_column {
+        let query =
+            RecordAppliedTimestamps(schema_name, table_name, primary_keys, timestamp_column);
+        trace!("Query: {}", query);
+        transaction.execute(&query.to_string(), &[]).await?;
+    }
+
+    Ok(reports)
+}
+
+/// Apply the change set of a table without a key in a transaction, matching the rows
+/// on the values of all their columns.
+async fn apply_full_row_changes(
+    transaction: &Transaction<'_>,
+    changes: &DataFrame,
+    payload: &UpsertDataframePayload,
+    match_columns: &[String],
+    has_before_images: bool,
+) -> Result<()> {
+    let schema_name = payload.schema_name.clone();
+    let table_name = payload.table_name.clone();
+
+    let columns = changes
+        .get_column_names_str()
+        .into_iter()
+        .filter(|column| !is_change_column(column))
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    // Without before-images, the row an update replaces is not known
+    if !has_before_images {
+        let updates = changes
+            .column(CHANGE_OP_COLUMN)?
+            .str()?
+            .into_no_null_iter()
+            .filter(|op| *op == "U")
+            .count();
+        if updates > 0 {
+            warn!(
+                "{updates} updates of table {schema_name}.{table_name}, which has no key, are applied as inserts \
+                and the rows before them remain in the table: enable DMS before-images of all the columns \
+                and set the before-image column prefix to apply them"
+            );
         }
     }
+
+    let query = CreateStagingTable(
+        schema_name.clone(),
+        table_name.clone(),
+        columns.clone(),
+        None,
+    );
+    transaction.batch_execute(&query.to_string()).await?;
+
+    let copy_target = prepare_copy(
+        transaction.client(),
+        "pg_temp",
+        STAGING_TABLE,
+        changes,
+        &payload.geometry_srids,
+    )
+    .await?;
+    let staged_rows = copy_chunk(transaction.client(), &copy_target, changes).await?;
+    debug!("Staged {staged_rows} changes for table: {table_name}");
+
+    let queries = [
+        ReduceFullRowChanges(match_columns.to_vec(), columns.clone()),
+        DeleteFullRowChanges(
+            schema_name.clone(),
+            table_name.clone(),
+            match_columns.to_vec(),
+        ),
+        InsertFullRowChanges(schema_name, table_name, columns),
+    ];
+    for query in queries {
+        trace!("Query: {}", query);
+        transaction.batch_execute(&query.to_string()).await?;
+    }
+
+    Ok(())
 }
@@ -3,10 +3,20 @@ mod tests {
     use indexmap::IndexMap;
     use mockall::predicate::*;
     use polars::prelude::*;
+    use std::collections::HashMap;
 
+    use crate::cdc::apply_mode::ApplyModeValueEnum;
+    use crate::cdc::cdc_semantics::CdcSemanticsValueEnum;
+    use crate::dataframe::dms_metadata_columns::DmsMetadataColumns;
+    use crate::postgres::column_definition::{ColumnDefinition, IdentityGeneration};
     use crate::postgres::postgres_operator::{
-        InsertDataframePayload, MockPostgresOperator, PostgresOperator, UpsertDataframePayload,
+        CdcFileApplyReport, InsertDataframePayload, MockPostgresOperator, PostgresOperator,
+        UpsertDataframePayload,
     };
+    use crate::postgres::row_identity::RowIdentity;
+    use crate::postgres::source_table::{SourceTable, TableKind};
+    use crate::postgres::table_constraint::{ConstraintKind, TableConstraint};
+    use crate::postgres::user_defined_type::{TypeKind, UserDefinedType};
 
     #[tokio::test]
     async fn test_get_table_columns() {
@@ -31,6 +41,33 @@ mod tests {
         assert_eq!(result.get("column2").unwrap(), "text");
     }
 
+    #[tokio::test]
+    async fn test_get_column_definitions() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_get_column_definitions()
+            .times(1)
+            .with(eq("schema"), eq("table"))
+            .returning(|_, _| {
+                Ok(vec![
+                    ColumnDefinition {
+                        not_null: true,
+                        identity: Some(IdentityGeneration::Always),
+                        ..ColumnDefinition::new("id", "bigint")
+                    },
+                    ColumnDefinition::new("tags", "character varying(20)[]"),
+                ])
+            });
+
+        let result = postgres_operator
+            .get_column_definitions("schema", "table")
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].identity, Some(IdentityGeneration::Always));
+        assert_eq!(result[1].data_type, "character varying(20)[]");
+    }
+
     #[tokio::test]
     async fn test_get_primary_key() {
         let mut postgres_operator = MockPostgresOperator::new();
@@ -47,22 +84,121 @@ mod tests {
         assert_eq!(result, vec!["primary_key"]);
     }
 
+    #[tokio::test]
+    async fn test_get_unique_key() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_get_unique_key()
+            .times(1)
+            .with(eq("table"), eq("schema"))
+            .returning(|_, _| Ok(vec!["code".to_string()]));
+
+        let result = postgres_operator
+            .get_unique_key("table", "schema")
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["code"]);
+    }
+
+    #[tokio::test]
+    async fn test_has_duplicate_keys() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_has_duplicate_keys()
+            .times(1)
+            .with(eq(vec!["code".to_string()]), eq("table"), eq("schema"))
+            .returning(|_, _, _| Ok(false));
+
+        let result = postgres_operator
+            .has_duplicate_keys(&["code".to_string()], "table", "schema")
+            .await
+            .unwrap();
+        assert!(!result);
+    }
+
+    #[tokio::test]
+    async fn test_get_constraints() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_get_constraints()
+            .times(1)
+            .with(eq("orders"), eq("schema"))
+            .returning(|_, _| {
+                Ok(vec![TableConstraint {
+                    name: "orders_customer_id_fkey".to_string(),
+                    kind: ConstraintKind::ForeignKey,
+                    definition: "FOREIGN KEY (customer_id) REFERENCES customers(id)".to_string(),
+                    referenced_table: Some(("schema".to_string(), "customers".to_string())),
+                }])
+            });
+
+        let result = postgres_operator
+            .get_constraints("orders", "schema")
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].referenced_table_in("schema"), Some("customers"));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_defined_types() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_get_user_defined_types()
+            .times(1)
+            .with(eq("table"), eq("schema"))
+            .returning(|_, _| {
+                Ok(vec![UserDefinedType {
+                    schema_name: "schema".to_string(),
+                    name: "mood".to_string(),
+                    kind: TypeKind::Enum,
+                    extension: None,
+                    definition: Some("AS ENUM ('sad', 'happy')".to_string()),
+                }])
+            });
+
+        let result = postgres_operator
+            .get_user_defined_types("table", "schema")
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].kind, TypeKind::Enum);
+    }
+
+    #[tokio::test]
+    async fn test_get_geometry_srids() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_get_geometry_srids()
+            .times(1)
+            .with(eq("table"), eq("schema"))
+            .returning(|_, _| Ok(HashMap::from([("location".to_string(), 4326)])));
+
+        let result = postgres_operator
+            .get_geometry_srids("table", "schema")
+            .await
+            .unwrap();
+        assert_eq!(result.get("location"), Some(&4326));
+    }
+
     #[tokio::test]
     async fn test_create_table() {
         let mut postgres_operator = MockPostgresOperator::new();
         postgres_operator
             .expect_create_table()
             .times(1)
-            .returning(|_, _, _, _| Ok(()));
+            .returning(|_, _, _, _, _| Ok(()));
 
-        let mut column_data_types = IndexMap::new();
-        column_data_types.insert("column1".to_string(), "text".to_string());
-        column_data_types.insert("column2".to_string(), "text".to_string());
+        let columns = vec![
+            ColumnDefinition::new("column1", "text"),
+            ColumnDefinition::new("column2", "numeric(10,2)"),
+        ];
 
         postgres_operator
             .create_table(
-                &column_data_types,
-                vec!["primary_key".to_string()].as_slice(),
+                &columns,
+                &RowIdentity::PrimaryKey(vec!["primary_key".to_string()]),
+                None,
                 "schema",
                 "table",
             )
@@ -70,6 +206,30 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn test_create_partition() {
+        let mut postgres_operator = MockPostgresOperator::new();
+        postgres_operator
+            .expect_create_partition()
+            .times(1)
+            .withf(|partition, schema_name| {
+                partition.parent.as_deref() == Some("events") && schema_name == "schema"
+            })
+            .returning(|_, _| Ok(()));
+
+        let partition = SourceTable {
+            parent: Some("events".to_string()),
+            root: Some("events".to_string()),
+            partition_bound: Some("DEFAULT".to_string()),
+            ..SourceTable::new("events_default", TableKind::Partition)
+        };
+
+        postgres_operator
+            .create_partition(&partition, "schema")
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_insert_dataframe_in_target_db() {
         let mut postgres_operator = MockPostgresOperator::new();
@@ -83,6 +243,9 @@ mod tests {
             database_name: "database".to_string(),
             schema_name: "schema".to_string(),
             table_name: "table".to_string(),
+            dms_metadata_columns: DmsMetadataColumns::default(),
+            cdc_semantics: CdcSemanticsValueEnum::default(),
+            geometry_srids: HashMap::new(),
         };
 
         postgres_operator
@@ -97,19 +260,31 @@ mod tests {
         postgres_operator
             .expect_upsert_dataframe_in_target_db()
             .times(1)
-            .returning(|_, _| Ok(()));
+            .returning(|_, _| {
+                Ok(vec![CdcFileApplyReport {
+                    file_name: Some("file.parquet".to_string()),
+                    skipped_deletes: 1,
+                    ..CdcFileApplyReport::default()
+                }])
+            });
 
         let df = DataFrame::new(vec![Series::new("column1".into(), &[1, 2, 3]).into()]).unwrap();
         let payload = UpsertDataframePayload {
             database_name: "database".to_string(),
             schema_name: "schema".to_string(),
             table_name: "table".to_string(),
-            primary_key: "primary_key".to_string(),
+            primary_keys: vec!["primary_key".to_string()],
+            dms_metadata_columns: DmsMetadataColumns::default(),
+            cdc_semantics: CdcSemanticsValueEnum::default(),
+            apply_mode: ApplyModeValueEnum::Idempotent,
+            is_compacted: false,
+            geometry_srids: HashMap::new(),
         };
-        postgres_operator
+        let reports = postgres_operator
             .upsert_dataframe_in_target_db(&df, &payload)
             .await
             .unwrap();
+        assert_eq!(reports[0].skipped_deletes, 1);
     }
 
     #[tokio::test]
@@ -0,0 +1,483 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use polars::prelude::{AnyValue, TimeUnit};
+use tokio_postgres::types::{Kind, Type};
+
+use super::postgres_copy::{copy_text_value, datetime_from_timestamp, hex_encode, strip_nul};
+use super::postgres_geometry_type::PostgresGeometryType;
+
+/// Converts the values of a DataFrame column to the text input of the type of its target column.
+///
+/// Values are converted by [`copy_text_value`] unless the target type reads them differently,
+/// e.g. binary values are the bytes of a uuid, and durations are intervals.
+///
+/// Datetimes without a timezone are wall times, which Postgres reads in the timezone of the
+/// session, and datetimes with a timezone are instants, shown in the timezone of the session
+/// by the types without one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PostgresValueConverter {
+    Text,
+    Boolean,
+    Integer,
+    Json,
+    Jsonb,
+    Uuid,
+    Bytea,
+    Network,
+    Timestamp(Tz),
+    TimestampTz(Tz),
+    Date(Tz),
+    Time(Tz),
+    TimeTz(Tz),
+    Geometry(PostgresGeometryType),
+    Other,
+}
+
+impl PostgresValueConverter {
+    /// Gets the converter of a target column, from its type or the base type of its domain.
+    ///
+    /// # Arguments
+    ///
+    /// * `pg_type` - The type of the target column.
+    /// * `geometry_srid` - The SRID of the source column, if it is a PostGIS column.
+    /// * `session_timezone` - The `TimeZone` of the session that writes the values.
+    pub fn for_column(pg_type: &Type, geometry_srid: Option<i32>, session_timezone: Tz) -> Self {
+        let mut pg_type = pg_type;
+        while let Kind::Domain(base_type) = pg_type.kind() {
+            pg_type = base_type;
+        }
+
+        match *pg_type {
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => PostgresValueConverter::Text,
+            Type::BOOL => PostgresValueConverter::Boolean,
+            Type::INT2 | Type::INT4 | Type::INT8 => PostgresValueConverter::Integer,
+            Type::JSON => PostgresValueConverter::Json,
+            Type::JSONB => PostgresValueConverter::Jsonb,
+            Type::UUID => PostgresValueConverter::Uuid,
+            Type::BYTEA => PostgresValueConverter::Bytea,
+            Type::INET | Type::CIDR | Type::MACADDR | Type::MACADDR8 => {
+                PostgresValueConverter::Network
+            }
+            Type::TIMESTAMP => PostgresValueConverter::Timestamp(session_timezone),
+            Type::TIMESTAMPTZ => PostgresValueConverter::TimestampTz(session_timezone),
+            Type::DATE => PostgresValueConverter::Date(session_timezone),
+            Type::TIME => PostgresValueConverter::Time(session_timezone),
+            Type::TIMETZ => PostgresValueConverter::TimeTz(session_timezone),
+            _ if PostgresGeometryType::is_geometry_type(pg_type.name()) => {
+                PostgresValueConverter::Geometry(PostgresGeometryType::new(
+                    geometry_srid.unwrap_or_default(),
+                ))
+            }
+            _ => PostgresValueConverter::Other,
+        }
+    }
+
+    /// Renders a value in the text representation the target column type accepts as input.
+    ///
+    /// Returns `None` for nulls.
+    pub fn copy_text_value(&self, value: &AnyValue) -> Result<Option<String>> {
+        if let PostgresValueConverter::Geometry(geometry_type) = self {
+            return geometry_type.copy_text_value(value);
+        }
+
+        let text = match (self, value) {
+            (_, AnyValue::Null) => return Ok(None),
+            (PostgresValueConverter::Text, AnyValue::Boolean(v)) => v.to_string(),
+            (PostgresValueConverter::Boolean, v) if v.is_integer() => {
+                let is_true = v.extract::<i64>().is_some_and(|integer| integer != 0);
+                if is_true { "t" } else { "f" }.to_string()
+            }
+            (PostgresValueConverter::Integer, AnyValue::Boolean(v)) => u8::from(*v).to_string(),
+            // Postgres does not store NUL characters, and jsonb does not read their escapes
+            (PostgresValueConverter::Jsonb, AnyValue::String(_) | AnyValue::StringOwned(_)) => {
+                strip_json_nul_escapes(&strip_nul(&value.str_value()))
+            }
+            (PostgresValueConverter::Uuid, AnyValue::Binary(bytes)) if bytes.len() == 16 => {
+                format_uuid(bytes)
+            }
+            (PostgresValueConverter::Uuid, AnyValue::BinaryOwned(bytes)) if bytes.len() == 16 => {
+                format_uuid(bytes)
+            }
+            // Text with NUL bytes is read as bytes, which the escape format can not represent
+            (PostgresValueConverter::Bytea, AnyValue::String(_) | AnyValue::StringOwned(_))
+                if value.str_value().contains('\0') =>
+            {
+                format!("\\x{}", hex_encode(value.str_value().as_bytes()))
+            }
+            (
+                PostgresValueConverter::Uuid | PostgresValueConverter::Network,
+                AnyValue::String(_) | AnyValue::StringOwned(_),
+            ) => value.str_value().trim().to_string(),
+            (_, AnyValue::Datetime(v, unit, tz)) if self.is_temporal() => {
+                self.format_datetime(*v, *unit, tz.is_some())?
+            }
+            (_, AnyValue::DatetimeOwned(v, unit, tz)) if self.is_temporal() => {
+                self.format_datetime(*v, *unit, tz.is_some())?
+            }
+            (_, value) => return Ok(copy_text_value(value)),
+        };
+
+        Ok(Some(text))
+    }
+
+    fn is_temporal(&self) -> bool {
+        matches!(
+            self,
+            PostgresValueConverter::Timestamp(_)
+                | PostgresValueConverter::TimestampTz(_)
+                | PostgresValueConverter::Date(_)
+                | PostgresValueConverter::Time(_)
+                | PostgresValueConverter::TimeTz(_)
+        )
+    }
+
+    /// Formats a datetime, of the given unit since the epoch, for a temporal target type.
+    fn format_datetime(&self, value: i64, unit: TimeUnit, has_timezone: bool) -> Result<String> {
+        let instant = datetime_from_timestamp(value, unit)?;
+        let wall_time = |session_timezone: &Tz| -> NaiveDateTime {
+            if has_timezone {
+                instant.with_timezone(session_timezone).naive_local()
+            } else {
+                instant.naive_utc()
+            }
+        };
+
+        let text = match self {
+            PostgresValueConverter::TimestampTz(_) if has_timezone => instant.to_rfc3339(),
+            PostgresValueConverter::Timestamp(session_timezone)
+            | PostgresValueConverter::TimestampTz(session_timezone) => wall_time(session_timezone)
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string(),
+            PostgresValueConverter::Date(session_timezone) => {
+                wall_time(session_timezone).date().to_string()
+            }
+            PostgresValueConverter::TimeTz(session_timezone) if has_timezone => instant
+                .with_timezone(session_timezone)
+                .format("%H:%M:%S%.6f%:z")
+                .to_string(),
+            PostgresValueConverter::Time(session_timezone)
+            | PostgresValueConverter::TimeTz(session_timezone) => wall_time(session_timezone)
+                .format("%H:%M:%S%.6f")
+                .to_string(),
+            _ => instant
+                .naive_utc()
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string(),
+        };
+
+        Ok(text)
+    }
+}
+
+fn format_uuid(bytes: &[u8]) -> String {
+    let hex = hex_encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Removes the `\u0000` escapes of a JSON document, leaving escaped backslashes followed
+/// by `u0000` as they are.
+fn strip_json_nul_escapes(json: &str) -> String {
+    const NUL_ESCAPE: &str = "\\u0000";
+
+    let mut stripped = String::with_capacity(json.len());
+    let mut rest = json;
+    let mut escaped = false;
+    while let Some(c) = rest.chars().next() {
+        if !escaped && rest.starts_with(NUL_ESCAPE) {
+            rest = &rest[NUL_ESCAPE.len()..];
+            continue;
+        }
+        escaped = !escaped && c == '\\';
+        stripped.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::TimeZone;
+
+    fn text(converter: &PostgresValueConverter, value: AnyValue) -> Option<String> {
+        converter.copy_text_value(&value).unwrap()
+    }
+
+    #[test]
+    fn test_for_column() {
+        assert_eq!(
+            PostgresValueConverter::for_column(&Type::JSONB, None, Tz::UTC),
+            PostgresValueConverter::Jsonb
+        );
+        assert_eq!(
+            PostgresValueConverter::for_column(&Type::CIDR, None, Tz::UTC),
+            PostgresValueConverter::Network
+        );
+        assert_eq!(
+            PostgresValueConverter::for_column(&Type::NUMERIC, None, Tz::UTC),
+            PostgresValueConverter::Other
+        );
+
+        let email = Type::new(
+            "email".to_string(),
+            0,
+            Kind::Domain(Type::UUID),
+            "public".to_string(),
+        );
+        assert_eq!(
+            PostgresValueConverter::for_column(&email, None, Tz::UTC),
+            PostgresValueConverter::Uuid
+        );
+
+        let geometry = Type::new(
+            "geometry".to_string(),
+            0,
+            Kind::Simple,
+            "public".to_string(),
+        );
+        assert_eq!(
+            PostgresValueConverter::for_column(&geometry, Some(4326), Tz::UTC),
+            PostgresValueConverter::Geometry(PostgresGeometryType::new(4326))
+        );
+    }
+
+    #[test]
+    fn test_json_is_written_as_it_is() {
+        let document = r#"{"name": "ann", "tags": ["a", "b"], "nested": {"n": 1.5}}"#;
+        for converter in [PostgresValueConverter::Json, PostgresValueConverter::Jsonb] {
+            assert_eq!(
+                text(&converter, AnyValue::String(document)),
+                Some(document.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_jsonb_strips_nul() {
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Jsonb,
+                AnyValue::String("{\"a\": \"x\\u0000y\", \"b\": \"\\\\u0000\", \"c\": \"\0\"}")
+            ),
+            Some("{\"a\": \"xy\", \"b\": \"\\\\u0000\", \"c\": \"\"}".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Json, AnyValue::String("\"a\0b\"")),
+            Some("\"ab\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_uuid_from_bytes_and_text() {
+        let uuid = "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11";
+        let bytes = [
+            0xa0, 0xee, 0xbc, 0x99, 0x9c, 0x0b, 0x4e, 0xf8, 0xbb, 0x6d, 0x6b, 0xb9, 0xbd, 0x38,
+            0x0a, 0x11,
+        ];
+        assert_eq!(
+            text(&PostgresValueConverter::Uuid, AnyValue::Binary(&bytes)),
+            Some(uuid.to_string())
+        );
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Uuid,
+                AnyValue::String(&format!(" {uuid} "))
+            ),
+            Some(uuid.to_string())
+        );
+    }
+
+    #[test]
+    fn test_bytea_is_written_in_hex() {
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Bytea,
+                AnyValue::Binary(&[0, 1, 0xde, 0xad])
+            ),
+            Some("\\x0001dead".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Bytea, AnyValue::String("a\0b")),
+            Some("\\x610062".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Bytea, AnyValue::String("\\x0001")),
+            Some("\\x0001".to_string())
+        );
+    }
+
+    #[test]
+    fn test_duration_is_written_in_seconds() {
+        let one_day_and_a_half_second = 86_400_500_000;
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Other,
+                AnyValue::Duration(one_day_and_a_half_second, TimeUnit::Microseconds)
+            ),
+            Some("86400.500000 seconds".to_string())
+        );
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Other,
+                AnyValue::Duration(-1_500, TimeUnit::Milliseconds)
+            ),
+            Some("-1.500000 seconds".to_string())
+        );
+        assert_eq!(
+            text(
+                &PostgresValueConverter::Other,
+                AnyValue::String("1 day 02:00:00")
+            ),
+            Some("1 day 02:00:00".to_string())
+        );
+    }
+
+    #[test]
+    fn test_network_text_is_trimmed() {
+        for value in ["192.168.0.1/24", "2001:db8::/32", "08:00:2b:01:02:03"] {
+            assert_eq!(
+                text(
+                    &PostgresValueConverter::Network,
+                    AnyValue::String(&format!("{value}\n"))
+                ),
+                Some(value.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_time_text() {
+        let nanos = ((13 * 60 + 45) * 60 + 30) * 1_000_000_000 + 123_456_000;
+        assert_eq!(
+            text(&PostgresValueConverter::Other, AnyValue::Time(nanos)),
+            Some("13:45:30.123456".to_string())
+        );
+        // Postgres gives times without an offset the offset of the session
+        assert_eq!(
+            text(
+                &PostgresValueConverter::TimeTz(Tz::UTC),
+                AnyValue::Time(nanos)
+            ),
+            Some("13:45:30.123456".to_string())
+        );
+        assert_eq!(
+            text(
+                &PostgresValueConverter::TimeTz(Tz::UTC),
+                AnyValue::String("13:45:30+02")
+            ),
+            Some("13:45:30+02".to_string())
+        );
+    }
+
+    #[test]
+    fn test_timestamp_text_in_session_timezone() {
+        // 2024-02-14 09:00:00.123 UTC, as DMS writes it with millisecond timestamps
+        let millis = 1_707_901_200_123;
+        let utc = TimeZone::UTC;
+        let naive = AnyValue::Datetime(millis, TimeUnit::Milliseconds, None);
+        let instant = AnyValue::Datetime(millis, TimeUnit::Milliseconds, Some(&utc));
+        let athens = Tz::Europe__Athens;
+
+        assert_eq!(
+            text(&PostgresValueConverter::Timestamp(athens), naive.clone()),
+            Some("2024-02-14 09:00:00.123".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Timestamp(athens), instant.clone()),
+            Some("2024-02-14 11:00:00.123".to_string())
+        );
+        assert_eq!(
+            text(
+                &PostgresValueConverter::TimestampTz(athens),
+                instant.clone()
+            ),
+            Some("2024-02-14T09:00:00.123+00:00".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::TimestampTz(athens), naive.clone()),
+            Some("2024-02-14 09:00:00.123".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Time(athens), instant.clone()),
+            Some("11:00:00.123000".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::TimeTz(athens), instant),
+            Some("11:00:00.123000+02:00".to_string())
+        );
+    }
+
+    #[test]
+    fn test_timestamp_units() {
+        let utc = TimeZone::UTC;
+        let nanos = AnyValue::Datetime(1_707_901_200_123_456_789, TimeUnit::Nanoseconds, None);
+        assert_eq!(
+            text(&PostgresValueConverter::Timestamp(Tz::UTC), nanos),
+            Some("2024-02-14 09:00:00.123456789".to_string())
+        );
+
+        // 03:00 UTC is still the day before in New York
+        let micros = AnyValue::Datetime(1_707_879_600_000_000, TimeUnit::Microseconds, Some(&utc));
+        assert_eq!(
+            text(&PostgresValueConverter::Date(Tz::America__New_York), micros),
+            Some("2024-02-13".to_string())
+        );
+    }
+
+    #[test]
+    fn test_boolean_text_of_target_type() {
+        assert_eq!(
+            text(&PostgresValueConverter::Boolean, AnyValue::Boolean(true)),
+            Some("t".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Boolean, AnyValue::Int64(0)),
+            Some("f".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Text, AnyValue::Boolean(false)),
+            Some("false".to_string())
+        );
+        assert_eq!(
+            text(&PostgresValueConverter::Integer, AnyValue::Boolean(true)),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_float_special_values() {
+        for (value, expected) in [
+            (f64::NAN, "NaN"),
+            (f64::INFINITY, "Infinity"),
+            (f64::NEG_INFINITY, "-Infinity"),
+            (0.1, "0.1"),
+        ] {
+            assert_eq!(
+                text(&PostgresValueConverter::Other, AnyValue::Float64(value)),
+                Some(expected.to_string())
+            );
+        }
+        assert_eq!(
+            text(&PostgresValueConverter::Other, AnyValue::Float32(0.1)),
+            Some("0.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_text_strips_nul() {
+        assert_eq!(
+            text(&PostgresValueConverter::Text, AnyValue::String("a\0b")),
+            Some("ab".to_string())
+        );
+        assert_eq!(text(&PostgresValueConverter::Text, AnyValue::Null), None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::column_definition::ColumnDefinition;
+
+/// Represents how the changes of a table are matched to the rows of the target table.
+///
+/// The identity is resolved in order of preference:
+///
+/// * PrimaryKey - The primary key of the source table.
+/// * UniqueIndex - The columns of the first unique index of the source table.
+/// * KeyOverride - The key columns set by the user for the table.
+/// * FullRow - All the columns of the rows, for tables without any key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RowIdentity {
+    PrimaryKey(Vec<String>),
+    UniqueIndex(Vec<String>),
+    KeyOverride(Vec<String>),
+    FullRow,
+}
+
+impl RowIdentity {
+    /// Resolves the identity of a table from its keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary_key` - The primary key of the table, empty if it has none.
+    /// * `unique_key` - The columns of the first unique index of the table, empty if it has none.
+    /// * `key_override` - The key columns set by the user for the table.
+    pub fn resolve(
+        primary_key: Vec<String>,
+        unique_key: Vec<String>,
+        key_override: Option<Vec<String>>,
+    ) -> Self {
+        if !primary_key.is_empty() {
+            RowIdentity::PrimaryKey(primary_key)
+        } else if !unique_key.is_empty() {
+            RowIdentity::UniqueIndex(unique_key)
+        } else {
+            match key_override {
+                Some(key_override) if !key_override.is_empty() => {
+                    RowIdentity::KeyOverride(key_override)
+                }
+                _ => RowIdentity::FullRow,
+            }
+        }
+    }
+
+    /// Gets the columns that identify a row, or none if rows are matched on all their columns.
+    pub fn keys(&self) -> &[String] {
+        match self {
+            RowIdentity::PrimaryKey(keys)
+            | RowIdentity::UniqueIndex(keys)
+            | RowIdentity::KeyOverride(keys) => keys,
+            RowIdentity::FullRow => &[],
+        }
+    }
+
+    /// Whether the changes of the table can be replicated exactly.
+    ///
+    /// Rows matched on all their columns can have duplicates, and their updates
+    /// can only be matched when the CDC files have before-images of all the columns.
+    pub fn is_exact(&self) -> bool {
+        *self != RowIdentity::FullRow
+    }
+
+    /// Gets the key columns that can be null, whose rows with nulls never match their changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The definitions of the columns of the table.
+    pub fn nullable_keys(&self, columns: &[ColumnDefinition]) -> Vec<String> {
+        self.keys()
+            .iter()
+            .filter(|key| {
+                columns
+                    .iter()
+                    .any(|column| &column.name == *key && !column.not_null)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Gets the key columns that are not columns of the table, e.g. of a mistyped key override.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The definitions of the columns of the table.
+    pub fn missing_keys(&self, columns: &[ColumnDefinition]) -> Vec<String> {
+        self.keys()
+            .iter()
+            .filter(|key| !columns.iter().any(|column| &column.name == *key))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Display for RowIdentity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RowIdentity::PrimaryKey(keys) => write!(f, "primary key ({})", keys.join(", ")),
+            RowIdentity::UniqueIndex(keys) => write!(f, "unique index ({})", keys.join(", ")),
+            RowIdentity::KeyOverride(keys) => write!(f, "key override ({})", keys.join(", ")),
+            RowIdentity::FullRow => write!(f, "full row"),
+        }
+    }
+}
+
+/// Parses a key override of the form `table=column1,column2`.
+///
+/// # Returns
+///
+/// The table and its key columns, or None if the key override is not valid.
+pub fn parse_key_override(key_override: &str) -> Option<(String, Vec<String>)> {
+    let (table, columns) = key_override.split_once('=')?;
+    let columns = columns
+        .split(',')
+        .map(str::trim)
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    if table.trim().is_empty() || columns.iter().any(String::is_empty) {
+        return None;
+    }
+
+    Some((table.trim().to_string(), columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let key = |column: &str| vec![column.to_string()];
+
+        assert_eq!(
+            RowIdentity::resolve(key("id"), key("code"), Some(key("name"))),
+            RowIdentity::PrimaryKey(key("id"))
+        );
+        assert_eq!(
+            RowIdentity::resolve(vec![], key("code"), Some(key("name"))),
+            RowIdentity::UniqueIndex(key("code"))
+        );
+        assert_eq!(
+            RowIdentity::resolve(vec![], vec![], Some(key("name"))),
+            RowIdentity::KeyOverride(key("name"))
+        );
+        assert_eq!(
+            RowIdentity::resolve(vec![], vec![], None),
+            RowIdentity::FullRow
+        );
+        assert!(RowIdentity::FullRow.keys().is_empty());
+        assert!(!RowIdentity::FullRow.is_exact());
+        assert!(RowIdentity::KeyOverride(key("name")).is_exact());
+    }
+
+    #[test]
+    fn test_nullable_and_missing_keys() {
+        let columns = vec![
+            ColumnDefinition {
+                not_null: true,
+                ..ColumnDefinition::new("order_id", "integer")
+            },
+            ColumnDefinition::new("line", "integer"),
+        ];
+        let key_override = RowIdentity::KeyOverride(vec![
+            "order_id".to_string(),
+            "line".to_string(),
+            "Line".to_string(),
+        ]);
+
+        assert_eq!(key_override.nullable_keys(&columns), vec!["line"]);
+        assert_eq!(key_override.missing_keys(&columns), vec!["Line"]);
+        assert!(RowIdentity::FullRow.nullable_keys(&columns).is_empty());
+        assert!(RowIdentity::FullRow.missing_keys(&columns).is_empty());
+    }
+
+    #[test]
+    fn test_parse_key_override() {
+        assert_eq!(
+            parse_key_override("order_lines=order_id, line"),
+            Some((
+                "order_lines".to_string(),
+                vec!["order_id".to_string(), "line".to_string()]
+            ))
+        );
+        assert_eq!(parse_key_override("order_lines"), None);
+        assert_eq!(parse_key_override("order_lines=order_id,"), None);
+        assert_eq!(parse_key_override("=order_id"), None);
+    }
+}
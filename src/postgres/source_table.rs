@@ -0,0 +1,254 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Represents the kind of a relation in a schema of the source database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableKind {
+    Table,
+    PartitionedTable,
+    Partition,
+    View,
+    ForeignTable,
+}
+
+impl TableKind {
+    /// Parses the `relkind` of a relation, and whether it is a partition of another table.
+    pub fn from_relkind(relkind: &str, is_partition: bool) -> Self {
+        match relkind {
+            _ if is_partition => TableKind::Partition,
+            "p" => TableKind::PartitionedTable,
+            "v" | "m" => TableKind::View,
+            "f" => TableKind::ForeignTable,
+            _ => TableKind::Table,
+        }
+    }
+}
+
+impl Display for TableKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TableKind::Table => write!(f, "table"),
+            TableKind::PartitionedTable => write!(f, "partitioned table"),
+            TableKind::Partition => write!(f, "partition"),
+            TableKind::View => write!(f, "view"),
+            TableKind::ForeignTable => write!(f, "foreign table"),
+        }
+    }
+}
+
+/// Represents a relation found in a schema of the source database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceTable {
+    /// The name of the relation.
+    pub name: String,
+    /// The kind of the relation.
+    pub kind: TableKind,
+    /// The table that a partition is attached to.
+    pub parent: Option<String>,
+    /// The top-most partitioned table of the tree that a partition belongs to.
+    pub root: Option<String>,
+    /// The partitioning of a partitioned table, as formatted by `pg_get_partkeydef`,
+    /// e.g. `RANGE (created_at)`.
+    pub partition_key: Option<String>,
+    /// The bound of a partition, as formatted by `pg_get_expr`,
+    /// e.g. `FOR VALUES FROM ('2024-01-01') TO ('2025-01-01')` or `DEFAULT`.
+    pub partition_bound: Option<String>,
+}
+
+impl SourceTable {
+    /// Creates a table that is neither partitioned nor a partition.
+    pub fn new(name: &str, kind: TableKind) -> Self {
+        SourceTable {
+            name: name.to_string(),
+            kind,
+            parent: None,
+            root: None,
+            partition_key: None,
+            partition_bound: None,
+        }
+    }
+
+    /// Whether DMS replicates the rows of the relation, which it does not for views and foreign tables.
+    pub fn is_replicated(&self) -> bool {
+        !matches!(self.kind, TableKind::View | TableKind::ForeignTable)
+    }
+}
+
+impl Display for SourceTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)
+    }
+}
+
+/// Represents a table that is loaded in the target database, with the partitions
+/// whose DMS output is loaded into it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadedTable {
+    /// The table.
+    pub table: SourceTable,
+    /// The partitions of every level of the table, each after the table it is attached to.
+    pub partitions: Vec<SourceTable>,
+}
+
+impl LoadedTable {
+    /// Gets the names of the table and of its partitions, which DMS writes the output of the table under.
+    pub fn source_names(&self) -> Vec<&str> {
+        std::iter::once(&self.table)
+            .chain(&self.partitions)
+            .map(|table| table.name.as_str())
+            .collect()
+    }
+}
+
+/// Groups the relations of a schema into the tables that are loaded in the target database.
+///
+/// The partitions of a listed partitioned table are loaded into it, and the target table is
+/// created with the same partitioning. Partitions listed without their partitioned table
+/// are loaded as plain tables.
+///
+/// # Returns
+///
+/// The tables to load, in the order of the relations, and the views and foreign tables,
+/// which are not loaded.
+pub fn group_partitions(tables: Vec<SourceTable>) -> (Vec<LoadedTable>, Vec<SourceTable>) {
+    let (tables, skipped_tables): (Vec<_>, Vec<_>) =
+        tables.into_iter().partition(SourceTable::is_replicated);
+    let listed_roots = tables
+        .iter()
+        .filter(|table| table.kind == TableKind::PartitionedTable)
+        .map(|table| table.name.clone())
+        .collect::<Vec<_>>();
+
+    let (partitions, tables): (Vec<_>, Vec<_>) = tables.into_iter().partition(|table| {
+        table
+            .root
+            .as_ref()
+            .is_some_and(|root| listed_roots.contains(root))
+    });
+
+    let loaded_tables = tables
+        .into_iter()
+        .map(|table| {
+            let mut table_partitions = Vec::new();
+            let mut parents = vec![table.name.clone()];
+            // Each level of the tree is attached to the tables of the previous level
+            loop {
+                let level = partitions
+                    .iter()
+                    .filter(|partition| {
+                        partition.root.as_ref() == Some(&table.name)
+                            && partition
+                                .parent
+                                .as_ref()
+                                .is_some_and(|parent| parents.contains(parent))
+                            && !table_partitions.contains(*partition)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if level.is_empty() {
+                    break;
+                }
+                parents = level
+                    .iter()
+                    .map(|partition| partition.name.clone())
+                    .collect();
+                table_partitions.extend(level);
+            }
+
+            // Listed without their partitioned table, partitions are plain tables
+            let table = if table.kind == TableKind::Partition {
+                SourceTable::new(&table.name, TableKind::Table)
+            } else {
+                table
+            };
+            LoadedTable {
+                table,
+                partitions: table_partitions,
+            }
+        })
+        .collect();
+
+    (loaded_tables, skipped_tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partition(name: &str, parent: &str, root: &str) -> SourceTable {
+        SourceTable {
+            parent: Some(parent.to_string()),
+            root: Some(root.to_string()),
+            partition_bound: Some("DEFAULT".to_string()),
+            ..SourceTable::new(name, TableKind::Partition)
+        }
+    }
+
+    #[test]
+    fn test_group_partitions() {
+        let events = SourceTable {
+            partition_key: Some("RANGE (created_at)".to_string()),
+            ..SourceTable::new("events", TableKind::PartitionedTable)
+        };
+        let tables = vec![
+            partition("events_2024_01", "events_2024", "events"),
+            events.clone(),
+            partition("events_2024", "events", "events"),
+            SourceTable::new("orders", TableKind::Table),
+            SourceTable::new("order_totals", TableKind::View),
+            SourceTable::new("remote_orders", TableKind::ForeignTable),
+        ];
+
+        let (loaded_tables, skipped_tables) = group_partitions(tables);
+        assert_eq!(
+            loaded_tables,
+            vec![
+                LoadedTable {
+                    table: events,
+                    partitions: vec![
+                        partition("events_2024", "events", "events"),
+                        partition("events_2024_01", "events_2024", "events"),
+                    ],
+                },
+                LoadedTable {
+                    table: SourceTable::new("orders", TableKind::Table),
+                    partitions: vec![],
+                },
+            ]
+        );
+        assert_eq!(
+            loaded_tables[0].source_names(),
+            vec!["events", "events_2024", "events_2024_01"]
+        );
+        assert_eq!(
+            skipped_tables
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["view order_totals", "foreign table remote_orders"]
+        );
+    }
+
+    #[test]
+    fn test_group_partitions_without_partitioned_table() {
+        let (loaded_tables, _) =
+            group_partitions(vec![partition("events_2024", "events", "events")]);
+        assert_eq!(
+            loaded_tables,
+            vec![LoadedTable {
+                table: SourceTable::new("events_2024", TableKind::Table),
+                partitions: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_from_relkind() {
+        assert_eq!(
+            TableKind::from_relkind("p", false),
+            TableKind::PartitionedTable
+        );
+        assert_eq!(TableKind::from_relkind("p", true), TableKind::Partition);
+        assert_eq!(TableKind::from_relkind("m", false), TableKind::View);
+        assert_eq!(TableKind::from_relkind("f", false), TableKind::ForeignTable);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+/// Represents the kind of a constraint, other than the primary key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstraintKind {
+    Unique,
+    Check,
+    ForeignKey,
+    Exclusion,
+}
+
+impl ConstraintKind {
+    /// Parses the `contype` of a constraint, none for primary keys and triggers.
+    pub fn from_contype(contype: &str) -> Option<Self> {
+        match contype {
+            "u" => Some(ConstraintKind::Unique),
+            "c" => Some(ConstraintKind::Check),
+            "f" => Some(ConstraintKind::ForeignKey),
+            "x" => Some(ConstraintKind::Exclusion),
+            _ => None,
+        }
+    }
+}
+
+/// Represents a constraint of a table as it is defined in the catalog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableConstraint {
+    /// The name of the constraint.
+    pub name: String,
+    /// The kind of the constraint.
+    pub kind: ConstraintKind,
+    /// The definition of the constraint, as formatted by `pg_get_constraintdef`.
+    pub definition: String,
+    /// The schema and the name of the table a foreign key references.
+    pub referenced_table: Option<(String, String)>,
+}
+
+impl TableConstraint {
+    /// Gets the table of the schema that the constraint references, if it is a foreign key.
+    pub fn referenced_table_in(&self, schema_name: &str) -> Option<&str> {
+        self.referenced_table
+            .as_ref()
+            .filter(|(schema, _)| schema == schema_name)
+            .map(|(_, table)| table.as_str())
+    }
+}
+
+/// Orders the tables so that the tables a table references are loaded before it.
+///
+/// # Arguments
+///
+/// * `schema_name` - The schema of the tables.
+/// * `tables` - The tables to load.
+/// * `constraints` - The constraints of each table.
+///
+/// # Returns
+///
+/// The tables in levels: the tables of a level only reference tables of the previous levels,
+/// and can be loaded at the same time. Tables in reference cycles are in the last level.
+pub fn load_order(
+    schema_name: &str,
+    tables: &[String],
+    constraints: &HashMap<String, Vec<TableConstraint>>,
+) -> Vec<Vec<String>> {
+    // References to the table itself or to tables that are not loaded do not delay a table
+    let mut references = tables
+        .iter()
+        .map(|table| {
+            let referenced_tables = constraints
+                .get(table)
+                .into_iter()
+                .flatten()
+                .filter_map(|constraint| constraint.referenced_table_in(schema_name))
+                .filter(|referenced_table| {
+                    referenced_table != table && tables.iter().any(|t| t == referenced_table)
+                })
+                .map(String::from)
+                .collect::<Vec<_>>();
+            (table.clone(), referenced_tables)
+        })
+        .collect::<Vec<_>>();
+
+    let mut levels: Vec<Vec<String>> = Vec::new();
+    while !references.is_empty() {
+        let (level, rest): (Vec<_>, Vec<_>) = references
+            .into_iter()
+            .partition(|(_, referenced_tables)| referenced_tables.is_empty());
+
+        if level.is_empty() {
+            levels.push(rest.into_iter().map(|(table, _)| table).collect());
+            break;
+        }
+
+        let level = level
+            .into_iter()
+            .map(|(table, _)| table)
+            .collect::<Vec<_>>();
+        references = rest
+            .into_iter()
+            .map(|(table, referenced_tables)| {
+                let referenced_tables = referenced_tables
+                    .into_iter()
+                    .filter(|referenced_table| !level.contains(referenced_table))
+                    .collect();
+                (table, referenced_tables)
+            })
+            .collect();
+        levels.push(level);
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn foreign_key(name: &str, referenced_table: &str) -> TableConstraint {
+        TableConstraint {
+            name: name.to_string(),
+            kind: ConstraintKind::ForeignKey,
+            definition: format!("FOREIGN KEY (id) REFERENCES {referenced_table}(id)"),
+            referenced_table: Some(("public".to_string(), referenced_table.to_string())),
+        }
+    }
+
+    fn tables(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_load_order() {
+        let mut constraints = HashMap::new();
+        constraints.insert(
+            "order_lines".to_string(),
+            vec![
+                foreign_key("order_lines_order_fkey", "orders"),
+                foreign_key("order_lines_product_fkey", "products"),
+            ],
+        );
+        constraints.insert(
+            "orders".to_string(),
+            vec![
+                foreign_key("orders_customer_fkey", "customers"),
+                foreign_key("orders_parent_fkey", "orders"),
+            ],
+        );
+        constraints.insert(
+            "reviews".to_string(),
+            vec![foreign_key("reviews_user_fkey", "users")],
+        );
+
+        let levels = load_order(
+            "public",
+            &tables(&["order_lines", "orders", "customers", "products", "reviews"]),
+            &constraints,
+        );
+        assert_eq!(
+            levels,
+            vec![
+                tables(&["customers", "products", "reviews"]),
+                tables(&["orders"]),
+                tables(&["order_lines"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_order_with_cycle() {
+        let mut constraints = HashMap::new();
+        constraints.insert("a".to_string(), vec![foreign_key("a_b_fkey", "b")]);
+        constraints.insert("b".to_string(), vec![foreign_key("b_a_fkey", "a")]);
+
+        let levels = load_order("public", &tables(&["a", "b", "c"]), &constraints);
+        assert_eq!(levels, vec![tables(&["c"]), tables(&["a", "b"])]);
+    }
+
+    #[test]
+    fn test_from_contype() {
+        assert_eq!(
+            ConstraintKind::from_contype("f"),
+            Some(ConstraintKind::ForeignKey)
+        );
+        assert_eq!(ConstraintKind::from_contype("p"), None);
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use super::source_table::SourceTable;
+
+/// Represents a pattern that selects tables by name.
+///
+/// A pattern is a regex when it starts with `re:`, a glob when it has a `*`, `?` or `%`
+/// wildcard (`%` matches like `*`), and an exact name otherwise. Patterns match the whole name.
+#[derive(Clone, Debug)]
+pub enum TablePattern {
+    Exact(String),
+    Glob(String, Regex),
+    Regex(Regex),
+}
+
+impl TablePattern {
+    const REGEX_PREFIX: &str = "re:";
+    const GLOB_WILDCARDS: &[char] = &['*', '?', '%'];
+
+    /// Parses a pattern, failing when it is an invalid regex.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(anyhow!("Table pattern is empty"));
+        }
+
+        if let Some(regex) = pattern.strip_prefix(Self::REGEX_PREFIX) {
+            let regex = Regex::new(&format!("^(?:{regex})$"))
+                .map_err(|e| anyhow!("Invalid table pattern {pattern}: {e}"))?;
+            return Ok(TablePattern::Regex(regex));
+        }
+
+        if pattern.contains(Self::GLOB_WILDCARDS) {
+            let regex = pattern
+                .chars()
+                .map(|c| match c {
+                    '*' | '%' => ".*".to_string(),
+                    '?' => ".".to_string(),
+                    c => regex::escape(&c.to_string()),
+                })
+                .collect::<String>();
+            let regex = Regex::new(&format!("^{regex}$"))
+                .map_err(|e| anyhow!("Invalid table pattern {pattern}: {e}"))?;
+            return Ok(TablePattern::Glob(pattern.to_string(), regex));
+        }
+
+        Ok(TablePattern::Exact(pattern.to_string()))
+    }
+
+    /// Whether the pattern matches the whole name of a table.
+    pub fn matches(&self, table_name: &str) -> bool {
+        match self {
+            TablePattern::Exact(name) => name == table_name,
+            TablePattern::Glob(_, regex) | TablePattern::Regex(regex) => regex.is_match(table_name),
+        }
+    }
+}
+
+impl Display for TablePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TablePattern::Exact(name) | TablePattern::Glob(name, _) => write!(f, "{name}"),
+            TablePattern::Regex(regex) => {
+                let regex = regex.as_str();
+                let regex = regex
+                    .strip_prefix("^(?:")
+                    .and_then(|regex| regex.strip_suffix(")$"))
+                    .unwrap_or(regex);
+                write!(f, "{}{regex}", Self::REGEX_PREFIX)
+            }
+        }
+    }
+}
+
+/// Represents the selection of the tables of a schema, the same in snapshot and in validation.
+///
+/// A table is selected when it matches any included pattern, or there are none,
+/// and it matches no excluded pattern. The partitions of a partitioned table
+/// are selected with it.
+#[derive(Clone, Debug, Default)]
+pub struct TableFilter {
+    included: Vec<TablePattern>,
+    excluded: Vec<TablePattern>,
+}
+
+impl TableFilter {
+    /// Creates a filter from the patterns of the tables to include and to exclude.
+    pub fn new(included_tables: &[String], excluded_tables: &[String]) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| TablePattern::parse(pattern))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(TableFilter {
+            included: parse(included_tables)?,
+            excluded: parse(excluded_tables)?,
+        })
+    }
+
+    /// Whether a table name is selected.
+    pub fn matches(&self, table_name: &str) -> bool {
+        self.is_included(table_name) && !self.is_excluded(table_name)
+    }
+
+    /// Whether a relation is selected, or the partition tree it belongs to.
+    ///
+    /// A partition that is included on its own is selected, but excluding
+    /// a partition does not exclude it from its partitioned table.
+    pub fn selects(&self, table: &SourceTable) -> bool {
+        let tree_name = table.root.as_deref().unwrap_or(&table.name);
+        let is_included = self.is_included(&table.name) || self.is_included(tree_name);
+        is_included && !self.is_excluded(tree_name)
+    }
+
+    fn is_included(&self, table_name: &str) -> bool {
+        self.included.is_empty()
+            || self
+                .included
+                .iter()
+                .any(|pattern| pattern.matches(table_name))
+    }
+
+    fn is_excluded(&self, table_name: &str) -> bool {
+        self.excluded
+            .iter()
+            .any(|pattern| pattern.matches(table_name))
+    }
+}
+
+/// Reads a list of table patterns from a file, one per line.
+///
+/// Blank lines and lines starting with `#` are skipped.
+pub fn read_table_list(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read table list {}: {e}", path.display()))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::source_table::TableKind;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            TablePattern::parse("order_lines").unwrap(),
+            TablePattern::Exact(_)
+        ));
+        assert!(matches!(
+            TablePattern::parse("audit_*").unwrap(),
+            TablePattern::Glob(..)
+        ));
+        assert!(matches!(
+            TablePattern::parse("re:audit_\\d+").unwrap(),
+            TablePattern::Regex(_)
+        ));
+        assert!(TablePattern::parse("re:audit_(").is_err());
+        assert!(TablePattern::parse(" ").is_err());
+        assert_eq!(
+            TablePattern::parse("re:audit_\\d+").unwrap().to_string(),
+            "re:audit_\\d+"
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let glob = TablePattern::parse("audit_*").unwrap();
+        assert!(glob.matches("audit_"));
+        assert!(glob.matches("audit_logins"));
+        assert!(!glob.matches("old_audit_logins"));
+
+        // Underscores and dots are literal, unlike in LIKE patterns and regexes
+        let like = TablePattern::parse("tmp_%").unwrap();
+        assert!(like.matches("tmp_orders"));
+        assert!(!like.matches("tmpxorders"));
+        assert!(TablePattern::parse("v?.x").unwrap().matches("v1.x"));
+        assert!(!TablePattern::parse("v?.x").unwrap().matches("v1yx"));
+
+        let regex = TablePattern::parse("re:orders_\\d{4}").unwrap();
+        assert!(regex.matches("orders_2024"));
+        assert!(!regex.matches("orders_2024_old"));
+
+        assert!(!TablePattern::parse("Orders").unwrap().matches("orders"));
+    }
+
+    #[test]
+    fn test_table_filter() {
+        let filter = TableFilter::new(
+            &patterns(&["audit_*", "orders"]),
+            &patterns(&["re:.*_(old|tmp)"]),
+        )
+        .unwrap();
+        assert!(filter.matches("audit_logins"));
+        assert!(filter.matches("orders"));
+        assert!(!filter.matches("audit_logins_old"));
+        assert!(!filter.matches("customers"));
+
+        let filter = TableFilter::new(&[], &patterns(&["tmp_%"])).unwrap();
+        assert!(filter.matches("customers"));
+        assert!(!filter.matches("tmp_customers"));
+
+        assert!(TableFilter::default().matches("customers"));
+    }
+
+    #[test]
+    fn test_table_filter_selects_partitions_with_their_table() {
+        let partition = SourceTable {
+            parent: Some("events".to_string()),
+            root: Some("events".to_string()),
+            ..SourceTable::new("events_2024", TableKind::Partition)
+        };
+
+        let filter = TableFilter::new(&patterns(&["events"]), &[]).unwrap();
+        assert!(filter.selects(&partition));
+
+        let filter = TableFilter::new(&patterns(&["events_2024"]), &[]).unwrap();
+        assert!(filter.selects(&partition));
+
+        let filter = TableFilter::new(&[], &patterns(&["events"])).unwrap();
+        assert!(!filter.selects(&partition));
+
+        let filter = TableFilter::new(&[], &patterns(&["events_*"])).unwrap();
+        assert!(filter.selects(&partition));
+    }
+
+    #[test]
+    fn test_read_table_list() {
+        let path = std::env::temp_dir().join(format!(
+            "dms-cdc-operator-tables-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "# Audit tables\naudit_*\n\n  orders  \n").unwrap();
+
+        let tables = read_table_list(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tables, patterns(&["audit_*", "orders"]));
+
+        assert!(read_table_list("/nonexistent/tables.txt").is_err());
+    }
+}
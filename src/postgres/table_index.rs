@@ -0,0 +1,45 @@
+/// Represents an index of a table as it is defined in the catalog,
+/// other than the indexes of its primary key and constraints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableIndex {
+    /// The name of the index.
+    pub name: String,
+    /// The `CREATE INDEX` statement of the index, as formatted by `pg_get_indexdef`.
+    pub definition: String,
+    /// The columns of a unique index without a predicate or expressions, none otherwise.
+    pub unique_columns: Vec<String>,
+}
+
+impl TableIndex {
+    /// Whether the index only makes the given key columns unique,
+    /// e.g. the key a table is created with.
+    pub fn is_unique_on(&self, keys: &[String]) -> bool {
+        !keys.is_empty()
+            && self.unique_columns.len() == keys.len()
+            && keys.iter().all(|key| self.unique_columns.contains(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_unique_on() {
+        let index = TableIndex {
+            name: "order_lines_line_order_id_idx".to_string(),
+            definition: "CREATE UNIQUE INDEX order_lines_line_order_id_idx ON public.order_lines USING btree (line, order_id)".to_string(),
+            unique_columns: vec!["line".to_string(), "order_id".to_string()],
+        };
+
+        assert!(index.is_unique_on(&["order_id".to_string(), "line".to_string()]));
+        assert!(!index.is_unique_on(&["order_id".to_string()]));
+        assert!(!index.is_unique_on(&[]));
+
+        let non_unique_index = TableIndex {
+            unique_columns: vec![],
+            ..index
+        };
+        assert!(!non_unique_index.is_unique_on(&["order_id".to_string(), "line".to_string()]));
+    }
+}
@@ -1,5 +0,0 @@
-pub enum TableMode {
-    AllTables,
-    IncludeTables,
-    ExcludeTables,
-}
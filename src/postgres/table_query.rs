@@ -1,62 +1,367 @@
 use indexmap::IndexMap;
 use std::fmt::Display;
 
+use super::column_definition::ColumnDefinition;
+use super::row_identity::RowIdentity;
+use crate::dataframe::dms_metadata_columns::{
+    CHANGE_FILE_COLUMN, CHANGE_OP_COLUMN, CHANGE_SEQUENCE_COLUMN,
+};
+
+/// The temporary table a CDC file is copied into before being applied.
+pub const STAGING_TABLE: &str = "dms_cdc_staging";
+
+/// The temporary table with the last change of each key in the staging table.
+pub const CHANGES_TABLE: &str = "dms_cdc_changes";
+
+/// The suffix of the schema with the DMS timestamp of the last change applied to each key
+/// of the tables of a target schema, which the idempotent apply mode compares changes to.
+const APPLIED_TIMESTAMPS_SCHEMA_SUFFIX: &str = "_dms_applied";
+
+/// The comment of the schemas with the applied timestamps, which tells them from the other schemas.
+pub const APPLIED_TIMESTAMPS_SCHEMA_COMMENT: &str =
+    "DMS timestamps of the changes applied by dms-cdc-operator";
+
+/// The maximum length in bytes of a Postgres identifier, beyond which Postgres truncates it.
+pub const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// The column of the changes of a table without a key with the net number of copies
+/// of each row that the changes insert, or delete when it is negative.
+const CHANGE_NET_COLUMN: &str = "_dms_cdc_net";
+
+/// Represents the statements run against the source and target databases.
+///
+/// Names are always quoted as identifiers, and values are never part of the
+/// statement: the variants that filter by value expect them as bound parameters,
+/// in the order given on each variant.
 pub enum TableQuery {
-    FindAllColumns(String, String),
-    FindTablesForSchema(String, String),
-    DeleteRows(String, String, String, String),
-    FindPrimaryKey(String, String),
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindAllColumns,
+    /// Parameters: `$1` schema name.
+    FindTablesForSchema,
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindPrimaryKey,
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindUniqueKey,
+    /// Fields: schema, table, key columns.
+    FindDuplicateKeys(String, String, Vec<String>),
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindIndexes,
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindConstraints,
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindUserDefinedTypes,
+    /// Parameters: `$1` qualified type name.
+    TypeExists,
+    /// Parameters: `$1` schema name, `$2` table name.
+    FindGeometryColumns,
+    /// Parameters: `$1` schema name.
+    FindSchemaComment,
     CreateSchema(String),
-    CreateTable(String, String, IndexMap<String, String>, String),
+    CommentOnAppliedTimestampsSchema(String),
+    /// Fields: schema name, table name, columns, row identity, and the partitioning of a partitioned table.
+    CreateTable(
+        String,
+        String,
+        Vec<ColumnDefinition>,
+        RowIdentity,
+        Option<String>,
+    ),
+    /// Fields: schema name, partition name, parent table name, partition bound,
+    /// and the partitioning of a partition that is partitioned itself.
+    CreatePartition(String, String, String, String, Option<String>),
+    AddColumns(String, String, IndexMap<String, String>),
+    AddConstraint(String, String, String, String),
     DropSchema(String),
+    SelectNoRows(String, String, Vec<String>),
+    CopyFromStdin(String, String, Vec<String>, String),
+    ServerVersion,
+    SessionTimezone,
+    /// Fields: schema, table, columns, and the timestamp column of the changes, which the table does not have.
+    CreateStagingTable(String, String, Vec<String>, Option<String>),
+    ReduceStagingTable(Vec<String>, Vec<String>),
+    /// Takes the staging table as the changes, when they are already reduced to the last change of each key.
+    UseStagingTableAsChanges,
+    /// Fields: schema, table, columns, primary keys, identity columns generated always
+    /// (which are inserted but never updated), whether inserts are upserted.
+    MergeChanges(String, String, Vec<String>, Vec<String>, Vec<String>, bool),
+    DeleteChanges(String, String, Vec<String>),
+    /// Fields: the same as `MergeChanges`.
+    UpsertChanges(String, String, Vec<String>, Vec<String>, Vec<String>, bool),
+    InsertChanges(String, String, Vec<String>),
+    /// Fields: schema, table, primary keys, and the timestamp column of the changes,
+    /// which are compared to the applied timestamps when it is set.
+    CountConflictingChanges(String, String, Vec<String>, Option<String>),
+    /// Fields: schema, table, primary keys, timestamp column.
+    SkipStaleChanges(String, String, Vec<String>, String),
+    /// Fields: schema, table, primary keys, timestamp column.
+    RecordAppliedTimestamps(String, String, Vec<String>, String),
+    CountUpdatesOfMissingKeys(String, String, Vec<String>),
+    ReduceFullRowChanges(Vec<String>, Vec<String>),
+    DeleteFullRowChanges(String, String, Vec<String>),
+    InsertFullRowChanges(String, String, Vec<String>),
 }
 
 impl Display for TableQuery {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TableQuery::FindAllColumns(schema, table) => {
+            TableQuery::FindAllColumns => {
+                // format_type keeps the lengths, precisions, array element types and domains
                 write!(
                     f,
-                    "SELECT column_name , data_type
-                    FROM information_schema.columns 
-                    WHERE table_schema = '{}' 
-                    AND table_name = '{}'",
-                    schema, table
+                    // language=postgresql
+                    r#"
+                    SELECT a.attname AS column_name,
+                    format_type(a.atttypid, a.atttypmod) AS data_type,
+                    a.attnotnull AS not_null,
+                    pg_get_expr(d.adbin, d.adrelid) AS column_default,
+                    a.attidentity::text AS identity,
+                    a.attgenerated::text AS generated
+                    FROM   pg_attribute a
+                    JOIN   pg_class c ON c.oid = a.attrelid
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
+                    LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+                    WHERE  n.nspname = $1
+                    AND    c.relname = $2
+                    AND    a.attnum > 0
+                    AND    NOT a.attisdropped
+                    ORDER BY a.attnum"#
                 )
             }
-            TableQuery::FindTablesForSchema(schema, subquery) => {
+            TableQuery::FindTablesForSchema => {
+                // Partitions are listed with their parent and the root of their partition tree,
+                // so that the filters can select a partitioned table with all its partitions
                 write!(
                     f,
-                    r#"SELECT table_name
-                    FROM information_schema.tables
-                    WHERE table_schema = '{}' {}
-                    "#,
-                    schema, subquery
+                    // language=postgresql
+                    r#"
+                    SELECT c.relname AS table_name,
+                    c.relkind::text AS kind,
+                    c.relispartition AS is_partition,
+                    parent.relname AS parent_name,
+                    root.relname AS root_name,
+                    pg_get_partkeydef(c.oid) AS partition_key,
+                    pg_get_expr(c.relpartbound, c.oid) AS partition_bound
+                    FROM   pg_class c
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
+                    LEFT JOIN pg_inherits i ON i.inhrelid = c.oid AND c.relispartition
+                    LEFT JOIN pg_class parent ON parent.oid = i.inhparent
+                    LEFT JOIN pg_class root ON root.oid = pg_partition_root(c.oid) AND c.relispartition
+                    WHERE  n.nspname = $1
+                    AND    c.relkind IN ('r', 'p', 'v', 'm', 'f')
+                    ORDER BY c.relname"#
                 )
             }
-            TableQuery::DeleteRows(schema, table, primary_key, primary_key_value) => {
+            TableQuery::FindPrimaryKey => {
                 write!(
                     f,
                     // language=postgresql
                     r#"
-                    DELETE FROM {}.{}
-                    WHERE ({})=({})
-                    "#,
-                    schema, table, primary_key, primary_key_value
+                    SELECT a.attname
+                    FROM   pg_index i
+                    JOIN   pg_class c ON c.oid = i.indrelid
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
+                    JOIN   pg_attribute a ON a.attrelid = i.indrelid
+                    AND a.attnum = ANY(i.indkey)
+                    WHERE  n.nspname = $1
+                    AND    c.relname = $2
+                    AND    i.indisprimary
+                    ORDER BY array_position(i.indkey, a.attnum)"#
                 )
             }
-            TableQuery::FindPrimaryKey(table, schema) => {
+            TableQuery::FindUniqueKey => {
+                // Partial and expression indexes do not identify the rows by their columns,
+                // and neither do indexes with nullable columns, whose nulls are never equal
                 write!(
                     f,
                     // language=postgresql
                     r#"
+                    WITH first_index AS (
+                        SELECT i.indrelid, i.indkey
+                        FROM   pg_index i
+                        JOIN   pg_class c ON c.oid = i.indrelid
+                        JOIN   pg_namespace n ON n.oid = c.relnamespace
+                        WHERE  n.nspname = $1
+                        AND    c.relname = $2
+                        AND    i.indisunique
+                        AND    NOT i.indisprimary
+                        AND    i.indpred IS NULL
+                        AND    i.indexprs IS NULL
+                        AND    NOT EXISTS (
+                            SELECT 1
+                            FROM   pg_attribute nullable
+                            WHERE  nullable.attrelid = i.indrelid
+                            AND    nullable.attnum = ANY(i.indkey)
+                            AND    NOT nullable.attnotnull
+                        )
+                        ORDER BY i.indexrelid
+                        LIMIT 1
+                    )
                     SELECT a.attname
-                    FROM   pg_index i
+                    FROM   first_index i
                     JOIN   pg_attribute a ON a.attrelid = i.indrelid
                     AND a.attnum = ANY(i.indkey)
-                    WHERE  i.indrelid = '{}.{}'::regclass
-                    AND    i.indisprimary"#,
-                    schema, table,
+                    ORDER BY array_position(i.indkey, a.attnum)"#
+                )
+            }
+            TableQuery::FindDuplicateKeys(schema, table, keys) => {
+                let keys = quote_identifiers(keys);
+                write!(
+                    f,
+                    "SELECT EXISTS (SELECT 1 FROM {} GROUP BY {keys} HAVING count(*) > 1)",
+                    qualified_name(schema, table)
+                )
+            }
+
+            TableQuery::FindIndexes => {
+                // The indexes of the primary key and of the constraints are created with them
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    SELECT ic.relname::text AS name,
+                    pg_get_indexdef(i.indexrelid) AS definition,
+                    CASE WHEN i.indisunique AND i.indpred IS NULL AND i.indexprs IS NULL THEN ARRAY(
+                        SELECT a.attname::text
+                        FROM   pg_attribute a
+                        WHERE  a.attrelid = i.indrelid
+                        AND    a.attnum = ANY(i.indkey)
+                        ORDER BY array_position(i.indkey, a.attnum)
+                    ) ELSE ARRAY[]::text[] END AS unique_columns
+                    FROM   pg_index i
+                    JOIN   pg_class ic ON ic.oid = i.indexrelid
+                    JOIN   pg_class c ON c.oid = i.indrelid
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
+                    WHERE  n.nspname = $1
+                    AND    c.relname = $2
+                    AND    NOT EXISTS (
+                        SELECT 1 FROM pg_constraint con
+                        WHERE con.conrelid = i.indrelid
+                        AND con.conindid = i.indexrelid
+                        AND con.contype IN ('p', 'u', 'x')
+                    )
+                    ORDER BY i.indexrelid"#
+                )
+            }
+            TableQuery::FindConstraints => {
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    SELECT con.conname AS name,
+                    con.contype::text AS kind,
+                    pg_get_constraintdef(con.oid) AS definition,
+                    rn.nspname AS referenced_schema,
+                    rc.relname AS referenced_table
+                    FROM   pg_constraint con
+                    JOIN   pg_class c ON c.oid = con.conrelid
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
+                    LEFT JOIN pg_class rc ON rc.oid = con.confrelid
+                    LEFT JOIN pg_namespace rn ON rn.oid = rc.relnamespace
+                    WHERE  n.nspname = $1
+                    AND    c.relname = $2
+                    AND    con.contype IN ('u', 'c', 'f', 'x')
+                    ORDER BY con.conname"#
+                )
+            }
+            TableQuery::FindUserDefinedTypes => {
+                // The types of the columns, and the types they are built on: the elements of arrays,
+                // the base types of domains, the attributes of composite types and the subtypes of ranges.
+                // A type is listed after the types it depends on.
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    WITH RECURSIVE dependencies(type_oid, depth) AS (
+                        SELECT a.atttypid, 1
+                        FROM   pg_attribute a
+                        JOIN   pg_class c ON c.oid = a.attrelid
+                        JOIN   pg_namespace n ON n.oid = c.relnamespace
+                        WHERE  n.nspname = $1
+                        AND    c.relname = $2
+                        AND    a.attnum > 0
+                        AND    NOT a.attisdropped
+                        UNION
+                        SELECT d.dependency_oid, dependencies.depth + 1
+                        FROM   dependencies
+                        JOIN   pg_type t ON t.oid = dependencies.type_oid
+                        CROSS JOIN LATERAL (
+                            SELECT t.typelem WHERE t.typcategory = 'A'
+                            UNION ALL
+                            SELECT t.typbasetype WHERE t.typtype = 'd'
+                            UNION ALL
+                            SELECT a.atttypid FROM pg_attribute a
+                            WHERE t.typtype = 'c' AND a.attrelid = t.typrelid AND a.attnum > 0 AND NOT a.attisdropped
+                            UNION ALL
+                            SELECT r.rngsubtype FROM pg_range r WHERE r.rngtypid = t.oid
+                        ) AS d(dependency_oid)
+                        WHERE  dependencies.depth < 16
+                    )
+                    SELECT n.nspname AS schema_name,
+                    t.typname AS name,
+                    t.typtype::text AS kind,
+                    e.extname AS extension,
+                    en.nspname AS extension_schema,
+                    CASE t.typtype
+                    WHEN 'e' THEN 'AS ENUM (' || (
+                        SELECT string_agg(quote_literal(enumlabel), ', ' ORDER BY enumsortorder)
+                        FROM pg_enum WHERE enumtypid = t.oid) || ')'
+                    WHEN 'c' THEN (
+                        SELECT 'AS (' || string_agg(quote_ident(a.attname) || ' ' || format_type(a.atttypid, a.atttypmod), ', ' ORDER BY a.attnum) || ')'
+                        FROM pg_attribute a JOIN pg_class tc ON tc.oid = a.attrelid
+                        WHERE a.attrelid = t.typrelid AND tc.relkind = 'c' AND a.attnum > 0 AND NOT a.attisdropped)
+                    WHEN 'r' THEN (
+                        SELECT 'AS RANGE (SUBTYPE = ' || format_type(r.rngsubtype, NULL) || ')'
+                        FROM pg_range r WHERE r.rngtypid = t.oid)
+                    WHEN 'd' THEN format_type(t.typbasetype, t.typtypmod)
+                        || coalesce(' DEFAULT ' || t.typdefault, '')
+                        || CASE WHEN t.typnotnull THEN ' NOT NULL' ELSE '' END
+                        || coalesce((
+                            SELECT ' ' || string_agg('CONSTRAINT ' || quote_ident(con.conname) || ' ' || pg_get_constraintdef(con.oid), ' ' ORDER BY con.conname)
+                            FROM pg_constraint con WHERE con.contypid = t.oid), '')
+                    END AS definition
+                    FROM   dependencies
+                    JOIN   pg_type t ON t.oid = dependencies.type_oid
+                    JOIN   pg_namespace n ON n.oid = t.typnamespace
+                    LEFT JOIN pg_depend dep ON dep.classid = 'pg_type'::regclass
+                    AND dep.objid = t.oid AND dep.deptype = 'e'
+                    LEFT JOIN pg_extension e ON e.oid = dep.refobjid
+                    LEFT JOIN pg_namespace en ON en.oid = e.extnamespace
+                    WHERE  n.nspname NOT IN ('pg_catalog', 'information_schema')
+                    AND    t.typcategory <> 'A'
+                    GROUP BY t.oid, n.nspname, e.extname, en.nspname
+                    ORDER BY max(dependencies.depth) DESC, n.nspname, t.typname"#
+                )
+            }
+            TableQuery::TypeExists => write!(f, "SELECT to_regtype($1) IS NOT NULL AS type_exists"),
+            TableQuery::FindGeometryColumns => {
+                // Both views exist in every database with PostGIS
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    SELECT f_geometry_column::text AS column_name, srid
+                    FROM   geometry_columns
+                    WHERE  f_table_schema = $1
+                    AND    f_table_name = $2
+                    UNION ALL
+                    SELECT f_geography_column::text AS column_name, srid
+                    FROM   geography_columns
+                    WHERE  f_table_schema = $1
+                    AND    f_table_name = $2"#
+                )
+            }
+            TableQuery::FindSchemaComment => {
+                write!(
+                    f,
+                    "SELECT obj_description(oid, 'pg_namespace') AS comment FROM pg_namespace WHERE nspname = $1"
+                )
+            }
+            TableQuery::CommentOnAppliedTimestampsSchema(schema) => {
+                write!(
+                    f,
+                    "COMMENT ON SCHEMA {} IS '{}'",
+                    quote_identifier(schema),
+                    APPLIED_TIMESTAMPS_SCHEMA_COMMENT
                 )
             }
             TableQuery::CreateSchema(schema) => {
@@ -66,26 +371,82 @@ impl Display for TableQuery {
                     r#"
                     CREATE SCHEMA IF NOT EXISTS {}
                     "#,
-                    schema
+                    quote_identifier(schema)
                 )
             }
 
-            TableQuery::CreateTable(schema, table, column_data_types, primary_key) => {
-                let mut query = format!("CREATE TABLE IF NOT EXISTS {}.{} (", schema, table);
+            TableQuery::CreateTable(schema, table, columns, row_identity, partition_key) => {
+                let mut query = format!(
+                    "CREATE TABLE IF NOT EXISTS {} (",
+                    qualified_name(schema, table)
+                );
 
-                for (column, data_type) in column_data_types {
-                    query.push_str(&format!("{} {},", column, data_type));
+                for column in columns {
+                    query.push_str(&format!("{},", column.to_sql()));
                 }
-                if !primary_key.is_empty() {
-                    query.push_str(&format!("PRIMARY KEY ({})", primary_key));
-                } else {
-                    query.pop();
+                // Keys other than the primary key may have nulls
+                match row_identity {
+                    RowIdentity::PrimaryKey(keys) => {
+                        query.push_str(&format!("PRIMARY KEY ({})", quote_identifiers(keys)))
+                    }
+                    RowIdentity::UniqueIndex(keys) | RowIdentity::KeyOverride(keys) => {
+                        query.push_str(&format!("UNIQUE ({})", quote_identifiers(keys)))
+                    }
+                    RowIdentity::FullRow => {
+                        query.pop();
+                    }
                 }
                 query.push(')');
+                if let Some(partition_key) = partition_key {
+                    query.push_str(&format!(" PARTITION BY {}", partition_key));
+                }
 
                 write!(f, "{}", query)
             }
 
+            TableQuery::CreatePartition(schema, table, parent, bound, partition_key) => {
+                write!(
+                    f,
+                    "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} {}",
+                    qualified_name(schema, table),
+                    qualified_name(schema, parent),
+                    bound
+                )?;
+                match partition_key {
+                    Some(partition_key) => write!(f, " PARTITION BY {}", partition_key),
+                    None => Ok(()),
+                }
+            }
+
+            TableQuery::AddColumns(schema, table, column_data_types) => {
+                let columns = column_data_types
+                    .iter()
+                    .map(|(column, data_type)| {
+                        format!(
+                            "ADD COLUMN IF NOT EXISTS {} {}",
+                            quote_identifier(column),
+                            data_type
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "ALTER TABLE {} {}",
+                    qualified_name(schema, table),
+                    columns.join(", ")
+                )
+            }
+
+            TableQuery::AddConstraint(schema, table, name, definition) => {
+                write!(
+                    f,
+                    "ALTER TABLE {} ADD CONSTRAINT {} {}",
+                    qualified_name(schema, table),
+                    quote_identifier(name),
+                    definition
+                )
+            }
+
             TableQuery::DropSchema(schema) => {
                 write!(
                     f,
@@ -93,60 +454,529 @@ impl Display for TableQuery {
                     r#"
                     DROP SCHEMA IF EXISTS {} CASCADE
                     "#,
-                    schema
+                    quote_identifier(schema)
+                )
+            }
+
+            TableQuery::SelectNoRows(schema, table, columns) => {
+                write!(
+                    f,
+                    "SELECT {} FROM {} LIMIT 0",
+                    quote_identifiers(columns),
+                    qualified_name(schema, table)
+                )
+            }
+
+            TableQuery::CopyFromStdin(schema, table, columns, format) => {
+                write!(
+                    f,
+                    "COPY {} ({}) FROM STDIN (FORMAT {})",
+                    qualified_name(schema, table),
+                    quote_identifiers(columns),
+                    format
+                )
+            }
+
+            TableQuery::ServerVersion => write!(f, "SHOW server_version_num"),
+            TableQuery::SessionTimezone => write!(f, "SHOW TimeZone"),
+
+            TableQuery::CreateStagingTable(schema, table, columns, timestamp_column) => {
+                let timestamp = match timestamp_column {
+                    Some(timestamp_column) => {
+                        format!(
+                            ", NULL::timestamp AS {}",
+                            quote_identifier(timestamp_column)
+                        )
+                    }
+                    None => String::new(),
+                };
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    CREATE TEMP TABLE {} ON COMMIT DROP AS
+                    SELECT {}{}, NULL::text AS {}, NULL::bigint AS {}, NULL::text AS {}
+                    FROM {}
+                    WITH NO DATA
+                    "#,
+                    STAGING_TABLE,
+                    quote_identifiers(columns),
+                    timestamp,
+                    CHANGE_OP_COLUMN,
+                    CHANGE_SEQUENCE_COLUMN,
+                    CHANGE_FILE_COLUMN,
+                    qualified_name(schema, table)
+                )
+            }
+
+            TableQuery::ReduceStagingTable(columns, primary_keys) => {
+                // A key first inserted by the changes did not exist before them,
+                // otherwise an insert after other changes of the key replaces the existing row
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    CREATE TEMP TABLE {changes} ON COMMIT DROP AS
+                    SELECT DISTINCT ON ({primary_key}) {fields},
+                    CASE WHEN {op} = 'D' THEN {op}
+                    WHEN first_value({op}) OVER (PARTITION BY {primary_key} ORDER BY {sequence}) = 'I' THEN 'I'
+                    WHEN {op} = 'I' AND count(*) OVER (PARTITION BY {primary_key}) > 1 THEN 'U'
+                    ELSE {op} END AS {op}, {file}
+                    FROM {staging}
+                    ORDER BY {primary_key}, {sequence} DESC
+                    "#,
+                    changes = CHANGES_TABLE,
+                    staging = STAGING_TABLE,
+                    primary_key = quote_identifiers(primary_keys),
+                    fields = quote_identifiers(columns),
+                    op = CHANGE_OP_COLUMN,
+                    sequence = CHANGE_SEQUENCE_COLUMN,
+                    file = CHANGE_FILE_COLUMN,
+                )
+            }
+
+            TableQuery::UseStagingTableAsChanges => {
+                write!(f, "ALTER TABLE {STAGING_TABLE} RENAME TO {CHANGES_TABLE}")
+            }
+
+            TableQuery::MergeChanges(
+                schema,
+                table,
+                columns,
+                primary_keys,
+                identity_columns,
+                upsert_inserts,
+            ) => {
+                let mut query = format!(
+                    "MERGE INTO {target} AS target \
+                    USING (SELECT * FROM {CHANGES_TABLE} WHERE {CHANGE_OP_COLUMN} IN ({ops})) AS source \
+                    ON {join} \
+                    WHEN MATCHED AND source.{CHANGE_OP_COLUMN} = 'D' THEN DELETE",
+                    target = qualified_name(schema, table),
+                    ops = merged_ops(*upsert_inserts),
+                    join = join_on_keys(primary_keys),
+                );
+
+                let updates = updated_columns(columns, primary_keys, identity_columns)
+                    .map(|column| {
+                        let column = quote_identifier(column);
+                        format!("{column} = source.{column}")
+                    })
+                    .collect::<Vec<_>>();
+                if !updates.is_empty() {
+                    query.push_str(&format!(
+                        " WHEN MATCHED THEN UPDATE SET {}",
+                        updates.join(", ")
+                    ));
+                }
+
+                query.push_str(&format!(
+                    " WHEN NOT MATCHED AND source.{CHANGE_OP_COLUMN} <> 'D' THEN INSERT ({}) OVERRIDING SYSTEM VALUE VALUES ({})",
+                    quote_identifiers(columns),
+                    columns
+                        .iter()
+                        .map(|column| format!("source.{}", quote_identifier(column)))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+
+                write!(f, "{}", query)
+            }
+
+            TableQuery::DeleteChanges(schema, table, primary_keys) => {
+                write!(
+                    f,
+                    "DELETE FROM {} AS target USING {} AS source WHERE {} AND source.{} = 'D'",
+                    qualified_name(schema, table),
+                    CHANGES_TABLE,
+                    join_on_keys(primary_keys),
+                    CHANGE_OP_COLUMN
+                )
+            }
+
+            TableQuery::UpsertChanges(
+                schema,
+                table,
+                columns,
+                primary_keys,
+                identity_columns,
+                upsert_inserts,
+            ) => {
+                let fields = quote_identifiers(columns);
+                let updates = updated_columns(columns, primary_keys, identity_columns)
+                    .map(|column| {
+                        let column = quote_identifier(column);
+                        format!("{column} = EXCLUDED.{column}")
+                    })
+                    .collect::<Vec<_>>();
+                let on_conflict = if updates.is_empty() {
+                    "DO NOTHING".to_string()
+                } else {
+                    format!("DO UPDATE SET {}", updates.join(", "))
+                };
+
+                write!(
+                    f,
+                    "INSERT INTO {} AS target ({}) OVERRIDING SYSTEM VALUE SELECT {} FROM {} WHERE {} IN ({}) ON CONFLICT ({}) {}",
+                    qualified_name(schema, table),
+                    fields,
+                    fields,
+                    CHANGES_TABLE,
+                    CHANGE_OP_COLUMN,
+                    upserted_ops(*upsert_inserts),
+                    quote_identifiers(primary_keys),
+                    on_conflict
+                )
+            }
+
+            TableQuery::InsertChanges(schema, table, columns) => {
+                let fields = quote_identifiers(columns);
+                write!(
+                    f,
+                    "INSERT INTO {} ({}) OVERRIDING SYSTEM VALUE SELECT {} FROM {} WHERE {} = 'I'",
+                    qualified_name(schema, table),
+                    fields,
+                    fields,
+                    CHANGES_TABLE,
+                    CHANGE_OP_COLUMN
+                )
+            }
+
+            TableQuery::CountConflictingChanges(schema, table, primary_keys, timestamp_column) => {
+                let (applied, stale) = match timestamp_column {
+                    Some(timestamp_column) => (
+                        format!(
+                            "\n                    LEFT JOIN {} AS applied ON {}",
+                            qualified_name(&applied_timestamps_schema(schema), table),
+                            join_aliases_on_keys("applied", "source", primary_keys)
+                        ),
+                        format!("COALESCE({}, false)", is_stale(timestamp_column)),
+                    ),
+                    None => (String::new(), "false".to_string()),
+                };
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    SELECT source.{file} AS file_name,
+                    count(*) FILTER (WHERE source.{op} = 'I' AND target.ctid IS NOT NULL AND NOT {stale}) AS upserted_inserts,
+                    count(*) FILTER (WHERE source.{op} = 'D' AND target.ctid IS NULL) AS skipped_deletes,
+                    count(*) FILTER (WHERE source.{op} <> 'D' AND {stale}) AS skipped_stale_updates
+                    FROM {changes} AS source
+                    LEFT JOIN {target} AS target ON {join}{applied}
+                    GROUP BY source.{file}
+                    ORDER BY source.{file}
+                    "#,
+                    file = CHANGE_FILE_COLUMN,
+                    op = CHANGE_OP_COLUMN,
+                    changes = CHANGES_TABLE,
+                    target = qualified_name(schema, table),
+                    join = join_on_keys(primary_keys),
+                )
+            }
+
+            TableQuery::SkipStaleChanges(schema, table, primary_keys, timestamp_column) => {
+                // Deletes are applied even when they are older, since they only remove rows
+                write!(
+                    f,
+                    "DELETE FROM {} AS source USING {} AS applied WHERE {} AND source.{} <> 'D' AND {}",
+                    CHANGES_TABLE,
+                    qualified_name(&applied_timestamps_schema(schema), table),
+                    join_aliases_on_keys("applied", "source", primary_keys),
+                    CHANGE_OP_COLUMN,
+                    is_stale(timestamp_column)
+                )
+            }
+
+            TableQuery::RecordAppliedTimestamps(schema, table, primary_keys, timestamp_column) => {
+                let keys = quote_identifiers(primary_keys);
+                let timestamp_column = quote_identifier(timestamp_column);
+                write!(
+                    f,
+                    "INSERT INTO {} AS applied ({keys}, {timestamp_column}) \
+                    SELECT {keys}, {timestamp_column} FROM {} WHERE {timestamp_column} IS NOT NULL \
+                    ON CONFLICT ({keys}) DO UPDATE SET {timestamp_column} = EXCLUDED.{timestamp_column} \
+                    WHERE applied.{timestamp_column} < EXCLUDED.{timestamp_column}",
+                    qualified_name(&applied_timestamps_schema(schema), table),
+                    CHANGES_TABLE,
+                )
+            }
+
+            TableQuery::CountUpdatesOfMissingKeys(schema, table, primary_keys) => {
+                write!(
+                    f,
+                    "SELECT count(*) FROM {} AS source WHERE source.{} = 'U' \
+                    AND NOT EXISTS (SELECT 1 FROM {} AS target WHERE {})",
+                    CHANGES_TABLE,
+                    CHANGE_OP_COLUMN,
+                    qualified_name(schema, table),
+                    join_on_keys(primary_keys)
+                )
+            }
+
+            TableQuery::ReduceFullRowChanges(match_columns, columns) => {
+                // Rows without a key are only told apart by their values, so the changes of a row
+                // reduce to the number of copies of it they insert, or delete when negative
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    CREATE TEMP TABLE {changes} ON COMMIT DROP AS
+                    SELECT DISTINCT ON ({row}) {fields},
+                    sum(CASE WHEN {op} = 'D' THEN -1 ELSE 1 END) OVER (PARTITION BY {row}) AS {net}
+                    FROM {staging}
+                    "#,
+                    changes = CHANGES_TABLE,
+                    staging = STAGING_TABLE,
+                    row = row_text(None, match_columns),
+                    fields = quote_identifiers(columns),
+                    op = CHANGE_OP_COLUMN,
+                    net = CHANGE_NET_COLUMN,
+                )
+            }
+
+            TableQuery::DeleteFullRowChanges(schema, table, match_columns) => {
+                // The ctid of a row is only unique within its partition
+                write!(
+                    f,
+                    // language=postgresql
+                    r#"
+                    DELETE FROM {target} WHERE (tableoid, ctid) IN (
+                    SELECT target_table, target_row FROM (
+                    SELECT target.tableoid AS target_table, target.ctid AS target_row, source.{net} AS net,
+                    row_number() OVER (PARTITION BY source.ctid) AS copy
+                    FROM {changes} AS source
+                    JOIN {target} AS target ON {target_row} = {source_row}
+                    WHERE source.{net} < 0
+                    ) AS matches WHERE copy <= -net)
+                    "#,
+                    target = qualified_name(schema, table),
+                    changes = CHANGES_TABLE,
+                    target_row = row_text(Some("target"), match_columns),
+                    source_row = row_text(Some("source"), match_columns),
+                    net = CHANGE_NET_COLUMN,
+                )
+            }
+
+            TableQuery::InsertFullRowChanges(schema, table, columns) => {
+                write!(
+                    f,
+                    "INSERT INTO {} ({}) OVERRIDING SYSTEM VALUE SELECT {} FROM {} AS source \
+                    CROSS JOIN LATERAL generate_series(1, source.{}) WHERE source.{} > 0",
+                    qualified_name(schema, table),
+                    quote_identifiers(columns),
+                    columns
+                        .iter()
+                        .map(|column| format!("source.{}", quote_identifier(column)))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    CHANGES_TABLE,
+                    CHANGE_NET_COLUMN,
+                    CHANGE_NET_COLUMN
                 )
             }
         }
     }
 }
 
+/// The text of the values of a row, which tells rows apart even when the
+/// columns have types without equality (e.g. json), and can be hash joined.
+fn row_text(alias: Option<&str>, columns: &[String]) -> String {
+    let columns = columns
+        .iter()
+        .map(|column| match alias {
+            Some(alias) => format!("{alias}.{}", quote_identifier(column)),
+            None => quote_identifier(column),
+        })
+        .collect::<Vec<_>>();
+    format!("ROW({})::text", columns.join(", "))
+}
+
+/// The condition of a change that is older than the last change applied to its key.
+fn is_stale(timestamp_column: &str) -> String {
+    let timestamp_column = quote_identifier(timestamp_column);
+    format!("applied.{timestamp_column} > source.{timestamp_column}")
+}
+
+/// Gets the schema with the DMS timestamp of the last change applied to each key of the tables
+/// of a target schema, which keeps the target tables the same as the source tables.
+pub fn applied_timestamps_schema(schema: &str) -> String {
+    format!("{schema}{APPLIED_TIMESTAMPS_SCHEMA_SUFFIX}")
+}
+
+/// Quotes a name as a Postgres identifier, so that it keeps its case
+/// and can be a reserved word or contain any character.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quotes a table name qualified with its schema.
+pub fn qualified_name(schema: &str, table: &str) -> String {
+    format!("{}.{}", quote_identifier(schema), quote_identifier(table))
+}
+
+fn quote_identifiers(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote_identifier(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The ops applied with MERGE, inserts are applied separately unless they are upserted.
+fn merged_ops(upsert_inserts: bool) -> &'static str {
+    if upsert_inserts {
+        "'I', 'U', 'D'"
+    } else {
+        "'U', 'D'"
+    }
+}
+
+/// The ops applied with INSERT ... ON CONFLICT DO UPDATE.
+fn upserted_ops(upsert_inserts: bool) -> &'static str {
+    if upsert_inserts { "'I', 'U'" } else { "'U'" }
+}
+
+fn join_on_keys(primary_keys: &[String]) -> String {
+    join_aliases_on_keys("target", "source", primary_keys)
+}
+
+fn join_aliases_on_keys(left: &str, right: &str, primary_keys: &[String]) -> String {
+    primary_keys
+        .iter()
+        .map(|key| {
+            let key = quote_identifier(key);
+            format!("{left}.{key} = {right}.{key}")
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// The columns set by an update: identity columns generated always can only be updated to DEFAULT.
+fn updated_columns<'a>(
+    columns: &'a [String],
+    primary_keys: &'a [String],
+    identity_columns: &'a [String],
+) -> impl Iterator<Item = &'a String> {
+    columns
+        .iter()
+        .filter(|column| !primary_keys.contains(column) && !identity_columns.contains(column))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_display_find_all_columns() {
-        let query = TableQuery::FindAllColumns("schema".to_string(), "table".to_string());
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("table"), "\"table\"");
+        assert_eq!(quote_identifier("MixedCase"), "\"MixedCase\"");
+        assert_eq!(quote_identifier("user"), "\"user\"");
+        assert_eq!(quote_identifier("we\"ird, name"), "\"we\"\"ird, name\"");
+    }
+
+    #[test]
+    fn test_qualified_name() {
         assert_eq!(
-            query.to_string(),
-            "SELECT column_name , data_type
-                    FROM information_schema.columns 
-                    WHERE table_schema = 'schema' 
-                    AND table_name = 'table'"
+            qualified_name("My Schema", "order"),
+            "\"My Schema\".\"order\""
         );
     }
 
     #[test]
-    fn test_display_delete_rows() {
-        let query = TableQuery::DeleteRows(
-            "schema".to_string(),
-            "table".to_string(),
-            vec!["primary_key".to_string(), "primary_key2".to_string()]
-                .as_slice()
-                .join(","),
-            vec!["1".to_string(), "2".to_string()].as_slice().join(","),
-        );
+    fn test_display_find_all_columns() {
+        let query = TableQuery::FindAllColumns.to_string();
+        assert!(query.contains("format_type(a.atttypid, a.atttypmod) AS data_type"));
+        assert!(query.contains("pg_get_expr(d.adbin, d.adrelid) AS column_default"));
+        assert!(query.contains("NOT a.attisdropped"));
+    }
+
+    #[test]
+    fn test_find_all_columns_parameter_order() {
+        let query = TableQuery::FindAllColumns.to_string();
+        assert!(query.contains("n.nspname = $1"));
+        assert!(query.contains("c.relname = $2"));
+        assert!(!query.contains("$3"));
+    }
+
+    #[test]
+    fn test_find_primary_key_parameter_order() {
+        let query = TableQuery::FindPrimaryKey.to_string();
+        assert!(query.contains("n.nspname = $1"));
+        assert!(query.contains("c.relname = $2"));
+        assert!(!query.contains("$3"));
+    }
+
+    #[test]
+    fn test_find_tables_for_schema_parameter_order() {
+        let query = TableQuery::FindTablesForSchema.to_string();
+        assert!(query.contains("n.nspname = $1"));
+        assert!(!query.contains("$2"));
+    }
+
+    #[test]
+    fn test_join_on_composite_text_and_uuid_key() {
+        // The keys are compared as columns, so their types need no casts or literals
         assert_eq!(
-            query.to_string(),
-            r#"
-                    DELETE FROM schema.table
-                    WHERE (primary_key,primary_key2)=(1,2)
-                    "#
+            join_on_keys(&["tenant code".to_string(), "Id".to_string()]),
+            r#"target."tenant code" = source."tenant code" AND target."Id" = source."Id""#
         );
     }
 
+    #[test]
+    fn test_display_find_tables_for_schema() {
+        let query = TableQuery::FindTablesForSchema.to_string();
+        assert!(query.contains("c.relkind::text AS kind"));
+        assert!(query.contains("pg_get_expr(c.relpartbound, c.oid) AS partition_bound"));
+        assert!(query.contains("AND    c.relkind IN ('r', 'p', 'v', 'm', 'f')"));
+    }
+
     #[test]
     fn test_display_find_primary_key() {
-        let query = TableQuery::FindPrimaryKey("table".to_string(), "schema".to_string());
+        let query = TableQuery::FindPrimaryKey;
         assert_eq!(
             query.to_string(),
             r#"
                     SELECT a.attname
                     FROM   pg_index i
+                    JOIN   pg_class c ON c.oid = i.indrelid
+                    JOIN   pg_namespace n ON n.oid = c.relnamespace
                     JOIN   pg_attribute a ON a.attrelid = i.indrelid
                     AND a.attnum = ANY(i.indkey)
-                    WHERE  i.indrelid = 'schema.table'::regclass
-                    AND    i.indisprimary"#
+                    WHERE  n.nspname = $1
+                    AND    c.relname = $2
+                    AND    i.indisprimary
+                    ORDER BY array_position(i.indkey, a.attnum)"#
+        );
+    }
+
+    #[test]
+    fn test_display_find_unique_key_of_not_null_columns() {
+        let query = TableQuery::FindUniqueKey.to_string();
+        assert!(query.contains("n.nspname = $1"));
+        assert!(query.contains("c.relname = $2"));
+        assert!(query.contains("AND    NOT nullable.attnotnull"));
+    }
+
+    #[test]
+    fn test_find_indexes_parameter_order() {
+        let query = TableQuery::FindIndexes.to_string();
+        assert!(query.contains("ic.relname::text AS name"));
+        assert!(query.contains("n.nspname = $1"));
+        assert!(query.contains("c.relname = $2"));
+        assert!(!query.contains("$3"));
+    }
+
+    #[test]
+    fn test_display_find_duplicate_keys() {
+        let query = TableQuery::FindDuplicateKeys(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["order_id".to_string(), "line".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"SELECT EXISTS (SELECT 1 FROM "schema"."table" GROUP BY "order_id", "line" HAVING count(*) > 1)"#
         );
     }
 
@@ -156,40 +986,404 @@ mod tests {
         assert_eq!(
             query.to_string(),
             r#"
-                    CREATE SCHEMA IF NOT EXISTS schema
+                    CREATE SCHEMA IF NOT EXISTS "schema"
                     "#
         );
     }
 
+    #[test]
+    fn test_display_comment_on_applied_timestamps_schema() {
+        let query = TableQuery::CommentOnAppliedTimestampsSchema("public_dms_applied".to_string());
+        assert_eq!(
+            query.to_string(),
+            r#"COMMENT ON SCHEMA "public_dms_applied" IS 'DMS timestamps of the changes applied by dms-cdc-operator'"#
+        );
+    }
+
     #[test]
     fn test_display_drop_schema() {
-        let query = TableQuery::DropSchema("schema".to_string());
+        let query = TableQuery::DropSchema("Schema".to_string());
         assert_eq!(
             query.to_string(),
             r#"
-                    DROP SCHEMA IF EXISTS schema CASCADE
+                    DROP SCHEMA IF EXISTS "Schema" CASCADE
                     "#
         );
     }
 
     #[test]
     fn test_display_create_table() {
+        let columns = vec![
+            ColumnDefinition::new("column1", "character varying(20)"),
+            ColumnDefinition::new("column2", "integer[]"),
+        ];
+        let primary_keys = vec!["primary_key".to_string(), "primary_key2".to_string()];
+
+        let query = TableQuery::CreateTable(
+            "schema".to_string(),
+            "table".to_string(),
+            columns,
+            RowIdentity::PrimaryKey(primary_keys),
+            None,
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"CREATE TABLE IF NOT EXISTS "schema"."table" ("column1" character varying(20),"column2" integer[],PRIMARY KEY ("primary_key", "primary_key2"))"#
+        );
+    }
+
+    #[test]
+    fn test_display_create_table_with_reserved_words() {
+        let columns = vec![
+            ColumnDefinition::new("select", "text"),
+            ColumnDefinition::new("UserId", "uuid"),
+        ];
+
+        let query = TableQuery::CreateTable(
+            "Sales".to_string(),
+            "order".to_string(),
+            columns,
+            RowIdentity::FullRow,
+            None,
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"CREATE TABLE IF NOT EXISTS "Sales"."order" ("select" text,"UserId" uuid)"#
+        );
+    }
+
+    #[test]
+    fn test_display_create_partitioned_table() {
+        let query = TableQuery::CreateTable(
+            "schema".to_string(),
+            "events".to_string(),
+            vec![
+                ColumnDefinition::new("id", "bigint"),
+                ColumnDefinition::new("created_at", "timestamp without time zone"),
+            ],
+            RowIdentity::PrimaryKey(vec!["id".to_string(), "created_at".to_string()]),
+            Some("RANGE (created_at)".to_string()),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"CREATE TABLE IF NOT EXISTS "schema"."events" ("id" bigint,"created_at" timestamp without time zone,PRIMARY KEY ("id", "created_at")) PARTITION BY RANGE (created_at)"#
+        );
+    }
+
+    #[test]
+    fn test_display_create_partition() {
+        let query = TableQuery::CreatePartition(
+            "schema".to_string(),
+            "events_2024".to_string(),
+            "events".to_string(),
+            "FOR VALUES FROM ('2024-01-01 00:00:00') TO ('2025-01-01 00:00:00')".to_string(),
+            Some("LIST (tenant)".to_string()),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"CREATE TABLE IF NOT EXISTS "schema"."events_2024" PARTITION OF "schema"."events" FOR VALUES FROM ('2024-01-01 00:00:00') TO ('2025-01-01 00:00:00') PARTITION BY LIST (tenant)"#
+        );
+    }
+
+    #[test]
+    fn test_display_add_columns() {
         let mut column_data_types = IndexMap::new();
-        column_data_types.insert("column1".to_string(), "varchar".to_string());
-        column_data_types.insert("column2".to_string(), "int".to_string());
-        let primary_keys = vec!["primary_key".to_string(), "primary_key2".to_string()]
-            .as_slice()
-            .join(",");
+        column_data_types.insert("email".to_string(), "text".to_string());
+        column_data_types.insert("Score".to_string(), "double precision".to_string());
+
+        let query =
+            TableQuery::AddColumns("schema".to_string(), "table".to_string(), column_data_types);
+        assert_eq!(
+            query.to_string(),
+            r#"ALTER TABLE "schema"."table" ADD COLUMN IF NOT EXISTS "email" text, ADD COLUMN IF NOT EXISTS "Score" double precision"#
+        );
+    }
+
+    #[test]
+    fn test_display_create_table_with_unique_key() {
+        let columns = vec![ColumnDefinition::new("code", "text")];
 
         let query = TableQuery::CreateTable(
             "schema".to_string(),
             "table".to_string(),
-            column_data_types,
-            primary_keys,
+            columns,
+            RowIdentity::UniqueIndex(vec!["code".to_string()]),
+            None,
         );
         assert_eq!(
             query.to_string(),
-            "CREATE TABLE IF NOT EXISTS schema.table (column1 varchar,column2 int,PRIMARY KEY (primary_key,primary_key2))"
+            r#"CREATE TABLE IF NOT EXISTS "schema"."table" ("code" text,UNIQUE ("code"))"#
+        );
+    }
+
+    #[test]
+    fn test_display_add_constraint() {
+        let query = TableQuery::AddConstraint(
+            "schema".to_string(),
+            "order_lines".to_string(),
+            "order_lines_order_id_fkey".to_string(),
+            "FOREIGN KEY (order_id) REFERENCES orders(id)".to_string(),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"ALTER TABLE "schema"."order_lines" ADD CONSTRAINT "order_lines_order_id_fkey" FOREIGN KEY (order_id) REFERENCES orders(id)"#
+        );
+    }
+
+    #[test]
+    fn test_display_find_constraints() {
+        let query = TableQuery::FindConstraints.to_string();
+        assert!(query.contains("pg_get_constraintdef(con.oid) AS definition"));
+        assert!(query.contains("con.contype IN ('u', 'c', 'f', 'x')"));
+    }
+
+    #[test]
+    fn test_display_select_no_rows() {
+        let query = TableQuery::SelectNoRows(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["column1".to_string(), "Column2".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"SELECT "column1", "Column2" FROM "schema"."table" LIMIT 0"#
+        );
+    }
+
+    #[test]
+    fn test_display_copy_from_stdin() {
+        let query = TableQuery::CopyFromStdin(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["column1".to_string(), "column2".to_string()],
+            "binary".to_string(),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"COPY "schema"."table" ("column1", "column2") FROM STDIN (FORMAT binary)"#
+        );
+    }
+
+    #[test]
+    fn test_display_merge_changes() {
+        let query = TableQuery::MergeChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string(), "name".to_string()],
+            vec!["id".to_string()],
+            vec![],
+            false,
+        );
+        assert_eq!(
+            query.to_string(),
+            concat!(
+                r#"MERGE INTO "schema"."table" AS target "#,
+                r#"USING (SELECT * FROM dms_cdc_changes WHERE _dms_cdc_op IN ('U', 'D')) AS source "#,
+                r#"ON target."id" = source."id" "#,
+                r#"WHEN MATCHED AND source._dms_cdc_op = 'D' THEN DELETE "#,
+                r#"WHEN MATCHED THEN UPDATE SET "name" = source."name" "#,
+                r#"WHEN NOT MATCHED AND source._dms_cdc_op <> 'D' THEN INSERT ("id", "name") OVERRIDING SYSTEM VALUE VALUES (source."id", source."name")"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_upsert_changes() {
+        let query = TableQuery::UpsertChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string(), "name".to_string()],
+            vec!["id".to_string()],
+            vec![],
+            true,
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"INSERT INTO "schema"."table" AS target ("id", "name") OVERRIDING SYSTEM VALUE SELECT "id", "name" FROM dms_cdc_changes WHERE _dms_cdc_op IN ('I', 'U') ON CONFLICT ("id") DO UPDATE SET "name" = EXCLUDED."name""#
+        );
+    }
+
+    #[test]
+    fn test_display_upsert_changes_of_key_only_table() {
+        let query = TableQuery::UpsertChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            vec!["id".to_string()],
+            vec![],
+            false,
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"INSERT INTO "schema"."table" AS target ("id") OVERRIDING SYSTEM VALUE SELECT "id" FROM dms_cdc_changes WHERE _dms_cdc_op IN ('U') ON CONFLICT ("id") DO NOTHING"#
+        );
+    }
+
+    #[test]
+    fn test_display_delete_changes() {
+        let query = TableQuery::DeleteChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string(), "Tenant".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"DELETE FROM "schema"."table" AS target USING dms_cdc_changes AS source WHERE target."id" = source."id" AND target."Tenant" = source."Tenant" AND source._dms_cdc_op = 'D'"#
+        );
+    }
+
+    #[test]
+    fn test_display_use_staging_table_as_changes() {
+        assert_eq!(
+            TableQuery::UseStagingTableAsChanges.to_string(),
+            "ALTER TABLE dms_cdc_staging RENAME TO dms_cdc_changes"
+        );
+    }
+
+    #[test]
+    fn test_display_delete_full_row_changes() {
+        let query = TableQuery::DeleteFullRowChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string(), "payload".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"
+                    DELETE FROM "schema"."table" WHERE (tableoid, ctid) IN (
+                    SELECT target_table, target_row FROM (
+                    SELECT target.tableoid AS target_table, target.ctid AS target_row, source._dms_cdc_net AS net,
+                    row_number() OVER (PARTITION BY source.ctid) AS copy
+                    FROM dms_cdc_changes AS source
+                    JOIN "schema"."table" AS target ON ROW(target."id", target."payload")::text = ROW(source."id", source."payload")::text
+                    WHERE source._dms_cdc_net < 0
+                    ) AS matches WHERE copy <= -net)
+                    "#
+        );
+    }
+
+    #[test]
+    fn test_display_insert_full_row_changes() {
+        let query = TableQuery::InsertFullRowChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string(), "payload".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"INSERT INTO "schema"."table" ("id", "payload") OVERRIDING SYSTEM VALUE SELECT source."id", source."payload" FROM dms_cdc_changes AS source CROSS JOIN LATERAL generate_series(1, source._dms_cdc_net) WHERE source._dms_cdc_net > 0"#
+        );
+    }
+
+    #[test]
+    fn test_display_count_updates_of_missing_keys() {
+        let query = TableQuery::CountUpdatesOfMissingKeys(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"SELECT count(*) FROM dms_cdc_changes AS source WHERE source._dms_cdc_op = 'U' AND NOT EXISTS (SELECT 1 FROM "schema"."table" AS target WHERE target."id" = source."id")"#
+        );
+    }
+
+    #[test]
+    fn test_display_create_staging_table_with_timestamp() {
+        let query = TableQuery::CreateStagingTable(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            Some("ts".to_string()),
+        );
+        assert!(query.to_string().contains(
+            r#"SELECT "id", NULL::timestamp AS "ts", NULL::text AS _dms_cdc_op, NULL::bigint AS _dms_cdc_seq"#
+        ));
+        assert!(query.to_string().contains(r#"FROM "schema"."table""#));
+    }
+
+    #[test]
+    fn test_display_count_conflicting_changes_with_applied_timestamps() {
+        let query = TableQuery::CountConflictingChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            Some("ts".to_string()),
+        )
+        .to_string();
+        assert!(query.contains(
+            r#"LEFT JOIN "schema_dms_applied"."table" AS applied ON applied."id" = source."id""#
+        ));
+        assert!(query.contains(
+            r#"count(*) FILTER (WHERE source._dms_cdc_op <> 'D' AND COALESCE(applied."ts" > source."ts", false)) AS skipped_stale_updates"#
+        ));
+    }
+
+    #[test]
+    fn test_display_count_conflicting_changes_without_timestamp() {
+        let query = TableQuery::CountConflictingChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            None,
+        )
+        .to_string();
+        assert!(!query.contains("applied"));
+        assert!(query.contains("AND NOT false) AS upserted_inserts"));
+    }
+
+    #[test]
+    fn test_display_skip_stale_changes() {
+        let query = TableQuery::SkipStaleChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            "ts".to_string(),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"DELETE FROM dms_cdc_changes AS source USING "schema_dms_applied"."table" AS applied WHERE applied."id" = source."id" AND source._dms_cdc_op <> 'D' AND applied."ts" > source."ts""#
+        );
+    }
+
+    #[test]
+    fn test_display_record_applied_timestamps() {
+        let query = TableQuery::RecordAppliedTimestamps(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["id".to_string()],
+            "ts".to_string(),
+        );
+        assert_eq!(
+            query.to_string(),
+            concat!(
+                r#"INSERT INTO "schema_dms_applied"."table" AS applied ("id", "ts") "#,
+                r#"SELECT "id", "ts" FROM dms_cdc_changes WHERE "ts" IS NOT NULL "#,
+                r#"ON CONFLICT ("id") DO UPDATE SET "ts" = EXCLUDED."ts" "#,
+                r#"WHERE applied."ts" < EXCLUDED."ts""#
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_merge_changes_with_identity_column() {
+        let query = TableQuery::MergeChanges(
+            "schema".to_string(),
+            "table".to_string(),
+            vec!["code".to_string(), "id".to_string(), "name".to_string()],
+            vec!["code".to_string()],
+            vec!["id".to_string()],
+            false,
+        );
+        assert!(
+            query
+                .to_string()
+                .contains(r#"THEN UPDATE SET "name" = source."name" "#)
+        );
+        assert!(
+            query
+                .to_string()
+                .contains(r#"INSERT ("code", "id", "name") OVERRIDING SYSTEM VALUE VALUES"#)
         );
     }
 }
@@ -0,0 +1,131 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::table_query::{qualified_name, quote_identifier};
+
+/// Represents the kind of a user-defined type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypeKind {
+    Enum,
+    Composite,
+    Domain,
+    Range,
+    Base,
+}
+
+impl TypeKind {
+    /// Parses the `typtype` of a type.
+    pub fn from_typtype(typtype: &str) -> Self {
+        match typtype {
+            "e" => TypeKind::Enum,
+            "c" => TypeKind::Composite,
+            "d" => TypeKind::Domain,
+            "r" => TypeKind::Range,
+            _ => TypeKind::Base,
+        }
+    }
+}
+
+/// Represents a type that a table depends on and that is not built into Postgres.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserDefinedType {
+    /// The schema of the type.
+    pub schema_name: String,
+    /// The name of the type.
+    pub name: String,
+    /// The kind of the type.
+    pub kind: TypeKind,
+    /// The extension and the schema of the extension that the type belongs to, if any.
+    pub extension: Option<(String, String)>,
+    /// The body of the `CREATE TYPE` or `CREATE DOMAIN` statement after the name of the type,
+    /// or none if the type cannot be created with a statement.
+    pub definition: Option<String>,
+}
+
+impl UserDefinedType {
+    /// Gets the statements that create the type, or its extension, in the target database.
+    ///
+    /// # Returns
+    ///
+    /// The statements, or None if the type can only be created by its extension, and it has none.
+    pub fn to_sql(&self) -> Option<String> {
+        if let Some((extension, schema)) = &self.extension {
+            return Some(format!(
+                "CREATE SCHEMA IF NOT EXISTS {schema}; CREATE EXTENSION IF NOT EXISTS {} WITH SCHEMA {schema}",
+                quote_identifier(extension),
+                schema = quote_identifier(schema),
+            ));
+        }
+
+        let definition = self.definition.as_ref()?;
+        let name = qualified_name(&self.schema_name, &self.name);
+        match self.kind {
+            TypeKind::Domain => Some(format!("CREATE DOMAIN {name} AS {definition}")),
+            TypeKind::Base => None,
+            _ => Some(format!("CREATE TYPE {name} {definition}")),
+        }
+    }
+}
+
+impl Display for UserDefinedType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.extension {
+            Some((extension, _)) => write!(
+                f,
+                "{}.{} of extension {}",
+                self.schema_name, self.name, extension
+            ),
+            None => write!(f, "{}.{}", self.schema_name, self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_defined_type(kind: TypeKind, definition: Option<&str>) -> UserDefinedType {
+        UserDefinedType {
+            schema_name: "public".to_string(),
+            name: "Mood".to_string(),
+            kind,
+            extension: None,
+            definition: definition.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_to_sql() {
+        assert_eq!(
+            user_defined_type(TypeKind::Enum, Some("AS ENUM ('sad', 'happy')")).to_sql(),
+            Some(r#"CREATE TYPE "public"."Mood" AS ENUM ('sad', 'happy')"#.to_string())
+        );
+        assert_eq!(
+            user_defined_type(TypeKind::Domain, Some("text NOT NULL")).to_sql(),
+            Some(r#"CREATE DOMAIN "public"."Mood" AS text NOT NULL"#.to_string())
+        );
+        assert_eq!(user_defined_type(TypeKind::Base, None).to_sql(), None);
+    }
+
+    #[test]
+    fn test_to_sql_of_extension_type() {
+        let citext = UserDefinedType {
+            name: "citext".to_string(),
+            extension: Some(("citext".to_string(), "extensions".to_string())),
+            ..user_defined_type(TypeKind::Base, None)
+        };
+        assert_eq!(
+            citext.to_sql(),
+            Some(
+                r#"CREATE SCHEMA IF NOT EXISTS "extensions"; CREATE EXTENSION IF NOT EXISTS "citext" WITH SCHEMA "extensions""#
+                    .to_string()
+            )
+        );
+        assert_eq!(citext.to_string(), "public.citext of extension citext");
+    }
+
+    #[test]
+    fn test_from_typtype() {
+        assert_eq!(TypeKind::from_typtype("e"), TypeKind::Enum);
+        assert_eq!(TypeKind::from_typtype("b"), TypeKind::Base);
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, Timelike, Utc};
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+/// Represents the DMS `DatePartitionSequence` setting.
+///
+/// The sequence can be one of the following:
+///
+/// * YYYYMMDD - Partitioned by year, month and day.
+/// * YYYYMMDDHH - Partitioned by year, month, day and hour.
+/// * YYYYMM - Partitioned by year and month.
+/// * MMYYYYDD - Partitioned by month, year and day.
+/// * DDMMYYYY - Partitioned by day, month and year.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum DatePartitionSequence {
+    #[default]
+    #[value(name = "YYYYMMDD", alias = "yyyymmdd")]
+    YYYYMMDD,
+    #[value(name = "YYYYMMDDHH", alias = "yyyymmddhh")]
+    YYYYMMDDHH,
+    #[value(name = "YYYYMM", alias = "yyyymm")]
+    YYYYMM,
+    #[value(name = "MMYYYYDD", alias = "mmyyyydd")]
+    MMYYYYDD,
+    #[value(name = "DDMMYYYY", alias = "ddmmyyyy")]
+    DDMMYYYY,
+}
+
+impl Display for DatePartitionSequence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DatePartitionSequence::YYYYMMDD => write!(f, "YYYYMMDD"),
+            DatePartitionSequence::YYYYMMDDHH => write!(f, "YYYYMMDDHH"),
+            DatePartitionSequence::YYYYMM => write!(f, "YYYYMM"),
+            DatePartitionSequence::MMYYYYDD => write!(f, "MMYYYYDD"),
+            DatePartitionSequence::DDMMYYYY => write!(f, "DDMMYYYY"),
+        }
+    }
+}
+
+/// Represents the DMS `DatePartitionDelimiter` setting.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum DatePartitionDelimiter {
+    #[default]
+    #[value(name = "SLASH", alias = "slash")]
+    SLASH,
+    #[value(name = "DASH", alias = "dash")]
+    DASH,
+    #[value(name = "UNDERSCORE", alias = "underscore")]
+    UNDERSCORE,
+    #[value(name = "NONE", alias = "none")]
+    NONE,
+}
+
+impl DatePartitionDelimiter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DatePartitionDelimiter::SLASH => "/",
+            DatePartitionDelimiter::DASH => "-",
+            DatePartitionDelimiter::UNDERSCORE => "_",
+            DatePartitionDelimiter::NONE => "",
+        }
+    }
+}
+
+impl Display for DatePartitionDelimiter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DatePartitionDelimiter::SLASH => write!(f, "SLASH"),
+            DatePartitionDelimiter::DASH => write!(f, "DASH"),
+            DatePartitionDelimiter::UNDERSCORE => write!(f, "UNDERSCORE"),
+            DatePartitionDelimiter::NONE => write!(f, "NONE"),
+        }
+    }
+}
+
+/// The components of a date partition, in the order they appear in the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DatePartitionComponent {
+    Year,
+    Month,
+    Day,
+    Hour,
+}
+
+impl DatePartitionComponent {
+    fn hive_name(&self) -> &'static str {
+        match self {
+            DatePartitionComponent::Year => "year",
+            DatePartitionComponent::Month => "month",
+            DatePartitionComponent::Day => "day",
+            DatePartitionComponent::Hour => "hour",
+        }
+    }
+
+    fn format(&self, date: &NaiveDateTime) -> String {
+        match self {
+            DatePartitionComponent::Year => format!("{:04}", date.year()),
+            DatePartitionComponent::Month => format!("{:02}", date.month()),
+            DatePartitionComponent::Day => format!("{:02}", date.day()),
+            DatePartitionComponent::Hour => format!("{:02}", date.hour()),
+        }
+    }
+}
+
+/// Represents the layout of the date partitions that DMS writes the CDC files into.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub struct DatePartitionLayout {
+    sequence: DatePartitionSequence,
+    delimiter: DatePartitionDelimiter,
+    /// Whether each component is written as `name=value` (e.g. `year=2024/month=02`).
+    hive_style: bool,
+}
+
+impl DatePartitionLayout {
+    /// Creates the layout of the date partitions.
+    ///
+    /// # Returns
+    ///
+    /// The layout, or an error for Hive-style partitions with a delimiter other than `SLASH`,
+    /// which DMS does not write.
+    pub fn new(
+        sequence: DatePartitionSequence,
+        delimiter: DatePartitionDelimiter,
+        hive_style: bool,
+    ) -> Result<Self> {
+        if hive_style && delimiter != DatePartitionDelimiter::SLASH {
+            return Err(anyhow!(
+                "Hive-style date partitions are always delimited by SLASH, not {delimiter}"
+            ));
+        }
+
+        Ok(Self {
+            sequence,
+            delimiter,
+            hive_style,
+        })
+    }
+
+    fn components(&self) -> &'static [DatePartitionComponent] {
+        use DatePartitionComponent::*;
+
+        match self.sequence {
+            DatePartitionSequence::YYYYMMDD => &[Year, Month, Day],
+            DatePartitionSequence::YYYYMMDDHH => &[Year, Month, Day, Hour],
+            DatePartitionSequence::YYYYMM => &[Year, Month],
+            DatePartitionSequence::MMYYYYDD => &[Month, Year, Day],
+            DatePartitionSequence::DDMMYYYY => &[Day, Month, Year],
+        }
+    }
+
+    /// Truncates a date to the start of the partition that contains it.
+    fn truncate(&self, date: &NaiveDateTime) -> NaiveDateTime {
+        let day = date.date();
+        match self.sequence {
+            DatePartitionSequence::YYYYMMDDHH => day.and_hms_opt(date.hour(), 0, 0).unwrap(),
+            DatePartitionSequence::YYYYMM => NaiveDate::from_ymd_opt(day.year(), day.month(), 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            _ => day.and_hms_opt(0, 0, 0).unwrap(),
+        }
+    }
+
+    /// Advances a truncated date to the start of the next partition.
+    fn next(&self, date: &NaiveDateTime) -> NaiveDateTime {
+        match self.sequence {
+            DatePartitionSequence::YYYYMMDDHH => *date + Duration::hours(1),
+            DatePartitionSequence::YYYYMM => *date + Months::new(1),
+            _ => *date + Duration::days(1),
+        }
+    }
+
+    /// Formats the partition path of a date, without a trailing slash.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date to format
+    ///
+    /// # Returns
+    ///
+    /// The partition path, e.g. `2024/02/14` or `year=2024/month=02/day=14`.
+    pub fn partition_path(&self, date: &DateTime<Utc>) -> String {
+        let date = date.naive_utc();
+        self.components()
+            .iter()
+            .map(|component| {
+                if self.hive_style {
+                    format!("{}={}", component.hive_name(), component.format(&date))
+                } else {
+                    component.format(&date)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(self.delimiter.as_str())
+    }
+
+    /// Gets the bounds of the partitions that cover a date range, from the start of the
+    /// partition of the start date to the end of the partition of the stop date.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_date` - The start date
+    /// * `stop_date` - The stop date
+    ///
+    /// # Returns
+    ///
+    /// The start of the first partition and the end of the last partition, which is exclusive.
+    pub fn partition_bounds(
+        &self,
+        start_date: &DateTime<Utc>,
+        stop_date: &DateTime<Utc>,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = self.truncate(&start_date.naive_utc());
+        let stop = self.next(&self.truncate(&stop_date.naive_utc()));
+        (start.and_utc(), stop.and_utc())
+    }
+
+    /// Gets the partition prefixes that cover a date range, in chronological order.
+    ///
+    /// The prefixes are generated lazily, so that a caller can stop after as many
+    /// partitions as it is willing to list.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_date` - The start date
+    /// * `stop_date` - The stop date
+    ///
+    /// # Returns
+    ///
+    /// The partition paths, each followed by a `/`.
+    pub fn partition_prefixes(
+        &self,
+        start_date: &DateTime<Utc>,
+        stop_date: &DateTime<Utc>,
+    ) -> impl Iterator<Item = String> {
+        let stop_date = stop_date.naive_utc();
+
+        std::iter::successors(Some(self.truncate(&start_date.naive_utc())), |current| {
+            Some(self.next(current))
+        })
+        .take_while(move |current| *current <= stop_date)
+        .map(|current| format!("{}/", self.partition_path(&current.and_utc())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_partition_path_for_every_sequence() {
+        let date = date(2024, 2, 14, 9);
+        let cases = [
+            (DatePartitionSequence::YYYYMMDD, "2024/02/14"),
+            (DatePartitionSequence::YYYYMMDDHH, "2024/02/14/09"),
+            (DatePartitionSequence::YYYYMM, "2024/02"),
+            (DatePartitionSequence::MMYYYYDD, "02/2024/14"),
+            (DatePartitionSequence::DDMMYYYY, "14/02/2024"),
+        ];
+
+        for (sequence, expected) in cases {
+            let layout =
+                DatePartitionLayout::new(sequence, DatePartitionDelimiter::SLASH, false).unwrap();
+            assert_eq!(layout.partition_path(&date), expected);
+        }
+    }
+
+    #[test]
+    fn test_partition_path_for_every_delimiter() {
+        let date = date(2024, 2, 14, 9);
+        let cases = [
+            (DatePartitionDelimiter::SLASH, "2024/02/14"),
+            (DatePartitionDelimiter::DASH, "2024-02-14"),
+            (DatePartitionDelimiter::UNDERSCORE, "2024_02_14"),
+            (DatePartitionDelimiter::NONE, "20240214"),
+        ];
+
+        for (delimiter, expected) in cases {
+            let layout =
+                DatePartitionLayout::new(DatePartitionSequence::YYYYMMDD, delimiter, false)
+                    .unwrap();
+            assert_eq!(layout.partition_path(&date), expected);
+        }
+    }
+
+    #[test]
+    fn test_partition_path_hive_style() {
+        let layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDDHH,
+            DatePartitionDelimiter::SLASH,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            layout.partition_path(&date(2024, 2, 14, 9)),
+            "year=2024/month=02/day=14/hour=09"
+        );
+    }
+
+    #[test]
+    fn test_hive_style_requires_slash_delimiter() {
+        assert!(
+            DatePartitionLayout::new(
+                DatePartitionSequence::YYYYMMDD,
+                DatePartitionDelimiter::DASH,
+                true,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_partition_bounds() {
+        let layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDDHH,
+            DatePartitionDelimiter::SLASH,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            layout.partition_bounds(&date(2024, 2, 14, 9), &date(2024, 2, 14, 23)),
+            (
+                Utc.with_ymd_and_hms(2024, 2, 14, 9, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 2, 15, 0, 0, 0).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_partition_prefixes_by_day() {
+        let layout = DatePartitionLayout::default();
+        let prefixes = layout
+            .partition_prefixes(&date(2024, 2, 28, 22), &date(2024, 3, 1, 1))
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["2024/02/28/", "2024/02/29/", "2024/03/01/"]);
+    }
+
+    #[test]
+    fn test_partition_prefixes_by_hour() {
+        let layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDDHH,
+            DatePartitionDelimiter::NONE,
+            false,
+        )
+        .unwrap();
+        let prefixes = layout
+            .partition_prefixes(&date(2024, 2, 14, 22), &date(2024, 2, 15, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["2024021422/", "2024021423/", "2024021500/"]);
+    }
+
+    #[test]
+    fn test_partition_prefixes_by_month() {
+        let layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMM,
+            DatePartitionDelimiter::DASH,
+            false,
+        )
+        .unwrap();
+        let prefixes = layout
+            .partition_prefixes(&date(2023, 12, 31, 0), &date(2024, 2, 1, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["2023-12/", "2024-01/", "2024-02/"]);
+    }
+
+    #[test]
+    fn test_partition_prefixes_stop_when_taken() {
+        let layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDDHH,
+            DatePartitionDelimiter::SLASH,
+            false,
+        )
+        .unwrap();
+        let prefixes = layout
+            .partition_prefixes(&date(1970, 1, 1, 0), &date(9999, 12, 31, 23))
+            .take(2)
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["1970/01/01/00/", "1970/01/01/01/"]);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod date_partition;
+pub mod s3_layout;
 pub mod s3_operator;
 
 #[cfg(test)]
@@ -0,0 +1,238 @@
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::path::Path;
+
+use super::date_partition::{DatePartitionDelimiter, DatePartitionLayout, DatePartitionSequence};
+use crate::dataframe::file_format::{CsvSettings, FileFormatValueEnum};
+
+/// Represents how the DMS output of a schema is laid out in the object store:
+/// the date partitions of its CDC files and the format of its files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct S3Layout {
+    pub date_partition_layout: DatePartitionLayout,
+    pub file_format: FileFormatValueEnum,
+    pub csv_settings: CsvSettings,
+}
+
+/// Represents the DMS S3 endpoint settings that a layout is resolved from,
+/// as they are given on the command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3LayoutSettings {
+    pub date_partition_sequence: DatePartitionSequence,
+    pub date_partition_delimiter: DatePartitionDelimiter,
+    pub hive_style_partitions: bool,
+    pub file_format: FileFormatValueEnum,
+    pub csv_delimiter: String,
+    pub csv_row_delimiter: String,
+    pub csv_null_value: String,
+    pub csv_header: bool,
+    pub csv_gzip: bool,
+}
+
+impl S3LayoutSettings {
+    /// Overrides some of the settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `overrides` - The settings to override, as `name=value` with the name of the
+    ///   command line option, e.g. `file-format=csv` or `csv-gzip=true`
+    ///
+    /// # Returns
+    ///
+    /// The settings with the overrides, or an error for an unknown setting or an invalid value.
+    pub fn with_overrides(mut self, overrides: &[impl AsRef<str>]) -> Result<Self> {
+        for setting in overrides {
+            let setting = setting.as_ref();
+            let (name, value) = setting.split_once('=').ok_or_else(|| {
+                anyhow!("Invalid S3 layout setting {setting}: expected name=value")
+            })?;
+            let invalid = |e: String| anyhow!("Invalid S3 layout setting {setting}: {e}");
+            let parse_bool =
+                |value: &str| value.parse::<bool>().map_err(|e| invalid(e.to_string()));
+
+            match name {
+                "date-partition-sequence" => {
+                    self.date_partition_sequence =
+                        DatePartitionSequence::from_str(value, true).map_err(invalid)?
+                }
+                "date-partition-delimiter" => {
+                    self.date_partition_delimiter =
+                        DatePartitionDelimiter::from_str(value, true).map_err(invalid)?
+                }
+                "hive-style-partitions" => self.hive_style_partitions = parse_bool(value)?,
+                "file-format" => {
+                    self.file_format =
+                        FileFormatValueEnum::from_str(value, true).map_err(invalid)?
+                }
+                "csv-delimiter" => self.csv_delimiter = value.to_string(),
+                "csv-row-delimiter" => self.csv_row_delimiter = value.to_string(),
+                "csv-null-value" => self.csv_null_value = value.to_string(),
+                "csv-header" => self.csv_header = parse_bool(value)?,
+                "csv-gzip" => self.csv_gzip = parse_bool(value)?,
+                _ => return Err(anyhow!("Unknown S3 layout setting {name}")),
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Resolves the layout of the settings.
+    ///
+    /// # Returns
+    ///
+    /// The layout, or an error for an invalid date partition layout or CSV delimiter.
+    pub fn resolve(&self) -> Result<S3Layout> {
+        let date_partition_layout = DatePartitionLayout::new(
+            self.date_partition_sequence,
+            self.date_partition_delimiter,
+            self.hive_style_partitions,
+        )
+        .map_err(|e| anyhow!("Invalid date partition layout: {e}"))?;
+
+        let csv_settings = CsvSettings {
+            delimiter: CsvSettings::parse_delimiter(&self.csv_delimiter)
+                .ok_or_else(|| anyhow!("Invalid CSV delimiter: {}", self.csv_delimiter))?,
+            row_delimiter: CsvSettings::parse_delimiter(&self.csv_row_delimiter)
+                .ok_or_else(|| anyhow!("Invalid CSV row delimiter: {}", self.csv_row_delimiter))?,
+            null_value: self.csv_null_value.clone(),
+            has_header: self.csv_header,
+            gzip: self.csv_gzip,
+        };
+
+        Ok(S3Layout {
+            date_partition_layout,
+            file_format: self.file_format,
+            csv_settings,
+        })
+    }
+}
+
+/// Reads the S3 layout settings of schemas from a file, one schema per line.
+///
+/// Each line is the schema, as `schema` or `database.schema`, followed by the settings
+/// it overrides, separated by whitespace, e.g. `sales.billing file-format=csv csv-gzip=true`.
+/// Blank lines and lines starting with `#` are skipped.
+///
+/// # Returns
+///
+/// The schemas with their settings, in the order they are listed.
+pub fn read_s3_layouts(path: impl AsRef<Path>) -> Result<Vec<(String, Vec<String>)>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read S3 layouts {}: {e}", path.display()))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(String::from);
+            fields.next().map(|schema| (schema, fields.collect()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> S3LayoutSettings {
+        S3LayoutSettings {
+            date_partition_sequence: DatePartitionSequence::YYYYMMDD,
+            date_partition_delimiter: DatePartitionDelimiter::SLASH,
+            hive_style_partitions: false,
+            file_format: FileFormatValueEnum::Parquet,
+            csv_delimiter: ",".to_string(),
+            csv_row_delimiter: "\\n".to_string(),
+            csv_null_value: String::new(),
+            csv_header: false,
+            csv_gzip: false,
+        }
+    }
+
+    #[test]
+    fn test_resolve_defaults() {
+        assert_eq!(settings().resolve().unwrap(), S3Layout::default());
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let layout = settings()
+            .with_overrides(&[
+                "date-partition-sequence=yyyymmddhh",
+                "date-partition-delimiter=DASH",
+                "file-format=csv",
+                "csv-delimiter=\\t",
+                "csv-null-value=NULL",
+                "csv-gzip=true",
+            ])
+            .unwrap()
+            .resolve()
+            .unwrap();
+
+        assert_eq!(
+            layout,
+            S3Layout {
+                date_partition_layout: DatePartitionLayout::new(
+                    DatePartitionSequence::YYYYMMDDHH,
+                    DatePartitionDelimiter::DASH,
+                    false,
+                )
+                .unwrap(),
+                file_format: FileFormatValueEnum::Csv,
+                csv_settings: CsvSettings {
+                    delimiter: b'\t',
+                    null_value: "NULL".to_string(),
+                    gzip: true,
+                    ..CsvSettings::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        assert!(settings().with_overrides(&["file-format"]).is_err());
+        assert!(settings().with_overrides(&["file-format=orc"]).is_err());
+        assert!(settings().with_overrides(&["csv-header=yes"]).is_err());
+        assert!(settings().with_overrides(&["bucket-name=other"]).is_err());
+        assert!(
+            settings()
+                .with_overrides(&[
+                    "date-partition-delimiter=DASH",
+                    "hive-style-partitions=true"
+                ])
+                .unwrap()
+                .resolve()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_read_s3_layouts() {
+        let path = std::env::temp_dir().join(format!(
+            "dms-cdc-operator-s3-layouts-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "# Billing is written as CSV\nsales.billing  file-format=csv csv-gzip=true\n\naudit\n",
+        )
+        .unwrap();
+
+        let s3_layouts = read_s3_layouts(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            s3_layouts,
+            vec![
+                (
+                    "sales.billing".to_string(),
+                    vec!["file-format=csv".to_string(), "csv-gzip=true".to_string()]
+                ),
+                ("audit".to_string(), Vec::new()),
+            ]
+        );
+
+        assert!(read_s3_layouts("/nonexistent/s3_layouts.txt").is_err());
+    }
+}
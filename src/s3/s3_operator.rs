@@ -1,9 +1,21 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use aws_sdk_s3::Client as S3Client;
-use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
-use chrono::{Datelike, NaiveDate};
-use log::{debug, info};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{StreamExt, TryStreamExt, stream};
+use log::{debug, info, warn};
+
+use super::date_partition::DatePartitionLayout;
+use crate::storage::object_store::ObjectStore;
+
+/// The maximum number of partition or segment prefixes that are listed concurrently.
+const MAX_CONCURRENT_PARTITION_LISTINGS: usize = 16;
+
+/// The maximum number of date partitions listed for a table when no stop date is set,
+/// so that a start date far in the past does not list every partition up to now.
+const MAX_PARTITIONS_WITHOUT_STOP_DATE: usize = 10_000;
+
+/// The format of the timestamp in the DMS CDC file names, e.g. `20240214-101010123.parquet`.
+const DMS_FILE_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%3f";
 
 #[cfg(test)]
 use mockall::automock;
@@ -17,6 +29,7 @@ pub enum LoadParquetFilesPayload {
         table_name: String,
         start_date: String,
         stop_date: Option<String>,
+        date_partition_layout: DatePartitionLayout,
     },
     FullLoadOnly {
         bucket_name: String,
@@ -31,15 +44,38 @@ pub enum LoadParquetFilesPayload {
 #[derive(Debug)]
 pub struct S3ParquetFile {
     pub file_name: String,
+    pub last_modified: Option<DateTime<Utc>>,
 }
 
 impl S3ParquetFile {
     pub fn new(file_name: impl Into<String>) -> Self {
         Self {
             file_name: file_name.into(),
+            last_modified: None,
         }
     }
 
+    pub fn with_last_modified(mut self, last_modified: Option<DateTime<Utc>>) -> Self {
+        self.last_modified = last_modified;
+        self
+    }
+
+    /// Whether the file was written outside the bounds of the partitions that were listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `partition_start` - The start of the first partition
+    /// * `partition_end` - The end of the last partition, which is exclusive
+    pub fn is_late_arrival(
+        &self,
+        partition_start: &DateTime<Utc>,
+        partition_end: &DateTime<Utc>,
+    ) -> bool {
+        self.last_modified.is_some_and(|last_modified| {
+            last_modified < *partition_start || last_modified >= *partition_end
+        })
+    }
+
     pub fn is_load_file(&self) -> bool {
         self.file_name.contains("LOAD")
     }
@@ -47,6 +83,127 @@ impl S3ParquetFile {
     pub fn is_first_load_file(&self) -> bool {
         self.is_load_file() && self.file_name == "LOAD00000001"
     }
+
+    /// Parses the sequence number of a LOAD file, e.g. 1 for `LOAD00000001.parquet`.
+    pub fn load_file_number(&self) -> Option<u32> {
+        let base_name = self.file_name.rsplit('/').next()?;
+        let stem = base_name.split('.').next()?;
+        stem.strip_prefix("LOAD")?.parse::<u32>().ok()
+    }
+
+    /// Gets the folder of the file, without a trailing slash.
+    pub fn folder(&self) -> &str {
+        self.file_name
+            .rsplit_once('/')
+            .map_or("", |(folder, _)| folder)
+    }
+
+    /// Parses the timestamp that DMS writes in the name of a CDC file.
+    ///
+    /// # Returns
+    ///
+    /// The timestamp, or None if the file name does not follow the `YYYYMMDD-HHMMSSmmm` format.
+    pub fn dms_timestamp(&self) -> Option<NaiveDateTime> {
+        let base_name = self.file_name.rsplit('/').next()?;
+        let stem = base_name.split('.').next()?;
+        NaiveDateTime::parse_from_str(stem, DMS_FILE_TIMESTAMP_FORMAT).ok()
+    }
+}
+
+/// Checks that the LOAD files of each folder are numbered LOAD00000001..N without gaps.
+///
+/// # Arguments
+///
+/// * `files` - The LOAD files, sorted by folder and number
+///
+/// # Returns
+///
+/// An error listing the missing LOAD files of every folder.
+pub fn check_load_file_numbering(files: &[S3ParquetFile]) -> Result<()> {
+    let mut missing_files: Vec<String> = Vec::new();
+    let mut expected_number = 1;
+    let mut current_folder: Option<&str> = None;
+
+    for file in files {
+        let Some(number) = file.load_file_number() else {
+            continue;
+        };
+
+        if current_folder != Some(file.folder()) {
+            current_folder = Some(file.folder());
+            expected_number = 1;
+        }
+
+        while expected_number < number {
+            missing_files.push(format!("{}/LOAD{:08}", file.folder(), expected_number));
+            expected_number += 1;
+        }
+        expected_number = number + 1;
+    }
+
+    if !missing_files.is_empty() {
+        return Err(anyhow!(
+            "Found gaps in the LOAD file numbering, missing files: {}",
+            missing_files.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether a sub-folder of a table holds the LOAD files of a parallel-load segment.
+///
+/// DMS names the segment folders after the source partition or segment, while the
+/// CDC files of the table are written in date partitions, e.g. `2024/`, `2024-02-14/`
+/// or `year=2024/`, which never hold LOAD files and are not listed as segments.
+///
+/// # Arguments
+///
+/// * `prefix` - The prefix of the sub-folder, followed by a `/`
+pub fn is_segment_folder(prefix: &str) -> bool {
+    let name = prefix
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let is_date_partition = name.contains('=')
+        || (name.chars().any(|c| c.is_ascii_digit())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '-' || c == '_'));
+
+    !name.is_empty() && !is_date_partition
+}
+
+/// Sorts CDC files by the timestamp in their file names.
+///
+/// Files with the same timestamp are sorted by name, and files without
+/// a parsable timestamp are placed last so that they are applied after the rest.
+pub fn sort_cdc_files_by_dms_timestamp(files: &mut [S3ParquetFile]) {
+    files.sort_by(|a, b| {
+        match (a.dms_timestamp(), b.dms_timestamp()) {
+            (Some(a_timestamp), Some(b_timestamp)) => a_timestamp.cmp(&b_timestamp),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        }
+        .then_with(|| a.file_name.cmp(&b.file_name))
+    });
+}
+
+/// Merges the files of a table that DMS writes in several folders, e.g. of a partitioned table and its partitions.
+///
+/// The LOAD files of every folder are loaded first, in the order of the folders,
+/// then the CDC files of all the folders in the order DMS wrote them.
+pub fn merge_table_files(folders: Vec<Vec<S3ParquetFile>>) -> Vec<S3ParquetFile> {
+    let (mut files, mut cdc_files): (Vec<_>, Vec<_>) = folders
+        .into_iter()
+        .flatten()
+        .partition(|file| file.is_load_file());
+    sort_cdc_files_by_dms_timestamp(&mut cdc_files);
+
+    files.extend(cdc_files);
+    files
 }
 
 #[cfg_attr(test, automock)]
@@ -73,32 +230,37 @@ pub trait S3Operator {
         load_parquet_files_payload: &LoadParquetFilesPayload,
     ) -> Result<Vec<S3ParquetFile>>;
 
-    /// Gets the list of files from S3 based on the date.
+    /// Gets the list of CDC files from S3 that are stored in the date partitions of a date range.
     ///
     /// # Arguments
     ///
     /// * `bucket_name` - The name of the S3 bucket
     /// * `table_name` - The name of the table
-    /// * `start_date_path` - The start date path
-    /// * `prefix_path` - The prefix path
+    /// * `prefix_path` - The prefix path of the table
+    /// * `date_partition_layout` - The layout of the date partitions
     /// * `start_date` - The start date to include the files
-    /// * `stop_date` - The stop date to include the files
+    /// * `stop_date` - The stop date to include the files, defaults to now
     ///
     /// # Returns
     ///
-    /// A list of files.
+    /// A list of files, in partition order, without the files of the first partition
+    /// whose DMS timestamp is before the start date.
     async fn get_files_from_s3_based_on_date(
         &self,
         bucket_name: &str,
         table_name: &str,
-        start_date_path: &str,
         prefix_path: &str,
-        start_date: &DateTime,
-        stop_date: Option<DateTime>,
+        date_partition_layout: &DatePartitionLayout,
+        start_date: &DateTime<Utc>,
+        stop_date: Option<DateTime<Utc>>,
     ) -> Result<Vec<S3ParquetFile>>;
 
     /// Gets only the full load files from S3.
     ///
+    /// The LOAD files of parallel-load tables may be stored in segment sub-folders,
+    /// so each sub-folder of the table that is not a date partition is searched too. The LOAD files of each folder
+    /// are expected to be numbered LOAD00000001..N without gaps.
+    ///
     /// # Arguments
     ///
     /// * `bucket_name` - The name of the S3 bucket
@@ -117,12 +279,12 @@ pub trait S3Operator {
 }
 
 pub struct S3OperatorImpl<'a> {
-    s3_client: &'a S3Client,
+    object_store: &'a dyn ObjectStore,
 }
 
 impl<'a> S3OperatorImpl<'a> {
-    pub fn new(s3_client: &'a S3Client) -> Self {
-        Self { s3_client }
+    pub fn new(object_store: &'a dyn ObjectStore) -> Self {
+        Self { object_store }
     }
 }
 
@@ -141,46 +303,46 @@ impl S3Operator for S3OperatorImpl<'_> {
                 table_name,
                 start_date,
                 stop_date,
+                date_partition_layout,
             } => {
-                let iter_start_date =
-                    NaiveDate::parse_from_str(start_date.as_str(), "%Y-%m-%dT%H:%M:%SZ")?;
-                let year = iter_start_date.year();
-                let month = format!("{:02}", iter_start_date.month());
-                let day = format!("{:02}", iter_start_date.day());
                 let prefix_path = format!(
                     "{}/{}/{}/{}",
                     s3_prefix, database_name, schema_name, table_name
                 );
-                let start_date_path = format!("{}/{}/{}/{}/", prefix_path, year, month, day);
-
-                let start_date =
-                    DateTime::from_str(start_date.as_str(), DateTimeFormat::DateTimeWithOffset)?;
-                let stop_date = if stop_date.is_none() {
-                    None
-                } else {
-                    Some(DateTime::from_str(
-                        stop_date.as_ref().unwrap().as_str(),
-                        DateTimeFormat::DateTimeWithOffset,
-                    )?)
-                };
-
-                let mut files_list: Vec<S3ParquetFile> = self
+
+                let start_date = DateTime::parse_from_rfc3339(start_date)?.with_timezone(&Utc);
+                let stop_date = stop_date
+                    .as_ref()
+                    .map(|stop_date| DateTime::parse_from_rfc3339(stop_date))
+                    .transpose()?
+                    .map(|stop_date| stop_date.with_timezone(&Utc));
+
+                // We want to process the LOAD files first in INSERT mode,
+                // then, we will process the CDC files in UPSERT mode.
+                let mut files_list = self
+                    .get_full_load_files_from_s3(bucket_name, table_name, prefix_path.as_str())
+                    .await?;
+
+                let cdc_files = self
                     .get_files_from_s3_based_on_date(
-                        bucket_name.as_str(),
-                        table_name.as_str(),
-                        start_date_path.as_str(),
-                        format!("{}/", prefix_path).as_str(),
+                        bucket_name,
+                        table_name,
+                        prefix_path.as_str(),
+                        date_partition_layout,
                         &start_date,
                         stop_date,
                     )
                     .await?;
 
-                // We want to process the LOAD files first in INSERT mode, so we rotate the list,
-                // Then, we will process the rest CDC files in UPSERT mode.
-                files_list.retain(|s| s.is_load_file());
-                let load_files_count = files_list.len();
+                // The CDC files are applied strictly in the order DMS wrote them,
+                // regardless of the order of their partition paths
+                let mut cdc_files = cdc_files
+                    .into_iter()
+                    .filter(|f| !f.is_load_file())
+                    .collect::<Vec<_>>();
+                sort_cdc_files_by_dms_timestamp(&mut cdc_files);
 
-                files_list.rotate_right(load_files_count);
+                files_list.extend(cdc_files);
                 files_list
             }
             LoadParquetFilesPayload::FullLoadOnly {
@@ -211,68 +373,92 @@ impl S3Operator for S3OperatorImpl<'_> {
         &self,
         bucket_name: &str,
         table_name: &str,
-        start_date_path: &str,
         prefix_path: &str,
-        start_date: &DateTime,
-        stop_date: Option<DateTime>,
+        date_partition_layout: &DatePartitionLayout,
+        start_date: &DateTime<Utc>,
+        stop_date: Option<DateTime<Utc>>,
     ) -> Result<Vec<S3ParquetFile>> {
-        let mut files: Vec<String> = Vec::new();
-        let mut next_token = None;
-
-        loop {
-            let builder = self
-                .s3_client
-                .list_objects_v2()
-                .bucket(bucket_name)
-                .start_after(start_date_path)
-                .prefix(prefix_path);
-
-            let response = if next_token.is_some() {
-                builder
-                    .continuation_token(next_token.clone().unwrap())
-                    .send()
-                    .await
-                    .map_err(aws_sdk_s3::Error::from)?
-            } else {
-                builder
-                    .to_owned()
-                    .send()
+        let has_stop_date = stop_date.is_some();
+        let stop_date = stop_date.unwrap_or_else(Utc::now);
+        // Without a stop date, the partitions are generated only up to one past the limit
+        let max_partitions = if has_stop_date {
+            usize::MAX
+        } else {
+            MAX_PARTITIONS_WITHOUT_STOP_DATE + 1
+        };
+        let partition_prefixes = date_partition_layout
+            .partition_prefixes(start_date, &stop_date)
+            .take(max_partitions)
+            .map(|partition_prefix| format!("{}/{}", prefix_path, partition_prefix))
+            .collect::<Vec<_>>();
+        if !has_stop_date && partition_prefixes.len() > MAX_PARTITIONS_WITHOUT_STOP_DATE {
+            return Err(anyhow!(
+                "Listing more than {MAX_PARTITIONS_WITHOUT_STOP_DATE} date partitions for table {table_name} \
+                from {start_date} up to now, the most listed without a stop date: set a stop date"
+            ));
+        }
+        debug!(
+            "Listing {} partition prefixes for table {table_name}",
+            partition_prefixes.len()
+        );
+        let first_partition_prefix = partition_prefixes.first().cloned();
+
+        // The partitions are listed concurrently, but kept in chronological order
+        let partitions = stream::iter(partition_prefixes)
+            .map(|partition_prefix| async move {
+                self.object_store
+                    .list_objects(bucket_name, partition_prefix.as_str(), None)
                     .await
-                    .map_err(aws_sdk_s3::Error::from)?
-            };
-
-            next_token.clone_from(&response.next_continuation_token);
-
-            if let Some(contents) = response.contents {
-                for object in contents.clone() {
-                    let file = object.key.unwrap();
-                    // Filter files based on last modified date
-                    if let Some(last_modified) = object.last_modified {
-                        if let Some(stop_date) = stop_date {
-                            if (last_modified > *start_date && last_modified < stop_date)
-                                || file.contains("LOAD")
-                            {
-                                debug!("File: {:?}", file);
-                                files.push(file);
-                            }
-                        } else if last_modified > *start_date || file.contains("LOAD") {
-                            debug!("File: {:?}", file);
-                            files.push(file);
-                        }
-                    }
-                }
-            }
-            if next_token.is_none() {
-                info!("Files to process for table {table_name}: {:?}", files.len());
-                break;
-            }
+            })
+            .buffered(MAX_CONCURRENT_PARTITION_LISTINGS)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let files = partitions
+            .into_iter()
+            .flatten()
+            .map(|object| {
+                debug!("File: {:?}", object.key);
+                S3ParquetFile::new(object.key).with_last_modified(object.last_modified)
+            })
+            .collect::<Vec<_>>();
+
+        // The first partition starts before the start date, so the files DMS wrote before it are skipped
+        let (files, skipped_files): (Vec<_>, Vec<_>) = files.into_iter().partition(|f| {
+            !first_partition_prefix
+                .as_ref()
+                .is_some_and(|prefix| f.file_name.starts_with(prefix.as_str()))
+                || f.dms_timestamp()
+                    .is_none_or(|timestamp| timestamp >= start_date.naive_utc())
+        });
+        if !skipped_files.is_empty() {
+            debug!(
+                "Skipping {} files for table {table_name} written before {start_date}",
+                skipped_files.len()
+            );
         }
 
-        let files = files
+        // The partitions hold the files written from the start of the first one to the end of the last one
+        let (partition_start, partition_end) =
+            date_partition_layout.partition_bounds(start_date, &stop_date);
+        let late_arrivals = files
             .iter()
-            .map(|f| S3ParquetFile::new(f.to_string()))
+            .filter(|f| f.is_late_arrival(&partition_start, &partition_end))
             .collect::<Vec<_>>();
+        if !late_arrivals.is_empty() {
+            warn!(
+                "Found {} late-arriving files for table {table_name}, with partition inside but LastModified outside of {partition_start} - {partition_end}",
+                late_arrivals.len()
+            );
+            for file in late_arrivals {
+                warn!(
+                    "Late-arriving file: {}, LastModified: {:?}",
+                    file.file_name, file.last_modified
+                );
+            }
+        }
 
+        info!("Files to process for table {table_name}: {:?}", files.len());
         Ok(files)
     }
 
@@ -282,35 +468,47 @@ impl S3Operator for S3OperatorImpl<'_> {
         table_name: &str,
         prefix_path: &str,
     ) -> Result<Vec<S3ParquetFile>> {
-        let mut files: Vec<String> = Vec::new();
-
-        // The maximum no of keys returned is 1000,
-        // so we don't need to paginate with next_token
-        // since the full load files are limited
-
-        let builder = self
-            .s3_client
-            .list_objects_v2()
-            .bucket(bucket_name)
-            .prefix(format!("{}/LOAD", prefix_path));
-
-        let response = builder
-            .to_owned()
-            .send()
-            .await
-            .map_err(aws_sdk_s3::Error::from)?;
-
-        if let Some(contents) = response.contents {
-            for object in contents.clone() {
-                let file = object.key.unwrap();
-                debug!("File: {:?}", file);
-                files.push(file);
-            }
-        }
-        let files = files
-            .iter()
-            .map(|f| S3ParquetFile::new(f.to_string()))
+        // The table folder and every segment sub-folder are listed concurrently,
+        // and each listing is paginated by the object store
+        let segment_prefixes = self
+            .object_store
+            .list_prefixes(bucket_name, format!("{}/", prefix_path).as_str())
+            .await?;
+        let load_prefixes = std::iter::once(format!("{}/LOAD", prefix_path))
+            .chain(
+                segment_prefixes
+                    .into_iter()
+                    .filter(|segment_prefix| is_segment_folder(segment_prefix))
+                    .map(|segment_prefix| format!("{}LOAD", segment_prefix)),
+            )
+            .collect::<Vec<_>>();
+
+        let folders = stream::iter(load_prefixes)
+            .map(|load_prefix| async move {
+                self.object_store
+                    .list_objects(bucket_name, load_prefix.as_str(), None)
+                    .await
+            })
+            .buffered(MAX_CONCURRENT_PARTITION_LISTINGS)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut files = folders
+            .into_iter()
+            .flatten()
+            .map(|object| {
+                debug!("File: {:?}", object.key);
+                S3ParquetFile::new(object.key).with_last_modified(object.last_modified)
+            })
+            .filter(|file| file.load_file_number().is_some())
             .collect::<Vec<_>>();
+        files.sort_by(|a, b| {
+            a.folder()
+                .cmp(b.folder())
+                .then_with(|| a.load_file_number().cmp(&b.load_file_number()))
+        });
+
+        check_load_file_numbering(&files)?;
 
         info!("Files to process for table {table_name}: {:?}", files.len());
         Ok(files)
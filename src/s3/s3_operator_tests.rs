@@ -1,10 +1,18 @@
 #[cfg(test)]
 mod tests {
+    use crate::s3::date_partition::{
+        DatePartitionDelimiter, DatePartitionLayout, DatePartitionSequence,
+    };
     use crate::s3::s3_operator::LoadParquetFilesPayload;
     use crate::s3::s3_operator::MockS3Operator;
     use crate::s3::s3_operator::S3Operator;
+    use crate::s3::s3_operator::S3OperatorImpl;
     use crate::s3::s3_operator::S3ParquetFile;
-    use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+    use crate::s3::s3_operator::is_segment_folder;
+    use crate::s3::s3_operator::merge_table_files;
+    use crate::s3::s3_operator::sort_cdc_files_by_dms_timestamp;
+    use crate::storage::in_memory_store::InMemoryObjectStore;
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 
     #[tokio::test]
     async fn test_get_list_of_parquet_files_from_s3() {
@@ -34,6 +42,7 @@ mod tests {
             table_name,
             start_date,
             stop_date,
+            date_partition_layout: DatePartitionLayout::default(),
         };
 
         let files = s3_operator
@@ -54,20 +63,16 @@ mod tests {
 
         let bucket_name = "bucket_name".to_string();
         let table_name = "table_name".to_string();
-        let start_date_path = "start_date_path".to_string();
         let prefix_path = "prefix_path".to_string();
-        let start_date =
-            DateTime::from_str("2021-01-01T00:00:00Z", DateTimeFormat::DateTimeWithOffset).unwrap();
-        let stop_date = Some(
-            DateTime::from_str("2021-01-01T00:00:00Z", DateTimeFormat::DateTimeWithOffset).unwrap(),
-        );
+        let start_date = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let stop_date = Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap());
 
         let files = s3_operator
             .get_files_from_s3_based_on_date(
                 &bucket_name,
                 &table_name,
-                &start_date_path,
                 &prefix_path,
+                &DatePartitionLayout::default(),
                 &start_date,
                 stop_date,
             )
@@ -76,4 +81,358 @@ mod tests {
 
         assert_eq!(files.len(), 1);
     }
+
+    fn put_file(object_store: &InMemoryObjectStore, key: &str, last_modified: DateTime<Utc>) {
+        object_store.put_object("bucket", key, Vec::new(), last_modified);
+    }
+
+    #[tokio::test]
+    async fn test_date_aware_listing_keeps_load_and_cdc_files() {
+        let object_store = InMemoryObjectStore::new();
+        let day = |d| Utc.with_ymd_and_hms(2024, 2, d, 12, 0, 0).unwrap();
+
+        put_file(
+            &object_store,
+            "prefix/db/public/table/LOAD00000001.parquet",
+            day(1),
+        );
+        put_file(
+            &object_store,
+            "prefix/db/public/table/2024-02-13/20240213-120000000.parquet",
+            day(13),
+        );
+        put_file(
+            &object_store,
+            "prefix/db/public/table/2024-02-14/20240214-120000000.parquet",
+            day(14),
+        );
+        put_file(
+            &object_store,
+            "prefix/db/public/table/2024-02-15/20240215-120000000.parquet",
+            day(15),
+        );
+        put_file(
+            &object_store,
+            "prefix/db/public/table/2024-02-16/20240216-120000000.parquet",
+            day(16),
+        );
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let files = s3_operator
+            .get_list_of_parquet_files_from_s3(&LoadParquetFilesPayload::DateAware {
+                bucket_name: "bucket".to_string(),
+                s3_prefix: "prefix".to_string(),
+                database_name: "db".to_string(),
+                schema_name: "public".to_string(),
+                table_name: "table".to_string(),
+                start_date: "2024-02-14T00:00:00Z".to_string(),
+                stop_date: Some("2024-02-15T23:00:00Z".to_string()),
+                date_partition_layout: DatePartitionLayout::new(
+                    DatePartitionSequence::YYYYMMDD,
+                    DatePartitionDelimiter::DASH,
+                    false,
+                )
+                .unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let file_names = files
+            .iter()
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            vec![
+                "prefix/db/public/table/LOAD00000001.parquet",
+                "prefix/db/public/table/2024-02-14/20240214-120000000.parquet",
+                "prefix/db/public/table/2024-02-15/20240215-120000000.parquet",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_aware_listing_reports_late_arrivals() {
+        let object_store = InMemoryObjectStore::new();
+        let start_date = Utc.with_ymd_and_hms(2024, 2, 14, 10, 0, 0).unwrap();
+        let stop_date = Utc.with_ymd_and_hms(2024, 2, 14, 12, 0, 0).unwrap();
+
+        // Files of the partition written before the start date or after the stop date are on time
+        put_file(
+            &object_store,
+            "table/year=2024/month=02/day=14/on_time.parquet",
+            Utc.with_ymd_and_hms(2024, 2, 14, 9, 0, 0).unwrap(),
+        );
+        put_file(
+            &object_store,
+            "table/year=2024/month=02/day=14/on_time_after_stop.parquet",
+            Utc.with_ymd_and_hms(2024, 2, 14, 23, 0, 0).unwrap(),
+        );
+        put_file(
+            &object_store,
+            "table/year=2024/month=02/day=14/late.parquet",
+            Utc.with_ymd_and_hms(2024, 2, 20, 0, 0, 0).unwrap(),
+        );
+
+        let date_partition_layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDD,
+            DatePartitionDelimiter::SLASH,
+            true,
+        )
+        .unwrap();
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let files = s3_operator
+            .get_files_from_s3_based_on_date(
+                "bucket",
+                "table",
+                "table",
+                &date_partition_layout,
+                &start_date,
+                Some(stop_date),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(files.len(), 3);
+        let (partition_start, partition_end) =
+            date_partition_layout.partition_bounds(&start_date, &stop_date);
+        let late_arrivals = files
+            .iter()
+            .filter(|f| f.is_late_arrival(&partition_start, &partition_end))
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            late_arrivals,
+            vec!["table/year=2024/month=02/day=14/late.parquet"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_aware_listing_skips_files_written_before_the_start_date() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 15, 0, 0, 0).unwrap();
+
+        for key in [
+            "table/2024/02/14/20240214-095959999.parquet",
+            "table/2024/02/14/20240214-100000000.parquet",
+            "table/2024/02/14/no_timestamp.parquet",
+            "table/2024/02/15/20240215-010000000.parquet",
+        ] {
+            put_file(&object_store, key, last_modified);
+        }
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let files = s3_operator
+            .get_files_from_s3_based_on_date(
+                "bucket",
+                "table",
+                "table",
+                &DatePartitionLayout::default(),
+                &Utc.with_ymd_and_hms(2024, 2, 14, 10, 0, 0).unwrap(),
+                Some(Utc.with_ymd_and_hms(2024, 2, 15, 12, 0, 0).unwrap()),
+            )
+            .await
+            .unwrap();
+
+        let file_names = files
+            .iter()
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            vec![
+                "table/2024/02/14/20240214-100000000.parquet",
+                "table/2024/02/14/no_timestamp.parquet",
+                "table/2024/02/15/20240215-010000000.parquet",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_aware_listing_without_stop_date_is_capped() {
+        let object_store = InMemoryObjectStore::new();
+        let date_partition_layout = DatePartitionLayout::new(
+            DatePartitionSequence::YYYYMMDDHH,
+            DatePartitionDelimiter::SLASH,
+            false,
+        )
+        .unwrap();
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let result = s3_operator
+            .get_files_from_s3_based_on_date(
+                "bucket",
+                "table",
+                "table",
+                &date_partition_layout,
+                &Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap(),
+                None,
+            )
+            .await;
+
+        assert!(result.unwrap_err().to_string().contains("set a stop date"));
+    }
+
+    #[test]
+    fn test_dms_timestamp() {
+        let file = S3ParquetFile::new("prefix/table/2024/02/14/20240214-101010123.parquet");
+        assert_eq!(
+            file.dms_timestamp(),
+            Some(
+                NaiveDate::from_ymd_opt(2024, 2, 14)
+                    .unwrap()
+                    .and_hms_milli_opt(10, 10, 10, 123)
+                    .unwrap()
+            )
+        );
+
+        let file = S3ParquetFile::new("prefix/table/LOAD00000001.parquet");
+        assert_eq!(file.dms_timestamp(), None);
+    }
+
+    #[test]
+    fn test_sort_cdc_files_by_dms_timestamp() {
+        let mut files = vec![
+            S3ParquetFile::new("table/2024021411/20240214-110000000.parquet"),
+            S3ParquetFile::new("table/unknown.parquet"),
+            S3ParquetFile::new("table/2024/02/14/11/20240214-105959999.parquet"),
+            S3ParquetFile::new("table/2024-02-14-10/20240214-100000001.parquet"),
+        ];
+
+        sort_cdc_files_by_dms_timestamp(&mut files);
+
+        let file_names = files
+            .iter()
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            vec![
+                "table/2024-02-14-10/20240214-100000001.parquet",
+                "table/2024/02/14/11/20240214-105959999.parquet",
+                "table/2024021411/20240214-110000000.parquet",
+                "table/unknown.parquet",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_table_files() {
+        let files = merge_table_files(vec![
+            vec![
+                S3ParquetFile::new("events/LOAD00000001.parquet"),
+                S3ParquetFile::new("events/2024/02/14/20240214-110000000.parquet"),
+            ],
+            vec![
+                S3ParquetFile::new("events_2024/LOAD00000001.parquet"),
+                S3ParquetFile::new("events_2024/2024/02/14/20240214-100000000.parquet"),
+            ],
+        ]);
+
+        let file_names = files
+            .iter()
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            vec![
+                "events/LOAD00000001.parquet",
+                "events_2024/LOAD00000001.parquet",
+                "events_2024/2024/02/14/20240214-100000000.parquet",
+                "events/2024/02/14/20240214-110000000.parquet",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_load_listing_finds_segment_folders() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 14, 0, 0, 0).unwrap();
+
+        for number in (1..=1200).rev() {
+            put_file(
+                &object_store,
+                format!("table/LOAD{:08}.parquet", number).as_str(),
+                last_modified,
+            );
+        }
+        put_file(
+            &object_store,
+            "table/segment2/LOAD00000002.parquet",
+            last_modified,
+        );
+        put_file(
+            &object_store,
+            "table/segment2/LOAD00000001.parquet",
+            last_modified,
+        );
+        put_file(
+            &object_store,
+            "table/segment1/LOAD00000001.parquet",
+            last_modified,
+        );
+        put_file(
+            &object_store,
+            "table/2024/02/14/20240214-100000000.parquet",
+            last_modified,
+        );
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let files = s3_operator
+            .get_full_load_files_from_s3("bucket", "table", "table")
+            .await
+            .unwrap();
+
+        assert_eq!(files.len(), 1203);
+        assert_eq!(files[0].file_name, "table/LOAD00000001.parquet");
+        assert_eq!(files[1199].file_name, "table/LOAD00001200.parquet");
+        let segment_files = files[1200..]
+            .iter()
+            .map(|f| f.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segment_files,
+            vec![
+                "table/segment1/LOAD00000001.parquet",
+                "table/segment2/LOAD00000001.parquet",
+                "table/segment2/LOAD00000002.parquet",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_segment_folder() {
+        assert!(is_segment_folder("table/segment1/"));
+        assert!(is_segment_folder("table/events_2024_01/"));
+        assert!(!is_segment_folder("table/2024/"));
+        assert!(!is_segment_folder("table/2024-02-14/"));
+        assert!(!is_segment_folder("table/2024_02/"));
+        assert!(!is_segment_folder("table/2024021409/"));
+        assert!(!is_segment_folder("table/year=2024/"));
+    }
+
+    #[tokio::test]
+    async fn test_full_load_listing_reports_gaps() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 14, 0, 0, 0).unwrap();
+
+        put_file(&object_store, "table/LOAD00000001.parquet", last_modified);
+        put_file(&object_store, "table/LOAD00000004.parquet", last_modified);
+        put_file(
+            &object_store,
+            "table/segment1/LOAD00000002.parquet",
+            last_modified,
+        );
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let error = s3_operator
+            .get_full_load_files_from_s3("bucket", "table", "table")
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Found gaps in the LOAD file numbering, missing files: table/LOAD00000002, table/LOAD00000003, table/segment1/LOAD00000001"
+        );
+    }
 }
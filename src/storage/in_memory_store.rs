@@ -0,0 +1,91 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use super::object_store::{ObjectMeta, ObjectStore, common_prefixes};
+
+struct InMemoryObject {
+    bytes: Vec<u8>,
+    last_modified: DateTime<Utc>,
+}
+
+/// Represents an object store that keeps every object in memory.
+///
+/// Useful for running the snapshot pipeline in tests, without AWS or a filesystem.
+#[derive(Default)]
+pub struct InMemoryObjectStore {
+    objects: RwLock<BTreeMap<(String, String), InMemoryObject>>,
+}
+
+impl InMemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores an object, replacing any object with the same key.
+    pub fn put_object(
+        &self,
+        bucket_name: impl Into<String>,
+        key: impl Into<String>,
+        bytes: Vec<u8>,
+        last_modified: DateTime<Utc>,
+    ) {
+        self.objects.write().unwrap().insert(
+            (bucket_name.into(), key.into()),
+            InMemoryObject {
+                bytes,
+                last_modified,
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl ObjectStore for InMemoryObjectStore {
+    async fn list_objects(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+        start_after: Option<String>,
+    ) -> Result<Vec<ObjectMeta>> {
+        let objects = self.objects.read().unwrap();
+
+        // The BTreeMap keeps the keys in ascending order, like S3 does
+        let objects = objects
+            .iter()
+            .filter(|((bucket, key), _)| {
+                bucket == bucket_name
+                    && key.starts_with(prefix)
+                    && start_after
+                        .as_ref()
+                        .is_none_or(|start_after| key.as_str() > start_after.as_str())
+            })
+            .map(|((_, key), object)| ObjectMeta::new(key.clone(), Some(object.last_modified)))
+            .collect::<Vec<_>>();
+
+        Ok(objects)
+    }
+
+    async fn list_prefixes(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>> {
+        let objects = self.objects.read().unwrap();
+
+        Ok(common_prefixes(
+            objects
+                .keys()
+                .filter(|(bucket, _)| bucket == bucket_name)
+                .map(|(_, key)| key.as_str()),
+            prefix,
+        ))
+    }
+
+    async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Vec<u8>> {
+        self.objects
+            .read()
+            .unwrap()
+            .get(&(bucket_name.to_string(), key.to_string()))
+            .map(|object| object.bytes.clone())
+            .ok_or_else(|| anyhow!("Object {key} not found in bucket {bucket_name}"))
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+use super::object_store::{ObjectMeta, ObjectStore, common_prefixes};
+
+/// Represents an object store backed by a directory on the local filesystem.
+///
+/// Each bucket is a subdirectory of the root directory, and each key is
+/// the `/` separated path of a file relative to its bucket directory.
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn bucket_path(&self, bucket_name: &str) -> PathBuf {
+        self.root.join(bucket_name)
+    }
+}
+
+/// Gets the directory of a bucket that holds every key with a prefix, which is the directory
+/// of the prefix up to its last `/`, so that listing a prefix does not walk the whole bucket.
+fn prefix_dir(bucket_path: &Path, prefix: &str) -> PathBuf {
+    match prefix.rfind('/') {
+        Some(position) => bucket_path.join(prefix[..position].trim_start_matches('/')),
+        None => bucket_path.to_path_buf(),
+    }
+}
+
+/// Recursively collects the files under a directory, keyed relative to the bucket directory.
+fn collect_files(bucket_path: &Path, dir: &Path, objects: &mut Vec<ObjectMeta>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(bucket_path, &path, objects)?;
+            continue;
+        }
+
+        let key = path
+            .strip_prefix(bucket_path)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let last_modified = std::fs::metadata(&path)?
+            .modified()
+            .ok()
+            .map(DateTime::<Utc>::from);
+        objects.push(ObjectMeta::new(key, last_modified));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl ObjectStore for LocalObjectStore {
+    async fn list_objects(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+        start_after: Option<String>,
+    ) -> Result<Vec<ObjectMeta>> {
+        let bucket_path = self.bucket_path(bucket_name);
+        let prefix = prefix.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let mut objects = Vec::new();
+            // A missing directory has no files, and collects none
+            let dir = prefix_dir(&bucket_path, &prefix);
+            collect_files(&bucket_path, &dir, &mut objects)?;
+
+            objects.retain(|object| {
+                object.key.starts_with(&prefix)
+                    && start_after
+                        .as_ref()
+                        .is_none_or(|start_after| object.key.as_str() > start_after.as_str())
+            });
+            objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+            Ok(objects)
+        })
+        .await?
+    }
+
+    async fn list_prefixes(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>> {
+        let objects = self.list_objects(bucket_name, prefix, None).await?;
+
+        Ok(common_prefixes(
+            objects.iter().map(|object| object.key.as_str()),
+            prefix,
+        ))
+    }
+
+    async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Vec<u8>> {
+        let path = self.bucket_path(bucket_name).join(key);
+        let bytes = tokio::fs::read(&path).await?;
+
+        Ok(bytes)
+    }
+}
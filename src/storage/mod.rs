@@ -0,0 +1,7 @@
+pub mod in_memory_store;
+pub mod local_store;
+pub mod object_store;
+pub mod s3_store;
+
+#[cfg(test)]
+mod object_store_tests;
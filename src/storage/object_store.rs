@@ -0,0 +1,116 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+#[cfg(test)]
+use mockall::automock;
+
+/// Represents the backend that stores the DMS output files.
+///
+/// The backend can be one of the following:
+///
+/// * S3 - Amazon S3 or any S3 compatible store (e.g. MinIO).
+/// * Local - A directory on the local filesystem.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ObjectStoreValueEnum {
+    #[default]
+    S3,
+    Local,
+}
+
+impl Display for ObjectStoreValueEnum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectStoreValueEnum::S3 => write!(f, "S3"),
+            ObjectStoreValueEnum::Local => write!(f, "Local"),
+        }
+    }
+}
+
+/// Represents the metadata of a stored object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl ObjectMeta {
+    pub fn new(key: impl Into<String>, last_modified: Option<DateTime<Utc>>) -> Self {
+        Self {
+            key: key.into(),
+            last_modified,
+        }
+    }
+}
+
+/// Gets the distinct sub-folders of a prefix, like S3 `CommonPrefixes` with a `/` delimiter.
+///
+/// # Arguments
+///
+/// * `keys` - The keys of the objects
+/// * `prefix` - The prefix of the sub-folders
+///
+/// # Returns
+///
+/// The sub-folder prefixes in ascending order, each followed by a `/`.
+pub fn common_prefixes<'a>(keys: impl IntoIterator<Item = &'a str>, prefix: &str) -> Vec<String> {
+    let mut prefixes = keys
+        .into_iter()
+        .filter_map(|key| {
+            let remainder = key.strip_prefix(prefix)?;
+            let position = remainder.find('/')?;
+            Some(format!("{}{}", prefix, &remainder[..=position]))
+        })
+        .collect::<Vec<_>>();
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Lists the objects under a prefix in ascending key order, following pagination until exhausted.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket
+    /// * `prefix` - The prefix of the object keys
+    /// * `start_after` - Only keys after this key are returned
+    ///
+    /// # Returns
+    ///
+    /// The metadata of all the matching objects.
+    async fn list_objects(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+        start_after: Option<String>,
+    ) -> Result<Vec<ObjectMeta>>;
+
+    /// Lists the sub-folders directly under a prefix, following pagination until exhausted.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket
+    /// * `prefix` - The prefix of the sub-folders
+    ///
+    /// # Returns
+    ///
+    /// The sub-folder prefixes in ascending order, each followed by a `/`.
+    async fn list_prefixes(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>>;
+
+    /// Reads the content of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket
+    /// * `key` - The key of the object
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the object.
+    async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Vec<u8>>;
+}
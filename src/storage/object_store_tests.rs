@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use polars::prelude::*;
+
+    use crate::dataframe::dataframe_ops::{
+        CreateDataframePayload, DataframeOperator, DataframeOperatorImpl,
+    };
+    use crate::s3::s3_operator::{LoadParquetFilesPayload, S3Operator, S3OperatorImpl};
+    use crate::storage::in_memory_store::InMemoryObjectStore;
+    use crate::storage::local_store::LocalObjectStore;
+    use crate::storage::object_store::{ObjectStore, common_prefixes};
+
+    fn parquet_bytes(df: &mut DataFrame) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ParquetWriter::new(&mut bytes).finish(df).unwrap();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_lists_objects_in_key_order() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 14, 10, 0, 0).unwrap();
+
+        object_store.put_object("bucket", "prefix/b.parquet", vec![2], last_modified);
+        object_store.put_object("bucket", "prefix/a.parquet", vec![1], last_modified);
+        object_store.put_object("bucket", "other/c.parquet", vec![3], last_modified);
+        object_store.put_object("other_bucket", "prefix/d.parquet", vec![4], last_modified);
+
+        let objects = object_store
+            .list_objects("bucket", "prefix/", None)
+            .await
+            .unwrap();
+        let keys = objects.iter().map(|o| o.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["prefix/a.parquet", "prefix/b.parquet"]);
+        assert_eq!(objects[0].last_modified, Some(last_modified));
+
+        let objects = object_store
+            .list_objects("bucket", "prefix/", Some("prefix/a.parquet".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].key, "prefix/b.parquet");
+
+        let bytes = object_store
+            .get_object("bucket", "prefix/b.parquet")
+            .await
+            .unwrap();
+        assert_eq!(bytes, vec![2]);
+
+        assert!(
+            object_store
+                .get_object("bucket", "missing.parquet")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_store_lists_nested_files() {
+        let root = std::env::temp_dir().join(format!("dms-cdc-operator-{}", std::process::id()));
+        let table_path = root.join("bucket/prefix/db/public/table/2024/02/14");
+        std::fs::create_dir_all(&table_path).unwrap();
+        std::fs::write(
+            root.join("bucket/prefix/db/public/table/LOAD00000001.parquet"),
+            [1],
+        )
+        .unwrap();
+        std::fs::write(table_path.join("20240214-101010123.parquet"), [2]).unwrap();
+
+        let object_store = LocalObjectStore::new(&root);
+        let objects = object_store
+            .list_objects("bucket", "prefix/db/public/table/", None)
+            .await
+            .unwrap();
+        let keys = objects.iter().map(|o| o.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "prefix/db/public/table/2024/02/14/20240214-101010123.parquet",
+                "prefix/db/public/table/LOAD00000001.parquet",
+            ]
+        );
+        assert!(objects.iter().all(|o| o.last_modified.is_some()));
+
+        let bytes = object_store
+            .get_object("bucket", "prefix/db/public/table/LOAD00000001.parquet")
+            .await
+            .unwrap();
+        assert_eq!(bytes, vec![1]);
+
+        let objects = object_store
+            .list_objects("bucket", "prefix/db/public/table/LOAD", None)
+            .await
+            .unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(
+            objects[0].key,
+            "prefix/db/public/table/LOAD00000001.parquet"
+        );
+
+        let objects = object_store
+            .list_objects("bucket", "prefix/db/public/missing_table/", None)
+            .await
+            .unwrap();
+        assert!(objects.is_empty());
+
+        let objects = object_store
+            .list_objects("missing_bucket", "", None)
+            .await
+            .unwrap();
+        assert!(objects.is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_operators_on_in_memory_store() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 14, 10, 0, 0).unwrap();
+        let mut df = df!(
+            "Op" => ["I", "I"],
+            "id" => [1, 2],
+            "name" => ["a", "b"],
+        )
+        .unwrap();
+
+        object_store.put_object(
+            "bucket",
+            "prefix/db/public/table/LOAD00000001.parquet",
+            parquet_bytes(&mut df),
+            last_modified,
+        );
+
+        let s3_operator = S3OperatorImpl::new(&object_store);
+        let files = s3_operator
+            .get_list_of_parquet_files_from_s3(&LoadParquetFilesPayload::FullLoadOnly {
+                bucket_name: "bucket".to_string(),
+                s3_prefix: "prefix".to_string(),
+                database_name: "db".to_string(),
+                schema_name: "public".to_string(),
+                table_name: "table".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_load_file());
+
+        let dataframe_operator = DataframeOperatorImpl::new(&object_store);
+        let read_df = dataframe_operator
+            .create_dataframe_from_parquet_file(&CreateDataframePayload {
+                bucket_name: "bucket".to_string(),
+                key: files[0].file_name.clone(),
+                database_name: "db".to_string(),
+                schema_name: "public".to_string(),
+                table_name: "table".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(read_df.equals(&df));
+    }
+
+    #[test]
+    fn test_common_prefixes() {
+        let keys = [
+            "table/LOAD00000001.parquet",
+            "table/segment1/LOAD00000001.parquet",
+            "table/segment1/LOAD00000002.parquet",
+            "table/2024/02/14/20240214-100000000.parquet",
+            "other/LOAD00000001.parquet",
+        ];
+
+        assert_eq!(
+            common_prefixes(keys, "table/"),
+            vec!["table/2024/", "table/segment1/"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_lists_prefixes() {
+        let object_store = InMemoryObjectStore::new();
+        let last_modified = Utc.with_ymd_and_hms(2024, 2, 14, 10, 0, 0).unwrap();
+
+        object_store.put_object(
+            "bucket",
+            "table/LOAD00000001.parquet",
+            vec![],
+            last_modified,
+        );
+        object_store.put_object(
+            "bucket",
+            "table/segment1/LOAD00000001.parquet",
+            vec![],
+            last_modified,
+        );
+        object_store.put_object(
+            "other_bucket",
+            "table/segment2/LOAD00000001.parquet",
+            vec![],
+            last_modified,
+        );
+
+        let prefixes = object_store
+            .list_prefixes("bucket", "table/")
+            .await
+            .unwrap();
+        assert_eq!(prefixes, vec!["table/segment1/"]);
+    }
+}
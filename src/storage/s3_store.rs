@@ -0,0 +1,110 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_sdk_s3::Client as S3Client;
+use chrono::DateTime;
+
+use super::object_store::{ObjectMeta, ObjectStore};
+
+/// Represents an object store backed by Amazon S3 or an S3 compatible service.
+pub struct S3ObjectStore {
+    s3_client: S3Client,
+}
+
+impl S3ObjectStore {
+    pub fn new(s3_client: S3Client) -> Self {
+        Self { s3_client }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3ObjectStore {
+    async fn list_objects(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+        start_after: Option<String>,
+    ) -> Result<Vec<ObjectMeta>> {
+        let mut objects: Vec<ObjectMeta> = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let response = self
+                .s3_client
+                .list_objects_v2()
+                .bucket(bucket_name)
+                .prefix(prefix)
+                .set_start_after(start_after.clone())
+                .set_continuation_token(next_token.clone())
+                .send()
+                .await
+                .map_err(aws_sdk_s3::Error::from)?;
+
+            next_token.clone_from(&response.next_continuation_token);
+
+            for object in response.contents.unwrap_or_default() {
+                let Some(key) = object.key else {
+                    continue;
+                };
+                let last_modified = object
+                    .last_modified
+                    .and_then(|date| DateTime::from_timestamp(date.secs(), date.subsec_nanos()));
+                objects.push(ObjectMeta::new(key, last_modified));
+            }
+
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn list_prefixes(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>> {
+        let mut prefixes: Vec<String> = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let response = self
+                .s3_client
+                .list_objects_v2()
+                .bucket(bucket_name)
+                .prefix(prefix)
+                .delimiter("/")
+                .set_continuation_token(next_token.clone())
+                .send()
+                .await
+                .map_err(aws_sdk_s3::Error::from)?;
+
+            next_token.clone_from(&response.next_continuation_token);
+
+            prefixes.extend(
+                response
+                    .common_prefixes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|common_prefix| common_prefix.prefix),
+            );
+
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(prefixes)
+    }
+
+    async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Vec<u8>> {
+        let object = self
+            .s3_client
+            .get_object()
+            .bucket(bucket_name)
+            .key(key)
+            .send()
+            .await
+            .map_err(aws_sdk_s3::Error::from)?;
+
+        let bytes = object.body.collect().await?.into_bytes();
+
+        Ok(bytes.to_vec())
+    }
+}